@@ -1,7 +1,27 @@
 use crate::*;
-use utils::{add_if_not_found, push, remove_first, remove};
+use utils::{add_if_not_found, push, remove};
 use rand::prelude::*;
 
+/// Fails when no page can make room: there are no frames, or every page in
+/// them is pinned
+fn evictable(page_frames: &[MemoryPage]) -> Result<(), Error> {
+    if page_frames.is_empty() {
+        return Err(Error::NothingToEvict);
+    }
+    if page_frames.iter().all(|x| x.pinned) {
+        return Err(Error::OutOfFrames { frames: page_frames.len() as u32 });
+    }
+    Ok(())
+}
+
+/// Remove the oldest page that is not pinned
+fn remove_first_unpinned(mut page_frames: Vec<MemoryPage>) -> Vec<MemoryPage> {
+    if let Some(index) = page_frames.iter().position(|x| !x.pinned) {
+        page_frames.remove(index);
+    }
+    page_frames
+}
+
 /// First In First Out Algorithm
 /// page_frames is the currently loaded pages in memory
 /// page is the page we want to load into memory
@@ -13,12 +33,14 @@ use rand::prelude::*;
 /// For Example: [0, 1, 2, 0]
 ///     By FIFO '0' is still the very first to have entered the queue
 /// Keep sort order of [Oldest -> Newest]
+/// Pinned pages are passed over, the oldest unpinned page is replaced
 pub fn fifo(
     page_frames: Vec<MemoryPage>,
     page: MemoryPage,
     _past_pages: Vec<u32>,
-) -> Vec<MemoryPage> {
-    push(remove_first(page_frames), page)
+) -> Result<Vec<MemoryPage>, Error> {
+    evictable(&page_frames)?;
+    Ok(push(remove_first_unpinned(page_frames), page))
 }
 
 /// Least Recently Used Algorithm
@@ -31,68 +53,94 @@ pub fn fifo(
 /// For Example: [0, 1, 2, 0]
 ///     By LRU: '1' is the oldest page in the queue
 /// No Sort Order
+///
+/// Every page in page_frames must appear in past_pages
+/// Pinned pages are passed over, the unpinned page used longest ago is replaced
 pub fn lru(
     page_frames: Vec<MemoryPage>,
     page: MemoryPage,
     mut past_pages: Vec<u32>,
-) -> Vec<MemoryPage> {
+) -> Result<Vec<MemoryPage>, Error> {
     let length = page_frames.len();
+    evictable(&page_frames)?;
     past_pages.reverse();
     let filter_pages = past_pages
         .into_iter()
         .fold(Vec::<u32>::new(), add_if_not_found);
 
-    let replace_page_number = *filter_pages.get(length - 1).unwrap();
+    // A pinned page stays resident however long ago it was used, so the
+    // frames are no longer the most recently used pages
+    if page_frames.iter().any(|x| x.pinned) {
+        let replace_page_number = *filter_pages
+            .iter()
+            .rev()
+            .find(|x| page_frames.iter().any(|y| y.number == **x && !y.pinned))
+            .ok_or_else(|| {
+                Error::InconsistentState("lru history has no unpinned page in the frames".to_string())
+            })?;
+        let page_frames = page_frames
+            .into_iter()
+            .filter(|x| x.number != replace_page_number)
+            .collect::<Vec<MemoryPage>>();
+        return Ok(push(page_frames, page));
+    }
+
+    let replace_page_number = *filter_pages.get(length - 1).ok_or_else(|| {
+        Error::InconsistentState(format!(
+            "lru history has {} distinct pages for {} frames",
+            filter_pages.len(),
+            length
+        ))
+    })?;
+    if !page_frames.iter().any(|x| x.number == replace_page_number) {
+        return Err(Error::InconsistentState(format!(
+            "lru picked page {} which is not in the frames",
+            replace_page_number
+        )));
+    }
 
     let page_frames = page_frames
         .into_iter()
         .filter(|x| x.number != replace_page_number)
         .collect::<Vec<MemoryPage>>();
-    push(page_frames, page)
+    Ok(push(page_frames, page))
 }
 
-pub fn clock(page_frames: Vec<MemoryPage>, page: MemoryPage ,mut _past_pages: Vec<u32>) -> Vec<MemoryPage>
-{
-    let mut clock_hand = 0;
-
-
-    /*let replacement_page = page_frames.clone().into_iter()
-    .filter(|x | x.referenced == false)
-    .collect::<Vec<MemoryPage>>();
-
-
-    let page_frames = page_frames.clone()
-    .into_iter()
-    .filter(|x| x.referenced != replacement_page.get(clock_hand).unwrap().referenced)
-    .collect::<Vec<MemoryPage>>();
-    clock_hand += 1;
-
-    return push(remove_first(page_frames), page);*/
-
-    for x in page_frames.clone().iter_mut()
-    {
-        if x.referenced == false
-        {
-            return push(remove_first(page_frames.clone()), page);
-        }
-        clock_hand += 1;
-        if x.referenced == true
-        {
-            x.clear();
-            clock_hand += 1;
+/// Clock Algorithm
+/// page_frames is the currently loaded pages in memory, in slot order
+/// page is the page we want to load into memory
+///
+/// The frames sit on a circle and the hand starts at the slot after the page
+/// loaded last. It sweeps clearing reference bits until it finds a page that
+/// has not been referenced, and the new page takes that page's slot
+/// Pinned pages are passed over without losing their reference bit
+pub fn clock(
+    mut page_frames: Vec<MemoryPage>,
+    page: MemoryPage,
+    _past_pages: Vec<u32>,
+) -> Result<Vec<MemoryPage>, Error> {
+    evictable(&page_frames)?;
+    let length = page_frames.len();
+    let newest = (0..length)
+        .max_by_key(|x| page_frames[*x].loaded_at)
+        .unwrap_or(0);
+    let mut hand = (newest + 1) % length;
+    // Ends within two sweeps, the first clears every unpinned page
+    loop {
+        let current = page_frames[hand];
+        if !current.pinned && !current.referenced {
+            page_frames[hand] = page;
+            return Ok(page_frames);
         }
-
-        if clock_hand == page_frames.len() - 1
-        {
-            clock_hand = clock_hand % page_frames.len();
+        if !current.pinned {
+            page_frames[hand] = current.unreferenced();
         }
+        hand = (hand + 1) % length;
     }
-
-    page_frames
 }
 
 pub fn nru(page_frames: Vec<MemoryPage>, page: MemoryPage,
-    mut _past_pages: Vec<u32>) -> Vec<MemoryPage>
+    mut _past_pages: Vec<u32>) -> Result<Vec<MemoryPage>, Error>
 {
     // filter page_frames by case 0
     // if non_empty -> remove random page from filter, then remove that page from page_frames, then add page
@@ -100,22 +148,23 @@ pub fn nru(page_frames: Vec<MemoryPage>, page: MemoryPage,
     // if non_empty -> remove random page, add page
 
     //let length = page_frames.len();
+    evictable(&page_frames)?;
     let mut rng = thread_rng();
 
     let case0 = page_frames.clone().into_iter()
-    .filter(|x| x.referenced == false && x.modified == false)
+    .filter(|x| !x.pinned && !x.referenced && !x.modified)
     .collect::<Vec<MemoryPage>>();
 
     let case1 = page_frames.clone().into_iter()
-    .filter(|x| x.referenced == false && x.modified == true)
+    .filter(|x| !x.pinned && !x.referenced && x.modified)
     .collect::<Vec<MemoryPage>>();
 
     let case2 = page_frames.clone().into_iter()
-    .filter(|x| x.referenced == true && x.modified == false)
+    .filter(|x| !x.pinned && x.referenced && !x.modified)
     .collect::<Vec<MemoryPage>>();
 
     let case3 = page_frames.clone().into_iter()
-    .filter(|x| x.referenced == true && x.modified == true)
+    .filter(|x| !x.pinned && x.referenced && x.modified)
     .collect::<Vec<MemoryPage>>();
 
     if !case0.is_empty() {
@@ -125,7 +174,7 @@ pub fn nru(page_frames: Vec<MemoryPage>, page: MemoryPage,
         let page_frames = page_frames.into_iter()
         .filter(|x| *x != remove_page)
         .collect();
-        return push(page_frames, page);
+        return Ok(push(page_frames, page));
     }
 
     if !case1.is_empty()
@@ -136,7 +185,7 @@ pub fn nru(page_frames: Vec<MemoryPage>, page: MemoryPage,
         let page_frames = page_frames.into_iter()
         .filter(|x| *x != remove_page)
         .collect::<Vec<MemoryPage>>();
-        return push(page_frames, page);
+        return Ok(push(page_frames, page));
     }
 
     if !case2.is_empty()
@@ -147,7 +196,7 @@ pub fn nru(page_frames: Vec<MemoryPage>, page: MemoryPage,
         let page_frames = page_frames.into_iter()
         .filter(|x| *x != remove_page)
         .collect::<Vec<MemoryPage>>();
-        return push(page_frames, page);
+        return Ok(push(page_frames, page));
     }
 
     if !case3.is_empty() {
@@ -157,10 +206,11 @@ pub fn nru(page_frames: Vec<MemoryPage>, page: MemoryPage,
         let page_frames = page_frames.into_iter()
         .filter(|x| *x != remove_page)
         .collect::<Vec<MemoryPage>>();
-        return push(page_frames, page);
+        return Ok(push(page_frames, page));
     }
 
-    page_frames
+    // Only reached without an unpinned page, which evictable rules out
+    Err(Error::NothingToEvict)
 }
 
 
@@ -169,28 +219,35 @@ pub fn nru(page_frames: Vec<MemoryPage>, page: MemoryPage,
 /// However, if the oldest has been referenced, then clear it, and look at the second oldest page
 /// Continue looking at the next oldest till you find one that has not been referenced and replace it
 /// Keep sort order of [Oldest -> Newest]
+/// Pinned pages are passed over without losing their reference bit
 pub fn second_chance(
     page_frames: Vec<MemoryPage>,
     page: MemoryPage,
     mut _past_pages: Vec<u32>,
-) -> Vec<MemoryPage> {
+) -> Result<Vec<MemoryPage>, Error> {
     recursive_second_chance(page_frames, page)
 }
 
 pub fn recursive_second_chance(
     mut page_frames: Vec<MemoryPage>,
     page: MemoryPage,
-) -> Vec<MemoryPage> {
-    let oldest_page = page_frames.get(0).unwrap();
+) -> Result<Vec<MemoryPage>, Error> {
+    // Without an unpinned page this would go round forever
+    evictable(&page_frames)?;
+    let oldest_page = page_frames[0];
     // Base Case
-    if oldest_page.referenced == false {
+    if !oldest_page.referenced && !oldest_page.pinned {
         // replace it
         page_frames.remove(0);
         page_frames.push(page);
-        return page_frames;
+        return Ok(page_frames);
     }
 
-    page_frames.push(oldest_page.clear());
+    if oldest_page.pinned {
+        page_frames.push(oldest_page);
+    } else {
+        page_frames.push(oldest_page.clear());
+    }
     page_frames.remove(0);
 
     recursive_second_chance(page_frames, page)
@@ -219,7 +276,7 @@ mod test {
         ];
 
         let res = lru(page_frames, page, past_pages);
-        assert_eq!(res, expected);
+        assert_eq!(res, Ok(expected));
     }
 
     #[test]
@@ -230,7 +287,7 @@ mod test {
         let expected = vec![MemoryPage::new(3), MemoryPage::new(5), MemoryPage::new(6)];
 
         let res = fifo(page_frames, page, past_pages);
-        assert_eq!(res, expected);
+        assert_eq!(res, Ok(expected));
     }
 
     #[test]
@@ -240,7 +297,7 @@ mod test {
         let expected = vec![MemoryPage::new(1), MemoryPage::new(2), MemoryPage::new(3)];
 
         let res = second_chance(page_frames, page, vec![]);
-        assert_eq!(res, expected);
+        assert_eq!(res, Ok(expected));
     }
 
     #[test]
@@ -253,7 +310,7 @@ mod test {
         let page = MemoryPage::new(3);
         let expected = vec![MemoryPage::new(2), MemoryPage::new(0), MemoryPage::new(3)];
         let res = second_chance(page_frames, page, vec![]);
-        assert_eq!(res, expected);
+        assert_eq!(res, Ok(expected));
     }
 
     #[test]
@@ -266,7 +323,7 @@ mod test {
         let page = MemoryPage::new(3);
         let expected = vec![MemoryPage::new(0), MemoryPage::new(1), MemoryPage::new(3)];
         let res = second_chance(page_frames, page, vec![]);
-        assert_eq!(res, expected);
+        assert_eq!(res, Ok(expected));
     }
 
     #[test]
@@ -279,7 +336,7 @@ mod test {
         let page = MemoryPage::new(3);
         let expected = vec![MemoryPage::new(1), MemoryPage::new(2), MemoryPage::new(3)];
         let res = second_chance(page_frames, page, vec![]);
-        assert_eq!(res, expected);
+        assert_eq!(res, Ok(expected));
     }
 
     #[test]
@@ -294,7 +351,7 @@ mod test {
         let page = MemoryPage::new(6).referenced();
         let expected = vec![MemoryPage::new(4),MemoryPage::new(2), MemoryPage::new(1), MemoryPage::new(6)];
         let res = nru(page_frames, page, vec![]);
-        assert_eq!(res, expected);
+        assert_eq!(res, Ok(expected));
     }
 
     #[test]
@@ -307,7 +364,7 @@ mod test {
         let page = MemoryPage::new(3).referenced();
         let expected = vec![MemoryPage::new(8), MemoryPage::new(9), MemoryPage::new(3)];
         let res = nru(page_frames, page, vec![]);
-        assert_eq!(res, expected);
+        assert_eq!(res, Ok(expected));
     }
 
     #[test]
@@ -320,6 +377,121 @@ mod test {
         let page = MemoryPage::new(4).modified_and_referenced();
         let expected = vec![MemoryPage::new(9), MemoryPage::new(1), MemoryPage::new(4)];
         let res = nru(page_frames, page, vec![]);
+        assert_eq!(res, Ok(expected));
+    }
+
+    #[test]
+    fn lru_history_missing_frames_should_fail() {
+        let page_frames = vec![MemoryPage::new(0), MemoryPage::new(1), MemoryPage::new(2)];
+        let res = lru(page_frames, MemoryPage::new(3), vec![0, 1]);
+        assert_eq!(
+            res,
+            Err(Error::InconsistentState(
+                "lru history has 2 distinct pages for 3 frames".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn lru_history_disagreeing_with_frames_should_fail() {
+        let page_frames = vec![MemoryPage::new(0), MemoryPage::new(1)];
+        let res = lru(page_frames, MemoryPage::new(3), vec![0, 5, 1]);
+        assert_eq!(
+            res,
+            Err(Error::InconsistentState(
+                "lru picked page 5 which is not in the frames".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn empty_frames_should_have_nothing_to_evict() {
+        let algorithms: [Algorithm; 5] = [fifo, lru, clock, nru, second_chance];
+        for algorithm in algorithms.iter() {
+            assert_eq!(
+                algorithm(vec![], MemoryPage::new(0), vec![]),
+                Err(Error::NothingToEvict)
+            );
+        }
+        assert_eq!(
+            recursive_second_chance(vec![], MemoryPage::new(0)),
+            Err(Error::NothingToEvict)
+        );
+    }
+
+    #[test]
+    fn fifo_should_pass_over_pinned_pages() {
+        let page_frames = vec![MemoryPage::new(0).pinned(true), MemoryPage::new(3), MemoryPage::new(5)];
+        let expected = vec![MemoryPage::new(0), MemoryPage::new(5), MemoryPage::new(6)];
+        let res = fifo(page_frames, MemoryPage::new(6), vec![]);
+        assert_eq!(res, Ok(expected));
+    }
+
+    #[test]
+    fn lru_should_replace_least_recent_unpinned_page() {
+        let page_frames = vec![MemoryPage::new(7).pinned(true), MemoryPage::new(1), MemoryPage::new(2)];
+        let past_pages = vec![7, 1, 2, 1, 2, 1];
+        let expected = vec![MemoryPage::new(7), MemoryPage::new(1), MemoryPage::new(4)];
+        let res = lru(page_frames, MemoryPage::new(4), past_pages);
+        assert_eq!(res, Ok(expected));
+    }
+
+    #[test]
+    fn second_chance_should_pass_over_pinned_pages() {
+        let page_frames = vec![
+            MemoryPage::new(0).pinned(true),
+            MemoryPage::new(1).referenced(),
+            MemoryPage::new(2).pinned(true).referenced(),
+        ];
+        let expected = vec![MemoryPage::new(2), MemoryPage::new(0), MemoryPage::new(3)];
+        let res = second_chance(page_frames, MemoryPage::new(3), vec![]).unwrap();
+        assert_eq!(res, expected);
+        assert!(res[0].pinned && res[0].referenced);
+        assert!(res[1].pinned);
+    }
+
+    #[test]
+    fn nru_and_clock_should_never_evict_pinned_pages() {
+        for _ in 0..20 {
+            let page_frames = vec![
+                MemoryPage::new(0).pinned(true),
+                MemoryPage::new(1).referenced(),
+                MemoryPage::new(2).pinned(true),
+            ];
+            let expected = vec![MemoryPage::new(0), MemoryPage::new(2), MemoryPage::new(3)];
+            assert_eq!(nru(page_frames.clone(), MemoryPage::new(3), vec![]), Ok(expected));
+            // 1 loses its bit on the first sweep and its slot on the second
+            let expected = vec![MemoryPage::new(0), MemoryPage::new(3), MemoryPage::new(2)];
+            assert_eq!(clock(page_frames, MemoryPage::new(3), vec![]), Ok(expected));
+        }
+    }
+
+    #[test]
+    fn clock_should_start_after_the_newest_page() {
+        let page_frames = vec![
+            MemoryPage::new(0).loaded_at(3),
+            MemoryPage::new(1).loaded_at(1).referenced(),
+            MemoryPage::new(2).loaded_at(2),
+        ];
+        let expected = vec![MemoryPage::new(0), MemoryPage::new(1), MemoryPage::new(4)];
+        let res = clock(page_frames, MemoryPage::new(4), vec![]).unwrap();
         assert_eq!(res, expected);
+        assert!(!res[1].referenced);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn all_pinned_frames_should_be_out_of_frames() {
+        let page_frames = vec![MemoryPage::new(0).pinned(true), MemoryPage::new(1).referenced().pinned(true)];
+        let algorithms: [Algorithm; 5] = [fifo, lru, clock, nru, second_chance];
+        for algorithm in algorithms.iter() {
+            assert_eq!(
+                algorithm(page_frames.clone(), MemoryPage::new(2), vec![0, 1]),
+                Err(Error::OutOfFrames { frames: 2 })
+            );
+        }
+        assert_eq!(
+            recursive_second_chance(page_frames, MemoryPage::new(2)),
+            Err(Error::OutOfFrames { frames: 2 })
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use crate::error::Error;
+use crate::{PageId, Time};
+use std::collections::{BTreeSet, HashMap};
+
+/// Invariants every reference must keep, given the pages resident before and
+/// after page was referenced at step
+///
+/// - no more pages resident than there are frames
+/// - no page resident twice
+/// - the referenced page is resident afterwards
+/// - a hit changes nothing
+/// - a fault loads only the referenced page, and evicts exactly one page
+///   when memory was full and none otherwise
+///
+/// Costs O(frames), meant for checked runs rather than every run
+pub fn step(
+    step: Time,
+    page: PageId,
+    capacity: u32,
+    before: &[PageId],
+    after: &[PageId],
+) -> Result<(), Error> {
+    let violation = |message: String| Err(Error::Invariant { step, message });
+
+    if after.len() as u32 > capacity {
+        return violation(format!(
+            "{} pages resident in {} frames",
+            after.len(),
+            capacity
+        ));
+    }
+    let mut counts = HashMap::new();
+    for x in after {
+        *counts.entry(*x).or_insert(0) += 1;
+    }
+    if let Some((duplicate, count)) = counts.iter().filter(|(_, count)| **count > 1).min() {
+        return violation(format!("page {} is resident {} times", duplicate, count));
+    }
+    if !counts.contains_key(&page) {
+        return violation(format!(
+            "page {} is not resident after it was referenced",
+            page
+        ));
+    }
+
+    let before = before.iter().copied().collect::<BTreeSet<PageId>>();
+    let after = counts.keys().copied().collect::<BTreeSet<PageId>>();
+    let evicted = before.difference(&after).copied().collect::<Vec<PageId>>();
+    let loaded = after.difference(&before).copied().collect::<Vec<PageId>>();
+
+    if before.contains(&page) {
+        if !evicted.is_empty() || !loaded.is_empty() {
+            return violation(format!(
+                "hit on page {} evicted {} and loaded {}",
+                page,
+                list(&evicted),
+                list(&loaded)
+            ));
+        }
+        return Ok(());
+    }
+    if loaded != [page] {
+        return violation(format!("fault on page {} loaded {}", page, list(&loaded)));
+    }
+    let expected = if before.len() as u32 >= capacity {
+        1
+    } else {
+        0
+    };
+    if evicted.len() != expected {
+        return violation(format!(
+            "fault on page {} with {} of {} frames used evicted {}",
+            page,
+            before.len(),
+            capacity,
+            list(&evicted)
+        ));
+    }
+    Ok(())
+}
+
+/// Every page pinned before a reference is still resident after it
+pub fn pinned(step: Time, pinned: &[PageId], after: &[PageId]) -> Result<(), Error> {
+    let evicted = pinned
+        .iter()
+        .filter(|x| !after.contains(x))
+        .copied()
+        .collect::<Vec<PageId>>();
+    if evicted.is_empty() {
+        return Ok(());
+    }
+    Err(Error::Invariant {
+        step,
+        message: format!("pinned pages {} were evicted", list(&evicted)),
+    })
+}
+
+/// A reference served from a slower tier, without promoting its page,
+/// changed nothing in the frames
+pub fn slow_hit(
+    step: Time,
+    page: PageId,
+    before: &[PageId],
+    after: &[PageId],
+) -> Result<(), Error> {
+    let before = before.iter().copied().collect::<BTreeSet<PageId>>();
+    let after = after.iter().copied().collect::<BTreeSet<PageId>>();
+    if before == after {
+        return Ok(());
+    }
+    let evicted = before.difference(&after).copied().collect::<Vec<PageId>>();
+    let loaded = after.difference(&before).copied().collect::<Vec<PageId>>();
+    Err(Error::Invariant {
+        step,
+        message: format!(
+            "reference to page {} in a slower tier evicted {} and loaded {}",
+            page,
+            list(&evicted),
+            list(&loaded)
+        ),
+    })
+}
+
+/// Every huge page of heads has all span of its pages resident, and the
+/// pages fit the frames
+pub fn huge(
+    step: Time,
+    capacity: u32,
+    span: u32,
+    heads: &[PageId],
+    after: &[PageId],
+) -> Result<(), Error> {
+    let violation = |message: String| Err(Error::Invariant { step, message });
+    if after.len() as u32 > capacity {
+        return violation(format!(
+            "{} pages resident in {} frames after collapsing",
+            after.len(),
+            capacity
+        ));
+    }
+    let after = after.iter().copied().collect::<BTreeSet<PageId>>();
+    for head in heads {
+        let missing = (head.number..head.number + span)
+            .map(|x| PageId::new(head.process, x))
+            .filter(|x| !after.contains(x))
+            .collect::<Vec<PageId>>();
+        if !missing.is_empty() {
+            return violation(format!(
+                "huge page at {} is missing pages {}",
+                head,
+                list(&missing)
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Pages read ahead after a reference fit the frames and left the
+/// referenced page resident
+pub fn prefetch(step: Time, page: PageId, capacity: u32, after: &[PageId]) -> Result<(), Error> {
+    let violation = |message: String| Err(Error::Invariant { step, message });
+    if after.len() as u32 > capacity {
+        return violation(format!(
+            "{} pages resident in {} frames after prefetching",
+            after.len(),
+            capacity
+        ));
+    }
+    if !after.contains(&page) {
+        return violation(format!(
+            "prefetching evicted page {} it read ahead of",
+            page
+        ));
+    }
+    Ok(())
+}
+
+fn list(pages: &[PageId]) -> String {
+    let pages = pages.iter().map(|x| x.to_string()).collect::<Vec<String>>();
+    format!("[{}]", pages.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(
+        step: Time,
+        page: u32,
+        capacity: u32,
+        before: &[u32],
+        after: &[u32],
+    ) -> Result<(), Error> {
+        let ids = |x: &[u32]| x.iter().map(|x| PageId::from(*x)).collect::<Vec<PageId>>();
+        super::step(step, page.into(), capacity, &ids(before), &ids(after))
+    }
+
+    fn message(res: Result<(), Error>) -> String {
+        match res {
+            Err(Error::Invariant { step, message }) => format!("{}: {}", step, message),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn valid_steps_should_pass() {
+        assert_eq!(check(0, 1, 2, &[], &[1]), Ok(()));
+        assert_eq!(check(1, 1, 2, &[1], &[1]), Ok(()));
+        assert_eq!(check(2, 3, 2, &[1, 2], &[2, 3]), Ok(()));
+    }
+
+    #[test]
+    fn prefetch_should_keep_the_referenced_page() {
+        let ids = |x: &[u32]| x.iter().map(|x| PageId::from(*x)).collect::<Vec<PageId>>();
+        assert_eq!(prefetch(1, 2.into(), 3, &ids(&[2, 3, 4])), Ok(()));
+        let res = prefetch(1, 2.into(), 3, &ids(&[3, 4, 5]));
+        assert_eq!(
+            message(res),
+            "1: prefetching evicted page 2 it read ahead of"
+        );
+        let res = prefetch(4, 2.into(), 2, &ids(&[2, 3, 4]));
+        assert_eq!(
+            message(res),
+            "4: 3 pages resident in 2 frames after prefetching"
+        );
+    }
+
+    #[test]
+    fn slow_hit_should_leave_the_frames_alone() {
+        let ids = |x: &[u32]| x.iter().map(|x| PageId::from(*x)).collect::<Vec<PageId>>();
+        assert_eq!(slow_hit(3, 9.into(), &ids(&[1, 2]), &ids(&[2, 1])), Ok(()));
+        let res = slow_hit(3, 9.into(), &ids(&[1, 2]), &ids(&[2, 9]));
+        assert_eq!(
+            message(res),
+            "3: reference to page 9 in a slower tier evicted [1] and loaded [9]"
+        );
+    }
+
+    #[test]
+    fn huge_pages_should_be_whole() {
+        let ids = |x: &[u32]| x.iter().map(|x| PageId::from(*x)).collect::<Vec<PageId>>();
+        let after = ids(&[4, 5, 6, 7, 1]);
+        assert_eq!(huge(2, 5, 4, &ids(&[4]), &after), Ok(()));
+        let res = huge(2, 5, 4, &ids(&[0, 4]), &after);
+        assert_eq!(message(res), "2: huge page at 0 is missing pages [0, 2, 3]");
+        let res = huge(2, 4, 4, &ids(&[4]), &after);
+        assert_eq!(
+            message(res),
+            "2: 5 pages resident in 4 frames after collapsing"
+        );
+    }
+
+    #[test]
+    fn evicted_pinned_page_should_fail() {
+        let ids = |x: &[u32]| x.iter().map(|x| PageId::from(*x)).collect::<Vec<PageId>>();
+        assert_eq!(pinned(2, &ids(&[1]), &ids(&[1, 3])), Ok(()));
+        let res = pinned(3, &ids(&[1, 2]), &ids(&[2, 4]));
+        assert_eq!(message(res), "3: pinned pages [1] were evicted");
+    }
+
+    #[test]
+    fn too_many_pages_should_fail() {
+        let res = check(4, 3, 2, &[1, 2], &[1, 2, 3]);
+        assert_eq!(message(res), "4: 3 pages resident in 2 frames");
+    }
+
+    #[test]
+    fn duplicate_page_should_fail() {
+        let res = check(5, 3, 3, &[1, 2], &[3, 2, 3]);
+        assert_eq!(message(res), "5: page 3 is resident 2 times");
+    }
+
+    #[test]
+    fn missing_page_should_fail() {
+        let res = check(6, 3, 2, &[1, 2], &[1, 2]);
+        assert_eq!(
+            message(res),
+            "6: page 3 is not resident after it was referenced"
+        );
+    }
+
+    #[test]
+    fn hit_changing_residency_should_fail() {
+        let res = check(7, 1, 2, &[1, 2], &[1]);
+        assert_eq!(message(res), "7: hit on page 1 evicted [2] and loaded []");
+    }
+
+    #[test]
+    fn fault_must_evict_exactly_one_when_full() {
+        let res = check(8, 3, 2, &[1, 2], &[3]);
+        assert_eq!(
+            message(res),
+            "8: fault on page 3 with 2 of 2 frames used evicted [1, 2]"
+        );
+        let res = check(9, 3, 3, &[1, 2], &[3, 1]);
+        assert_eq!(
+            message(res),
+            "9: fault on page 3 with 2 of 3 frames used evicted [2]"
+        );
+        let res = check(10, 3, 3, &[1], &[1, 3, 4]);
+        assert_eq!(message(res), "10: fault on page 3 loaded [3, 4]");
+    }
+}
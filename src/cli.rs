@@ -0,0 +1,760 @@
+use crate::huge::{self, HugePages};
+use crate::kswapd::{self, Watermarks};
+use crate::load::LoadControl;
+use crate::output::{Format, RunResult};
+use crate::policy::{self, POLICIES};
+use crate::prefetch::Prefetch;
+use crate::scope::Scope;
+use crate::settings::{product, Settings};
+use crate::swap::{self, Device};
+use crate::sweep::{self, Progress};
+use crate::tier::{self, Tiering};
+use crate::tlb::{self, Translation};
+use crate::workload::{Source, Workload};
+use crate::zswap::{self, Zswap};
+use rand::prelude::*;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Simulate page replacement algorithms
+
+USAGE:
+    page_replacement [run] [OPTIONS]
+    page_replacement experiment <FILE> [OPTIONS]
+    page_replacement list
+    page_replacement help
+
+RUN OPTIONS:
+    -a, --algorithms <NAMES>  comma separated algorithm names or 'all',
+                              active_inactive and mglru take settings as in
+                              active_inactive:swappiness=60,inactive_ratio=1,
+                              file_from=1000 (pages from 1000 on are file backed,
+                              the rest anonymous) or mglru:generations=4
+                              [default: all]
+    -f, --frames <SIZES>      frame sizes as a list and/or ranges, e.g. 3,5,10 or 2..=16
+                              or 4..32:4 (every 4th) [default: 3,5,10]
+    -t, --trace <FILE>        replay a recorded trace (plain, gzip or zstd) instead of
+                              generating one, lines starting with 'write', 'pin' or
+                              'unpin' write, pin or unpin the pages on them, and
+                              'share 0:5 1:5' maps 1:5 copy on write onto 0:5's frame
+    -w, --workload <KIND>     uniform, loop or zipf [default: uniform]
+    -l, --length <N>          references to generate [default: 100]
+    -p, --pages <N>           distinct pages to generate [default: 20]
+    -z, --exponent <S>        zipf exponent [default: 1.0]
+    -P, --processes <N>       processes taking turns, each running its own copy of the
+                              workload with its own pages [default: 1]
+        --scope <SCOPE>       which pages a fault may evict: global (any process), local
+                              (only the faulting process's, equal shares of the frames),
+                              local:proportional (shares by process size) or
+                              local:priority=3,1,1 (shares by weight), or pff
+                              (equal shares moved by page fault frequency, tuned as in
+                              pff:window=100,lower=0.05,upper=0.25) [default: global]
+        --load-control <SPEC> suspend processes while memory thrashes, judged by
+                              fault_rate or working_set, tuned as in
+                              fault_rate:window=100,suspend=0.5,resume=0.2 or
+                              working_set:window=100,tau=200. Every run is also done
+                              without it to compare throughput [default: off]
+        --tlb <SPEC>          translate every reference through a TLB and page table
+                              walk: on for 64 entries of 4 ways over levels=8/8/8/8,
+                              or tuned as in entries=32,ways=8,policy=fifo,levels=10/10,
+                              page_size=4096,lookup=1 [default: off]
+        --prefetch <SPEC>     read pages ahead of their references: sequential (the
+                              window pages after every fault), adaptive (readahead
+                              doubling its window along a stream) or stride (along a
+                              repeated distance), tuned as in sequential:window=4,
+                              adaptive:initial=4,max=32 or stride:degree=2
+                              [default: off]
+        --swap <SPEC>         simulate a backing store faults and dirty write-backs wait
+                              on, in nanoseconds: on for a 5 ms disk serving one request
+                              at a time, or tuned as in read=100000,write=200000,
+                              bandwidth=2,queue_depth=32,page_size=4096,memory=100,
+                              clean=4 (clean writes up to 4 dirty pages in the
+                              background whenever the device is idle) [default: off]
+        --watermarks <SPEC>   run a page-out daemon that wakes when fewer than low frames
+                              are free and evicts up to batch pages a reference until
+                              high are: on for low=2,high=8,batch=4, or tuned as in
+                              low=1,high=4,batch=2 [default: off]
+        --tiers <SPEC>        demote evicted pages to slower memory below the frames
+                              and promote the ones referenced hot times within window
+                              references back: on for 64 pages at 300 ns under frames
+                              at 100 ns, promoted on 2 references in 100, or tuned as
+                              in tiers=64@300/256@1000,fast=100,hot=2,window=100,
+                              page_size=4096 (up to 3 slow tiers) [default: off]
+        --zswap <SPEC>        compress pages leaving memory into a pool in RAM before
+                              swap: on for 256 KiB compressing every page to a third,
+                              2 us to decompress and lru writing pages out, or tuned
+                              as in size=262144,ratio=2..4,decompress=2000,policy=lru,
+                              page_size=4096 [default: off]
+        --huge <SPEC>         collapse regions of hot pages into huge pages, split
+                              when the policy evicts them: on for 512 pages of 4 KiB
+                              collapsed once 256 were referenced in 1000 references,
+                              or tuned as in span=512,promote=256,window=1000,
+                              page_size=4096 [default: off]
+    -s, --seed <N>            generator seed [default: random]
+    -o, --format <FORMAT>     table, json, csv or markdown [default: table]
+        --output <FILE>       write results to FILE instead of stdout
+    -j, --jobs <N>            simulations to run at once [default: one per core]
+        --check               validate the frames after every reference and stop at
+                              the first broken invariant
+
+EXPERIMENT OPTIONS:
+        --output <DIR>        write the results bundle to DIR instead of the one in FILE
+    -j, --jobs <N>            simulations to run at once [default: one per core]
+";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Run(Box<RunOptions>),
+    /// Run every simulation of an experiment file
+    Experiment {
+        path: PathBuf,
+        output: Option<PathBuf>,
+        jobs: usize,
+    },
+    /// Print the available algorithms
+    List,
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunOptions {
+    pub algorithms: Vec<String>,
+    pub frames: Vec<u32>,
+    pub source: Source,
+    pub seed: Option<u64>,
+    pub format: Format,
+    pub output: Option<PathBuf>,
+    /// Simulations to run at once, 0 for one per core
+    pub jobs: usize,
+    /// Validate invariants after every reference
+    pub check: bool,
+    pub scope: Scope,
+    pub load_control: LoadControl,
+    /// TLB and page table in front of the frames
+    pub tlb: Option<Translation>,
+    pub prefetch: Prefetch,
+    pub swap: Option<Device>,
+    pub watermarks: Option<Watermarks>,
+    pub tiers: Option<Tiering>,
+    pub zswap: Option<Zswap>,
+    pub huge: Option<HugePages>,
+}
+
+/// Parse the arguments that follow the binary name
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let rest = match args.first().map(String::as_str) {
+        Some("list") => return no_arguments(&args[1..], Command::List),
+        Some("help") | Some("-h") | Some("--help") => {
+            return no_arguments(&args[1..], Command::Help)
+        }
+        Some("experiment") => return parse_experiment(&args[1..]),
+        Some("run") => &args[1..],
+        _ => args,
+    };
+    parse_run(rest).map(|x| Command::Run(Box::new(x)))
+}
+
+fn no_arguments(rest: &[String], command: Command) -> Result<Command, String> {
+    match rest.first() {
+        Some(x) => Err(format!("unexpected argument '{}'", x)),
+        None => Ok(command),
+    }
+}
+
+fn parse_experiment(args: &[String]) -> Result<Command, String> {
+    let mut path = None;
+    let mut output = None;
+    let mut jobs = 0;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--output" => output = Some(PathBuf::from(value()?)),
+            "-j" | "--jobs" => jobs = parse_number(arg, value()?)?,
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ if path.is_some() => return Err(format!("unexpected argument '{}'", arg)),
+            _ => path = Some(PathBuf::from(arg)),
+        }
+    }
+    match path {
+        Some(path) => Ok(Command::Experiment { path, output, jobs }),
+        None => Err("experiment needs a FILE".to_string()),
+    }
+}
+
+fn parse_run(args: &[String]) -> Result<RunOptions, String> {
+    let mut algorithms = vec!["all".to_string()];
+    let mut frames = vec![3, 5, 10];
+    let mut trace = None;
+    let mut kind = "uniform".to_string();
+    let mut length = 100;
+    let mut pages = 20;
+    let mut exponent = 1.0;
+    let mut processes = 1;
+    let mut seed = None;
+    let mut format = Format::Table;
+    let mut output = None;
+    let mut jobs = 0;
+    let mut check = false;
+    let mut scope = Scope::Global;
+    let mut load_control = LoadControl::Off;
+    let mut tlb = None;
+    let mut prefetch = Prefetch::Off;
+    let mut swap = None;
+    let mut watermarks = None;
+    let mut tiers = None;
+    let mut zswap = None;
+    let mut huge = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.find('=') {
+            Some(index) if arg.starts_with("--") => {
+                (&arg[..index], Some(arg[index + 1..].to_string()))
+            }
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next().cloned())
+                .ok_or_else(|| format!("{} needs a value", flag))
+        };
+        match flag {
+            "-a" | "--algorithms" => algorithms = parse_algorithms(&value()?),
+            "-f" | "--frames" => frames = parse_frames(&value()?)?,
+            "-t" | "--trace" => trace = Some(PathBuf::from(value()?)),
+            "-w" | "--workload" => kind = value()?,
+            "-l" | "--length" => length = parse_number(flag, &value()?)?,
+            "-p" | "--pages" => pages = parse_number(flag, &value()?)?,
+            "-z" | "--exponent" => exponent = parse_number(flag, &value()?)?,
+            "-P" | "--processes" => processes = parse_number(flag, &value()?)?,
+            "-s" | "--seed" => seed = Some(parse_number(flag, &value()?)?),
+            "-o" | "--format" => format = value()?.parse()?,
+            "--output" => output = Some(PathBuf::from(value()?)),
+            "-j" | "--jobs" => jobs = parse_number(flag, &value()?)?,
+            "--check" => check = true,
+            "--scope" => scope = value()?.parse()?,
+            "--load-control" => load_control = value()?.parse()?,
+            "--tlb" => tlb = tlb::parse_spec(&value()?)?,
+            "--prefetch" => prefetch = value()?.parse()?,
+            "--swap" => swap = swap::parse_spec(&value()?)?,
+            "--watermarks" => watermarks = kswapd::parse_spec(&value()?)?,
+            "--tiers" => tiers = tier::parse_spec(&value()?)?,
+            "--zswap" => zswap = zswap::parse_spec(&value()?)?,
+            "--huge" => huge = huge::parse_spec(&value()?)?,
+            _ => return Err(format!("unknown option '{}'", arg)),
+        }
+    }
+
+    if algorithms.iter().any(|x| x == "all") {
+        algorithms = POLICIES.iter().map(|name| name.to_string()).collect();
+    }
+    for name in algorithms.iter() {
+        policy::from_spec(name, 0)
+            .map_err(|err| format!("{}, see 'page_replacement list'", err))?;
+    }
+    if pages == 0 {
+        return Err("--pages must be at least 1".to_string());
+    }
+    if processes == 0 {
+        return Err("--processes must be at least 1".to_string());
+    }
+
+    let source = match trace {
+        Some(_) if processes > 1 => {
+            return Err(
+                "--processes cannot be used with --trace, traces name their processes".to_string(),
+            )
+        }
+        Some(path) => Source::Trace(path),
+        None => {
+            let workload = match kind.as_str() {
+                "uniform" => Workload::Uniform { length, pages },
+                "loop" => Workload::Loop { length, pages },
+                "zipf" => Workload::Zipf {
+                    length,
+                    pages,
+                    exponent,
+                },
+                _ => return Err(format!("unknown workload '{}'", kind)),
+            };
+            Source::Generate(match processes {
+                1 => workload,
+                _ => Workload::Interleave(vec![workload; processes]),
+            })
+        }
+    };
+
+    Ok(RunOptions {
+        algorithms,
+        frames,
+        source,
+        seed,
+        format,
+        output,
+        jobs,
+        check,
+        scope,
+        load_control,
+        tlb,
+        prefetch,
+        swap,
+        watermarks,
+        tiers,
+        zswap,
+        huge,
+    })
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a number, got '{}'", flag, value))
+}
+
+/// Comma separated algorithm names, each optionally followed by its
+/// settings as in 'lru,active_inactive:swappiness=100,inactive_ratio=2'
+fn parse_algorithms(value: &str) -> Vec<String> {
+    let mut algorithms = Vec::<String>::new();
+    for part in value.split(',').map(str::trim) {
+        match algorithms.last_mut() {
+            // a setting of the algorithm before it
+            Some(last) if last.contains(':') && part.contains('=') && !part.contains(':') => {
+                last.push(',');
+                last.push_str(part);
+            }
+            _ => algorithms.push(part.to_string()),
+        }
+    }
+    algorithms
+}
+
+/// Most frame sizes one --frames may list
+const MAX_FRAME_SIZES: usize = 10_000;
+
+/// Frame sizes as a comma separated mix of numbers and ranges
+/// A range is start..end (exclusive) or start..=end (inclusive) with an optional :step
+pub fn parse_frames(value: &str) -> Result<Vec<u32>, String> {
+    let mut frames = vec![];
+    for part in value.split(',').map(str::trim) {
+        let (range, step) = match part.find(':') {
+            Some(index) => (
+                &part[..index],
+                parse_number("--frames", &part[index + 1..])?,
+            ),
+            None => (part, 1),
+        };
+        let sizes = match range.find("..") {
+            None => vec![parse_number("--frames", range)?],
+            Some(index) => {
+                let start: u32 = parse_number("--frames", &range[..index])?;
+                let end = &range[index + 2..];
+                // the last size in the range, if it has one
+                let last = match end.strip_prefix('=') {
+                    Some(end) => Some(parse_number::<u32>("--frames", end)?),
+                    None => parse_number::<u32>("--frames", end)?.checked_sub(1),
+                };
+                if step == 0 {
+                    return Err(format!("frame range '{}' has a step of 0", part));
+                }
+                let count = match last {
+                    Some(last) if last >= start => (last - start) as usize / step + 1,
+                    _ => 0,
+                };
+                if frames.len() + count > MAX_FRAME_SIZES {
+                    return Err(format!("--frames has more than {} sizes", MAX_FRAME_SIZES));
+                }
+                match last {
+                    Some(last) => (start..=last).step_by(step).collect(),
+                    None => vec![],
+                }
+            }
+        };
+        frames.extend(sizes);
+    }
+    if frames.len() > MAX_FRAME_SIZES {
+        return Err(format!("--frames has more than {} sizes", MAX_FRAME_SIZES));
+    }
+    if frames.is_empty() {
+        return Err(format!("'{}' has no frame sizes", value));
+    }
+    if frames.contains(&0) {
+        return Err("frame sizes must be at least 1".to_string());
+    }
+    Ok(frames)
+}
+
+/// Run every selected algorithm with every frame size over the source, once
+/// without load control and once with it when it is on
+pub fn execute(options: &RunOptions, progress: Progress) -> Result<Vec<RunResult>, String> {
+    let seed = options.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    let controls = match options.load_control {
+        LoadControl::Off => vec![LoadControl::Off],
+        control => vec![LoadControl::Off, control],
+    };
+
+    let axes = [
+        options.algorithms.len(),
+        options.frames.len(),
+        controls.len(),
+    ];
+    let cells = product(&axes)
+        .into_iter()
+        .map(|x| Settings {
+            algorithm: options.algorithms[x[0]].clone(),
+            frames: options.frames[x[1]],
+            seed,
+            scope: options.scope.clone(),
+            load_control: controls[x[2]],
+            tlb: options.tlb.clone(),
+            prefetch: options.prefetch,
+            swap: options.swap,
+            watermarks: options.watermarks,
+            tiers: options.tiers.clone(),
+            zswap: options.zswap.clone(),
+            huge: options.huge,
+            check: options.check,
+        })
+        .collect::<Vec<Settings>>();
+    sweep::run(
+        &cells,
+        options.jobs,
+        |settings| settings.run(&options.source, &settings.algorithm, None),
+        progress,
+    )
+    .into_iter()
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    /// The options of a line that parses to Command::Run
+    fn run_options(line: &str) -> RunOptions {
+        match parse(&args(line)).unwrap() {
+            Command::Run(options) => *options,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn no_arguments_should_run_defaults() {
+        let res = parse(&[]).unwrap();
+        let expected = RunOptions {
+            algorithms: vec![
+                "fifo".to_string(),
+                "second_chance".to_string(),
+                "lru".to_string(),
+                "nru".to_string(),
+                "clock".to_string(),
+                "active_inactive".to_string(),
+                "mglru".to_string(),
+            ],
+            frames: vec![3, 5, 10],
+            source: Source::Generate(Workload::Uniform {
+                length: 100,
+                pages: 20,
+            }),
+            seed: None,
+            format: Format::Table,
+            output: None,
+            jobs: 0,
+            check: false,
+            scope: Scope::Global,
+            load_control: LoadControl::Off,
+            tlb: None,
+            prefetch: Prefetch::Off,
+            swap: None,
+            watermarks: None,
+            tiers: None,
+            zswap: None,
+            huge: None,
+        };
+        assert_eq!(res, Command::Run(Box::new(expected)));
+    }
+
+    #[test]
+    fn run_should_parse_options() {
+        let res = parse(&args(
+            "run -a fifo,lru --frames=2..=4 -w zipf -l 500 -p 30 -z 0.8 -s 9 -o csv -j 3 --check",
+        ));
+        match res {
+            Ok(Command::Run(options)) => {
+                assert_eq!(options.algorithms, vec!["fifo", "lru"]);
+                assert_eq!(options.frames, vec![2, 3, 4]);
+                assert_eq!(
+                    options.source,
+                    Source::Generate(Workload::Zipf {
+                        length: 500,
+                        pages: 30,
+                        exponent: 0.8
+                    })
+                );
+                assert_eq!(options.seed, Some(9));
+                assert_eq!(options.format, Format::Csv);
+                assert_eq!(options.jobs, 3);
+                assert!(options.check);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn list_and_help_should_parse() {
+        assert_eq!(parse(&args("list")), Ok(Command::List));
+        assert_eq!(parse(&args("--help")), Ok(Command::Help));
+        assert!(parse(&args("list extra")).is_err());
+    }
+
+    #[test]
+    fn experiment_should_parse_path_and_output() {
+        assert_eq!(
+            parse(&args("experiment suite.toml --output out -j 4")),
+            Ok(Command::Experiment {
+                path: PathBuf::from("suite.toml"),
+                output: Some(PathBuf::from("out")),
+                jobs: 4,
+            })
+        );
+        assert_eq!(
+            parse(&args("experiment suite.toml")),
+            Ok(Command::Experiment {
+                path: PathBuf::from("suite.toml"),
+                output: None,
+                jobs: 0,
+            })
+        );
+        assert!(parse(&args("experiment")).is_err());
+        assert!(parse(&args("experiment a.toml b.toml")).is_err());
+    }
+
+    #[test]
+    fn bad_arguments_should_be_errors() {
+        assert!(parse(&args("-a belady")).is_err());
+        assert!(parse(&args("--frames")).is_err());
+        assert!(parse(&args("-w normal")).is_err());
+        assert!(parse(&args("--bogus")).is_err());
+        assert!(parse(&args("-l many")).is_err());
+        assert!(parse(&args("-P 0")).is_err());
+        assert!(parse(&args("-t a.trace -P 2")).is_err());
+        assert!(parse(&args("--scope nearby")).is_err());
+        assert!(parse(&args("--scope pff:lower=0.9,upper=0.1")).is_err());
+        assert!(parse(&args("--load-control always")).is_err());
+    }
+
+    #[test]
+    fn processes_should_interleave_the_workload() {
+        let options = run_options("-w loop -l 4 -p 2 -P 3");
+        let workload = Workload::Loop {
+            length: 4,
+            pages: 2,
+        };
+        assert_eq!(
+            options.source,
+            Source::Generate(Workload::Interleave(vec![workload; 3]))
+        );
+    }
+
+    #[test]
+    fn parse_frames_should_mix_lists_and_ranges() {
+        assert_eq!(parse_frames("3,5,10"), Ok(vec![3, 5, 10]));
+        assert_eq!(parse_frames("1..4"), Ok(vec![1, 2, 3]));
+        assert_eq!(parse_frames("4..=16:4,20"), Ok(vec![4, 8, 12, 16, 20]));
+        assert!(parse_frames("0,1").is_err());
+        assert!(parse_frames("1..9:0").is_err());
+        assert_eq!(
+            parse_frames("4294967294..=4294967295"),
+            Ok(vec![u32::MAX - 1, u32::MAX])
+        );
+        assert_eq!(parse_frames("1..1,2"), Ok(vec![2]));
+        assert!(parse_frames("1..=4294967295").is_err());
+    }
+
+    #[test]
+    fn parse_algorithms_should_keep_settings_with_their_name() {
+        assert_eq!(
+            parse_algorithms("lru, active_inactive:swappiness=100,inactive_ratio=2,clock"),
+            vec![
+                "lru",
+                "active_inactive:swappiness=100,inactive_ratio=2",
+                "clock"
+            ]
+        );
+        assert_eq!(
+            parse_algorithms("active_inactive,active_inactive:file_from=10"),
+            vec!["active_inactive", "active_inactive:file_from=10"]
+        );
+        let options = run_options("-a active_inactive:swappiness=0 -l 50 -s 2");
+        assert_eq!(execute(&options, &sweep::quiet).unwrap().len(), 3);
+        assert!(parse(&args("-a lru:swappiness=0")).is_err());
+        assert!(parse(&args("-a active_inactive:swappiness=300")).is_err());
+    }
+
+    #[test]
+    fn execute_should_run_every_combination() {
+        let options = run_options("-a fifo,lru -f 2,3 -l 40 -s 1");
+        let res = execute(&options, &sweep::quiet).unwrap();
+        assert_eq!(res.len(), 4);
+        assert_eq!((res[0].algorithm.as_str(), res[0].frames), ("fifo", 2));
+        assert_eq!((res[3].algorithm.as_str(), res[3].frames), ("lru", 3));
+        assert!(res
+            .iter()
+            .all(|x| x.seed == 1 && x.report.references() == 40));
+    }
+
+    #[test]
+    fn execute_should_report_unknown_policy() {
+        let mut options = run_options("-f 2 -l 10 -s 1");
+        options.algorithms = vec!["belady".to_string()];
+        let res = execute(&options, &sweep::quiet);
+        assert_eq!(res.err(), Some("unknown algorithm 'belady'".to_string()));
+    }
+
+    #[test]
+    fn local_scope_should_split_frames_between_processes() {
+        let line = "-a all -f 7 -w zipf -l 300 -P 3 -s 4 --check --scope local:priority=5";
+        let options = run_options(line);
+        assert_eq!(
+            options.scope,
+            Scope::Local(crate::scope::Allocation::Priority(vec![5]))
+        );
+        for result in execute(&options, &sweep::quiet).unwrap() {
+            let frames = result
+                .report
+                .processes()
+                .map(|(_, x)| x.frames())
+                .collect::<Vec<_>>();
+            assert_eq!(
+                frames,
+                vec![Some(4), Some(2), Some(1)],
+                "{}",
+                result.algorithm
+            );
+            assert_eq!(result.scope.to_string(), "local(priority(5))");
+        }
+        let options = RunOptions {
+            frames: vec![2],
+            ..options
+        };
+        assert_eq!(
+            execute(&options, &sweep::quiet).err(),
+            Some("2 frames cannot give each of 3 processes a frame".to_string())
+        );
+    }
+
+    #[test]
+    fn load_control_should_run_with_and_without() {
+        let line = "-a fifo -f 5 -w loop -l 200 -p 4 -P 2 -s 1 --load-control fault_rate:window=20";
+        let options = run_options(line);
+        let res = execute(&options, &sweep::quiet).unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].load_control, LoadControl::Off);
+        assert_eq!(res[1].load_control, options.load_control);
+        assert!(res[1].report.suspensions() > 0);
+        assert!(res[1].report.faults() < res[0].report.faults());
+        assert!(crate::output::to_table(&res).contains("| load control: off | suspensions: 0 |"));
+    }
+
+    #[test]
+    fn tlb_should_translate_every_reference() {
+        let line = "-a lru -f 4 -w zipf -l 300 -p 40 -s 2 --tlb entries=8,ways=2";
+        let options = run_options(line);
+        assert_eq!(options.tlb.as_ref().map(|x| x.entries), Some(8));
+        let res = execute(&options, &sweep::quiet).unwrap();
+        let translation = res[0].report.translation().unwrap();
+        assert_eq!(translation.hits() + translation.misses(), 300);
+        assert!(translation.misses() >= res[0].report.faults());
+        assert_eq!(translation.walk_references(), 4 * translation.misses());
+        assert!(crate::output::to_csv(&res).contains(",\"tlb(entries=8,ways=2,"));
+        assert!(parse(&args("--tlb entries=8,ways=3")).is_err());
+    }
+
+    #[test]
+    fn prefetch_should_report_useful_and_wasted_pages() {
+        let line = "-a fifo -f 6 -w loop -l 120 -p 12 -s 1 --prefetch sequential:window=2";
+        let options = run_options(line);
+        let res = execute(&options, &sweep::quiet).unwrap();
+        let report = &res[0].report;
+        assert_eq!(report.faults(), 40);
+        assert_eq!(report.useful_prefetches(), 80);
+        assert!(crate::output::to_csv(&res).contains(",sequential(window=2),"));
+        assert!(parse(&args("--prefetch sequential:window=0")).is_err());
+    }
+
+    #[test]
+    fn swap_should_report_simulated_time() {
+        let line =
+            "-a fifo,lru -f 4 -w zipf -l 300 -p 20 -s 6 --swap read=1000,write=1000,memory=10";
+        let options = run_options(line);
+        for result in execute(&options, &sweep::quiet).unwrap() {
+            let report = &result.report;
+            let swap = report.swap().unwrap();
+            assert_eq!(swap.reads(), report.faults());
+            let busy = report.hits() as f64 * 10.0 + report.faults() as f64 * 10.0;
+            assert!((swap.elapsed() - busy - swap.stall()).abs() < 1e-6);
+        }
+        assert!(parse(&args("--swap queue_depth=0")).is_err());
+    }
+
+    #[test]
+    fn watermarks_should_reclaim_in_the_background() {
+        let line = "-a lru -f 8 -w uniform -l 200 -p 30 -s 4 --watermarks low=2,high=3";
+        let options = run_options(line);
+        let res = execute(&options, &sweep::quiet).unwrap();
+        let report = &res[0].report;
+        assert_eq!(report.direct_reclaims(), 0);
+        assert_eq!(report.background_reclaims(), report.removed_count());
+        assert!(report.to_string().contains("| background reclaims: "));
+        assert!(parse(&args("--watermarks low=3,high=2")).is_err());
+    }
+
+    #[test]
+    fn tiers_should_serve_demoted_pages_without_faults() {
+        let line = "-a lru -f 8 -w zipf -l 400 -p 40 -s 3 --tiers tiers=16@300/32@900";
+        let options = run_options(line);
+        let tiered = execute(&options, &sweep::quiet).unwrap().remove(0).report;
+        let tiers = tiered.tiers().unwrap();
+        assert!(tiers.slow_hits() > 0);
+        assert_eq!(tiers.hits(2), None);
+        // 40 pages fit the frames and tiers, so only first touches fault
+        assert_eq!(tiered.faults(), tiered.compulsory_faults());
+        assert!(parse(&args("--tiers tiers=0@300")).is_err());
+    }
+
+    #[test]
+    fn zswap_should_turn_swap_ins_into_pool_hits() {
+        let line = "-a lru -f 8 -w zipf -l 400 -p 40 -s 3";
+        let run = |line: &str| {
+            let options = run_options(line);
+            execute(&options, &sweep::quiet).unwrap().remove(0).report
+        };
+        let plain = run(line);
+        let compressed = run(&format!("{} --zswap size=65536,ratio=2..4", line));
+        let pool = compressed.pool().unwrap();
+        assert!(pool.hits() > 0);
+        assert_eq!(compressed.faults(), plain.faults());
+        assert_eq!(compressed.swap_ins() + pool.hits(), plain.capacity_faults());
+        assert!(parse(&args("--zswap ratio=0.5")).is_err());
+    }
+
+    #[test]
+    fn huge_should_collapse_hot_regions_and_reach_further() {
+        let line = "-a lru -f 32 -w zipf -l 2000 -p 40 -s 2 --tlb on";
+        let run = |line: &str| {
+            let options = run_options(line);
+            execute(&options, &sweep::quiet).unwrap().remove(0).report
+        };
+        let plain = run(line);
+        assert_eq!(plain.translation().unwrap().reach_gain(), 1.0);
+        let report = run(&format!("{} --huge span=8,promote=4,window=100", line));
+        assert!(report.huge().unwrap().collapses() > 0);
+        assert!(report.translation().unwrap().reach_gain() > 1.0);
+        assert!(parse(&args("--huge span=6")).is_err());
+    }
+}
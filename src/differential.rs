@@ -0,0 +1,271 @@
+//! Random reference strings run through every policy, and through the
+//! algorithms of the same names, and compared against simple reference
+//! models that keep the frames in a plain Vec
+use crate::policy::Policy;
+use crate::simulation::{Outcome, Simulation};
+use crate::{PageId, PageReport};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+/// Reference strings to try for every property
+const CASES: u64 = 300;
+
+/// A random reference string with a random number of distinct pages, and a
+/// frame count that is sometimes larger than the number of pages
+fn case(seed: u64) -> (Vec<PageId>, u32) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let pages = rng.gen_range(1, 12);
+    let length = rng.gen_range(0, 150);
+    let trace = (0..length)
+        .map(|_| PageId::from(rng.gen_range(0, pages)))
+        .collect();
+    (trace, rng.gen_range(1, 10))
+}
+
+fn outcomes(policy: Box<dyn Policy>, trace: &[PageId], frames: u32) -> Vec<Outcome> {
+    let mut simulation = Simulation::new(policy, frames).unwrap().checked();
+    trace
+        .iter()
+        .map(|x| simulation.access(*x).unwrap())
+        .collect()
+}
+
+fn faults(outcomes: &[Outcome]) -> usize {
+    outcomes.iter().filter(|x| **x != Outcome::Hit).count()
+}
+
+/// Hits, faults and evictions, the counters a report of the same run has
+fn counters(outcomes: &[Outcome]) -> (u64, u64, u64) {
+    let evictions = outcomes
+        .iter()
+        .filter(|x| matches!(x, Outcome::Fault { evicted: Some(_) }))
+        .count();
+    let faults = faults(outcomes);
+    (
+        (outcomes.len() - faults) as u64,
+        faults as u64,
+        evictions as u64,
+    )
+}
+
+fn report_counters(report: &PageReport) -> (u64, u64, u64) {
+    (report.hits(), report.faults(), report.removed_count())
+}
+
+/// Resident pages with their referenced bit
+type Memory = Vec<(PageId, bool)>;
+
+/// A reference model, every outcome of trace with frames frames
+type Model = fn(&[PageId], u32) -> Vec<Outcome>;
+
+/// on_hit updates memory for a hit at an index, pick removes the victim
+/// once memory is full and says where the new page goes
+fn model<F>(trace: &[PageId], frames: u32, on_hit: fn(&mut Memory, usize), pick: F) -> Vec<Outcome>
+where
+    F: FnMut(&mut Memory, usize) -> (PageId, usize),
+{
+    let mut pick = pick;
+    let mut memory = Memory::new();
+    trace
+        .iter()
+        .enumerate()
+        .map(|(time, page)| {
+            if let Some(at) = memory.iter().position(|(x, _)| x == page) {
+                on_hit(&mut memory, at);
+                return Outcome::Hit;
+            }
+            if memory.len() < frames as usize {
+                memory.push((*page, false));
+                return Outcome::Fault { evicted: None };
+            }
+            let (victim, at) = pick(&mut memory, time);
+            memory.insert(at, (*page, false));
+            Outcome::Fault {
+                evicted: Some(victim),
+            }
+        })
+        .collect()
+}
+
+fn fifo(trace: &[PageId], frames: u32) -> Vec<Outcome> {
+    model(
+        trace,
+        frames,
+        |_, _| {},
+        |memory, _| {
+            let len = memory.len();
+            (memory.remove(0).0, len - 1)
+        },
+    )
+}
+
+/// Most recently used page last
+fn lru(trace: &[PageId], frames: u32) -> Vec<Outcome> {
+    let to_back = |memory: &mut Memory, at: usize| {
+        let page = memory.remove(at);
+        memory.push(page);
+    };
+    model(trace, frames, to_back, |memory, _| {
+        let len = memory.len();
+        (memory.remove(0).0, len - 1)
+    })
+}
+
+fn second_chance(trace: &[PageId], frames: u32) -> Vec<Outcome> {
+    let set_referenced = |memory: &mut Memory, at: usize| memory[at].1 = true;
+    model(trace, frames, set_referenced, |memory, _| loop {
+        let (page, referenced) = memory.remove(0);
+        if !referenced {
+            return (page, memory.len());
+        }
+        memory.push((page, false));
+    })
+}
+
+/// Frames in slot order, the new page takes the victim's slot
+fn clock(trace: &[PageId], frames: u32) -> Vec<Outcome> {
+    let set_referenced = |memory: &mut Memory, at: usize| memory[at].1 = true;
+    let mut hand = 0;
+    model(trace, frames, set_referenced, move |memory, _| loop {
+        let at = hand % memory.len();
+        hand = at + 1;
+        if !memory[at].1 {
+            return (memory.remove(at).0, at);
+        }
+        memory[at].1 = false;
+    })
+}
+
+/// Evict the page used again furthest in the future
+fn opt(trace: &[PageId], frames: u32) -> Vec<Outcome> {
+    model(
+        trace,
+        frames,
+        |_, _| {},
+        |memory, time| {
+            let next_use = |page: PageId| {
+                trace[time + 1..]
+                    .iter()
+                    .position(|x| *x == page)
+                    .unwrap_or(trace.len())
+            };
+            let at = (0..memory.len())
+                .max_by_key(|at| next_use(memory[*at].0))
+                .unwrap();
+            (memory.remove(at).0, at)
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::{by_name, Opt, POLICIES};
+    use crate::{algorithms, simulate_checked, Algorithm};
+
+    #[test]
+    fn policies_should_match_reference_models() {
+        let models: [(&str, Model); 4] = [
+            ("fifo", fifo),
+            ("lru", lru),
+            ("second_chance", second_chance),
+            ("clock", clock),
+        ];
+        for seed in 0..CASES {
+            let (trace, frames) = case(seed);
+            for (name, model) in models.iter() {
+                assert_eq!(
+                    outcomes(by_name(name, seed).unwrap(), &trace, frames),
+                    model(&trace, frames),
+                    "{} with {} frames on {:?}",
+                    name,
+                    frames,
+                    trace
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn opt_should_match_reference_model_and_beat_every_policy() {
+        for seed in 0..CASES {
+            let (trace, frames) = case(seed);
+            let best = faults(&outcomes(Box::new(Opt::new(&trace)), &trace, frames));
+            assert_eq!(best, faults(&opt(&trace, frames)), "opt on {:?}", trace);
+            for name in POLICIES.iter() {
+                let res = faults(&outcomes(by_name(name, seed).unwrap(), &trace, frames));
+                assert!(
+                    best <= res,
+                    "opt faulted {} times but {} only {} with {} frames on {:?}",
+                    best,
+                    name,
+                    res,
+                    frames,
+                    trace
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lru_faults_should_not_increase_with_frames() {
+        for seed in 0..CASES {
+            let (trace, _) = case(seed);
+            let res = (1..12)
+                .map(|frames| faults(&outcomes(by_name("lru", 0).unwrap(), &trace, frames)))
+                .collect::<Vec<usize>>();
+            assert!(
+                res.windows(2).all(|x| x[1] <= x[0]),
+                "lru faults {:?} on {:?}",
+                res,
+                trace
+            );
+        }
+    }
+
+    #[test]
+    fn second_chance_should_be_fifo_without_repeated_pages() {
+        for seed in 0..CASES {
+            let (trace, frames) = case(seed);
+            let mut seen = std::collections::HashSet::new();
+            let distinct = trace
+                .iter()
+                .copied()
+                .filter(|x| seen.insert(*x))
+                .collect::<Vec<PageId>>();
+            assert_eq!(
+                outcomes(by_name("second_chance", 0).unwrap(), &distinct, frames),
+                outcomes(by_name("fifo", 0).unwrap(), &distinct, frames),
+                "{:?}",
+                distinct
+            );
+        }
+    }
+
+    #[test]
+    fn algorithms_should_match_reference_models() {
+        let models: [(&str, Algorithm, Model); 4] = [
+            ("fifo", algorithms::fifo, fifo),
+            ("lru", algorithms::lru, lru),
+            ("second_chance", algorithms::second_chance, second_chance),
+            ("clock", algorithms::clock, clock),
+        ];
+        for seed in 0..CASES {
+            let (trace, frames) = case(seed);
+            let numbers = trace.iter().map(|x| x.number).collect::<Vec<u32>>();
+            for (name, algorithm, model) in models.iter() {
+                let res = simulate_checked(algorithm, &numbers, frames).unwrap_or_else(|err| {
+                    panic!("{} with {} frames on {:?}: {}", name, frames, numbers, err)
+                });
+                assert_eq!(
+                    report_counters(&res),
+                    counters(&model(&trace, frames)),
+                    "{} with {} frames on {:?}",
+                    name,
+                    frames,
+                    numbers
+                );
+            }
+        }
+    }
+}
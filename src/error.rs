@@ -0,0 +1,62 @@
+use crate::Time;
+use std::fmt;
+
+/// Everything that can go wrong configuring or running a simulation
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A simulation needs at least one frame
+    NoFrames,
+    /// Local replacement needs at least one frame per process
+    TooFewFrames { frames: u32, processes: usize },
+    /// Memory is full but the algorithm or policy had no page to evict
+    NothingToEvict,
+    /// Memory is full and every page that could make room is pinned
+    OutOfFrames { frames: u32 },
+    /// The frames, history or policy contradict each other
+    InconsistentState(String),
+    /// A checked run found a broken invariant at step, the reference index
+    Invariant { step: Time, message: String },
+    /// A trace could not be read or parsed
+    Trace(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoFrames => write!(f, "frame size must be at least 1"),
+            Error::TooFewFrames { frames, processes } => write!(
+                f,
+                "{} frames cannot give each of {} processes a frame",
+                frames, processes
+            ),
+            Error::NothingToEvict => write!(f, "memory is full but there is no page to evict"),
+            Error::OutOfFrames { frames } => {
+                write!(f, "out of frames: all {} frames hold pinned pages", frames)
+            }
+            Error::InconsistentState(x) => write!(f, "inconsistent state: {}", x),
+            Error::Invariant { step, message } => write!(f, "step {}: {}", step, message),
+            Error::Trace(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    /// Attach the step an inconsistency was found at
+    pub(crate) fn at(self, step: Time) -> Error {
+        match self {
+            Error::InconsistentState(message) => Error::Invariant { step, message },
+            err => err,
+        }
+    }
+}
+
+/// Option parsing and experiment files report plain messages
+impl From<Error> for String {
+    fn from(err: Error) -> String {
+        err.to_string()
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,739 @@
+use crate::cli::parse_frames;
+use crate::huge::{self, HugePages};
+use crate::kswapd::{self, Watermarks};
+use crate::load::LoadControl;
+use crate::output::{render, Format, RunResult};
+use crate::policy;
+use crate::prefetch::Prefetch;
+use crate::scope::Scope;
+use crate::settings::{product, Settings};
+use crate::swap::{self, Device};
+use crate::sweep::{self, Progress};
+use crate::tier::{self, Tiering};
+use crate::tlb::{self, Translation};
+use crate::workload::{Source, Workload};
+use crate::zswap::{self, Zswap};
+use crate::CostModel;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::value::{Table, Value};
+
+/// A policy in an experiment, reported under label
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicySpec {
+    pub algorithm: String,
+    pub label: String,
+}
+
+/// A declarative suite of simulations, read from a TOML file
+///
+/// ```toml
+/// name = "baseline"
+/// seeds = [1, 2, 3]
+/// frames = "2..=16"            # or a list, [3, 5, 10]
+/// scopes = ["global", "local:proportional", "pff:window=50"]  # defaults to ["global"]
+/// load_controls = ["off", "fault_rate", "working_set:tau=500"]  # defaults to ["off"]
+/// tlbs = ["off", "on", "entries=32,ways=8"]  # defaults to ["off"]
+/// prefetches = ["off", "sequential:window=8", "stride"]  # defaults to ["off"]
+/// swaps = ["off", "on", "read=100000,queue_depth=32,clean=4"]  # defaults to ["off"]
+/// watermarks = ["off", "low=2,high=8"]  # defaults to ["off"]
+/// tiers = ["off", "tiers=64@300/256@1000,hot=2"]  # defaults to ["off"]
+/// zswap = ["off", "size=262144,ratio=2..4"]  # defaults to ["off"]
+/// huge = ["off", "span=512,promote=256"]  # defaults to ["off"]
+/// check = true                 # validate invariants every step, defaults to false
+/// formats = ["csv", "json"]
+/// output = "results/baseline"  # defaults to results/<name>
+///
+/// [cost]
+/// hit = 100.0
+/// fault = 8000000.0
+///
+/// [[workload]]
+/// kind = "zipf"
+/// length = 10000
+/// pages = 200
+/// exponent = 0.9
+/// processes = 4                # copies taking turns, each its own process
+///
+/// [[workload]]
+/// kind = "trace"
+/// path = "traces/gcc.txt"
+///
+/// [[policy]]
+/// algorithm = "lru"
+///
+/// [[policy]]
+/// algorithm = "second_chance"
+/// label = "sc"
+///
+/// [[policy]]
+/// algorithm = "active_inactive:swappiness=100,file_from=1000"
+/// label = "linux"
+/// ```
+///
+/// Every workload is run with every seed, scope, load control, tlb,
+/// prefetch, swap device, watermarks, tiers, compressed pool, policy and
+/// frame size
+#[derive(Debug, Clone, PartialEq)]
+pub struct Experiment {
+    pub name: String,
+    pub workloads: Vec<Source>,
+    pub policies: Vec<PolicySpec>,
+    pub frames: Vec<u32>,
+    pub seeds: Vec<u64>,
+    pub scopes: Vec<Scope>,
+    pub load_controls: Vec<LoadControl>,
+    pub tlbs: Vec<Option<Translation>>,
+    pub prefetches: Vec<Prefetch>,
+    pub swaps: Vec<Option<Device>>,
+    pub watermarks: Vec<Option<Watermarks>>,
+    pub tiers: Vec<Option<Tiering>>,
+    pub zswaps: Vec<Option<Zswap>>,
+    pub huge_pages: Vec<Option<HugePages>>,
+    /// Run every cell as a checked simulation
+    pub check: bool,
+    pub cost: Option<CostModel>,
+    pub formats: Vec<Format>,
+    pub output: PathBuf,
+}
+
+/// One simulation of an experiment
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell<'a> {
+    pub source: &'a Source,
+    pub policy: &'a PolicySpec,
+    pub settings: Settings,
+}
+
+impl Experiment {
+    /// Relative paths in text are taken from base, the directory of the file
+    pub fn parse(text: &str, base: &Path) -> Result<Experiment, String> {
+        let root = text
+            .parse::<Value>()
+            .map_err(|err| format!("invalid experiment file: {}", err))?;
+        let root = root.as_table().unwrap();
+        check_keys(
+            root,
+            &[
+                "name",
+                "seeds",
+                "frames",
+                "scopes",
+                "load_controls",
+                "tlbs",
+                "prefetches",
+                "swaps",
+                "watermarks",
+                "tiers",
+                "zswap",
+                "huge",
+                "check",
+                "formats",
+                "output",
+                "cost",
+                "workload",
+                "policy",
+            ],
+            "experiment",
+        )?;
+
+        let name = match root.get("name") {
+            Some(x) => as_str(x, "name")?.to_string(),
+            None => "experiment".to_string(),
+        };
+        let seeds = axis(root, "seeds", 0, |x| {
+            as_integer(x, "seeds").map(|x| x as u64)
+        })?;
+        let frames = match root.get("frames") {
+            Some(Value::String(x)) => parse_frames(x)?,
+            Some(x) => {
+                let frames = as_array(x, "frames")?
+                    .iter()
+                    .map(|x| as_integer(x, "frames").map(|x| x.to_string()))
+                    .collect::<Result<Vec<String>, String>>()?;
+                parse_frames(&frames.join(","))?
+            }
+            None => return Err("experiment needs frames".to_string()),
+        };
+        let scopes = specs(root, "scopes", Scope::Global, str::parse)?;
+        let load_controls = specs(root, "load_controls", LoadControl::Off, str::parse)?;
+        let tlbs = specs(root, "tlbs", None, tlb::parse_spec)?;
+        let prefetches = specs(root, "prefetches", Prefetch::Off, str::parse)?;
+        let swaps = specs(root, "swaps", None, swap::parse_spec)?;
+        let watermarks = specs(root, "watermarks", None, kswapd::parse_spec)?;
+        let tiers = specs(root, "tiers", None, tier::parse_spec)?;
+        let zswaps = specs(root, "zswap", None, zswap::parse_spec)?;
+        let huge_pages = specs(root, "huge", None, huge::parse_spec)?;
+        let check = match root.get("check") {
+            Some(x) => x
+                .as_bool()
+                .ok_or_else(|| "check must be a boolean".to_string())?,
+            None => false,
+        };
+        let formats = match root.get("formats") {
+            Some(x) => as_array(x, "formats")?
+                .iter()
+                .map(|x| as_str(x, "formats")?.parse::<Format>())
+                .collect::<Result<Vec<Format>, String>>()?,
+            None => vec![Format::Csv],
+        };
+        let output = match root.get("output") {
+            Some(x) => base.join(as_str(x, "output")?),
+            None => base.join("results").join(&name),
+        };
+        let cost = match root.get("cost") {
+            Some(x) => Some(parse_cost(x)?),
+            None => None,
+        };
+        let workloads = tables(root, "workload")?
+            .into_iter()
+            .map(|x| parse_workload(x, base))
+            .collect::<Result<Vec<Source>, String>>()?;
+        let policies = tables(root, "policy")?
+            .into_iter()
+            .map(parse_policy)
+            .collect::<Result<Vec<PolicySpec>, String>>()?;
+
+        if workloads.is_empty() {
+            return Err("experiment needs at least one [[workload]]".to_string());
+        }
+        if policies.is_empty() {
+            return Err("experiment needs at least one [[policy]]".to_string());
+        }
+        Ok(Experiment {
+            name,
+            workloads,
+            policies,
+            frames,
+            seeds,
+            scopes,
+            load_controls,
+            tlbs,
+            prefetches,
+            swaps,
+            watermarks,
+            tiers,
+            zswaps,
+            huge_pages,
+            check,
+            cost,
+            formats,
+            output,
+        })
+    }
+
+    /// The experiment in path along with the exact text it was read from
+    pub fn load(path: &Path) -> Result<(Experiment, String), String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        Experiment::parse(&text, base).map(|x| (x, text))
+    }
+
+    /// Every simulation, ordered by workload, seed, scope, load control, tlb,
+    /// prefetch, swap device, watermarks, tiers, compressed pool, huge
+    /// pages, policy, then frames
+    pub fn cells(&self) -> Vec<Cell<'_>> {
+        let axes = [
+            self.workloads.len(),
+            self.seeds.len(),
+            self.scopes.len(),
+            self.load_controls.len(),
+            self.tlbs.len(),
+            self.prefetches.len(),
+            self.swaps.len(),
+            self.watermarks.len(),
+            self.tiers.len(),
+            self.zswaps.len(),
+            self.huge_pages.len(),
+            self.policies.len(),
+            self.frames.len(),
+        ];
+        product(&axes)
+            .into_iter()
+            .map(|x| Cell {
+                source: &self.workloads[x[0]],
+                policy: &self.policies[x[11]],
+                settings: Settings {
+                    algorithm: self.policies[x[11]].algorithm.clone(),
+                    frames: self.frames[x[12]],
+                    seed: self.seeds[x[1]],
+                    scope: self.scopes[x[2]].clone(),
+                    load_control: self.load_controls[x[3]],
+                    tlb: self.tlbs[x[4]].clone(),
+                    prefetch: self.prefetches[x[5]],
+                    swap: self.swaps[x[6]],
+                    watermarks: self.watermarks[x[7]],
+                    tiers: self.tiers[x[8]].clone(),
+                    zswap: self.zswaps[x[9]].clone(),
+                    huge: self.huge_pages[x[10]],
+                    check: self.check,
+                },
+            })
+            .collect()
+    }
+
+    /// Run every cell on threads threads (0 for one per core)
+    /// Each cell streams its own reference string, so no trace is ever held in memory
+    pub fn run(&self, threads: usize, progress: Progress) -> Result<Vec<RunResult>, String> {
+        sweep::run(&self.cells(), threads, |cell| self.run_cell(cell), progress)
+            .into_iter()
+            .collect()
+    }
+
+    pub fn run_cell(&self, cell: &Cell) -> Result<RunResult, String> {
+        cell.settings
+            .run(cell.source, &cell.policy.label, self.cost)
+    }
+
+    /// Write the results bundle: the config it came from and one file per format
+    pub fn write_bundle(&self, config: &str, results: &[RunResult]) -> Result<(), String> {
+        let write = |name: &str, contents: &str| {
+            let path = self.output.join(name);
+            fs::write(&path, contents)
+                .map_err(|err| format!("could not write {}: {}", path.display(), err))
+        };
+        fs::create_dir_all(&self.output)
+            .map_err(|err| format!("could not create {}: {}", self.output.display(), err))?;
+        write("experiment.toml", config)?;
+        for format in self.formats.iter() {
+            let name = match format {
+                Format::Table => "results.txt",
+                Format::Json => "results.json",
+                Format::Csv => "results.csv",
+                Format::Markdown => "results.md",
+            };
+            write(name, &render(results, *format))?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_cost(value: &Value) -> Result<CostModel, String> {
+    let table = value
+        .as_table()
+        .ok_or_else(|| "cost must be a table".to_string())?;
+    check_keys(table, &["hit", "fault", "eviction"], "cost")?;
+    let default = CostModel::default();
+    let get = |key: &str, default: f64| match table.get(key) {
+        Some(x) => as_float(x, key),
+        None => Ok(default),
+    };
+    Ok(CostModel {
+        hit: get("hit", default.hit)?,
+        fault: get("fault", default.fault)?,
+        eviction: get("eviction", default.eviction)?,
+    })
+}
+
+fn parse_workload(table: &Table, base: &Path) -> Result<Source, String> {
+    let kind = as_str(
+        table.get("kind").ok_or("workload needs a kind")?,
+        "workload kind",
+    )?;
+    if kind == "trace" {
+        check_keys(table, &["kind", "path"], "trace workload")?;
+        let path = as_str(table.get("path").ok_or("trace needs a path")?, "path")?;
+        return Ok(Source::Trace(base.join(path)));
+    }
+
+    check_keys(
+        table,
+        &["kind", "length", "pages", "exponent", "processes"],
+        "workload",
+    )?;
+    let integer = |key: &str, default: i64| match table.get(key) {
+        Some(x) => as_integer(x, key),
+        None => Ok(default),
+    };
+    let length = usize::try_from(integer("length", 100)?).map_err(|_| too_large("length"))?;
+    let pages = u32::try_from(integer("pages", 20)?).map_err(|_| too_large("pages"))?;
+    let processes =
+        usize::try_from(integer("processes", 1)?).map_err(|_| too_large("processes"))?;
+    if pages == 0 {
+        return Err("workload pages must be at least 1".to_string());
+    }
+    if processes == 0 {
+        return Err("workload processes must be at least 1".to_string());
+    }
+    let workload = match kind {
+        "uniform" => Workload::Uniform { length, pages },
+        "loop" => Workload::Loop { length, pages },
+        "zipf" => Workload::Zipf {
+            length,
+            pages,
+            exponent: match table.get("exponent") {
+                Some(x) => as_float(x, "exponent")?,
+                None => 1.0,
+            },
+        },
+        _ => return Err(format!("unknown workload kind '{}'", kind)),
+    };
+    Ok(Source::Generate(match processes {
+        1 => workload,
+        _ => Workload::Interleave(vec![workload; processes]),
+    }))
+}
+
+fn parse_policy(table: &Table) -> Result<PolicySpec, String> {
+    check_keys(table, &["algorithm", "label"], "policy")?;
+    let algorithm = as_str(
+        table.get("algorithm").ok_or("policy needs an algorithm")?,
+        "algorithm",
+    )?;
+    policy::from_spec(algorithm, 0)?;
+    let label = match table.get("label") {
+        Some(x) => as_str(x, "label")?,
+        None => algorithm,
+    };
+    Ok(PolicySpec {
+        algorithm: algorithm.to_string(),
+        label: label.to_string(),
+    })
+}
+
+/// The values of one axis of an experiment, each parsed from an entry of the
+/// array under key, or only default when the key is left out
+fn axis<T>(
+    root: &Table,
+    key: &str,
+    default: T,
+    parse: impl Fn(&Value) -> Result<T, String>,
+) -> Result<Vec<T>, String> {
+    let values = match root.get(key) {
+        Some(x) => as_array(x, key)?
+            .iter()
+            .map(parse)
+            .collect::<Result<Vec<T>, String>>()?,
+        None => vec![default],
+    };
+    if values.is_empty() {
+        return Err(format!("{} must not be empty", key));
+    }
+    Ok(values)
+}
+
+/// An axis of spec strings such as "working_set:tau=500"
+fn specs<T>(
+    root: &Table,
+    key: &str,
+    default: T,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> Result<Vec<T>, String> {
+    axis(root, key, default, |x| parse(as_str(x, key)?))
+}
+
+/// Every table of an array of tables such as [[workload]]
+fn tables<'a>(root: &'a Table, key: &str) -> Result<Vec<&'a Table>, String> {
+    match root.get(key) {
+        None => Ok(vec![]),
+        Some(x) => as_array(x, key)?
+            .iter()
+            .map(|x| x.as_table().ok_or(format!("every {} must be a table", key)))
+            .collect(),
+    }
+}
+
+fn check_keys(table: &Table, allowed: &[&str], context: &str) -> Result<(), String> {
+    match table.keys().find(|x| !allowed.contains(&x.as_str())) {
+        Some(key) => Err(format!("unknown key '{}' in {}", key, context)),
+        None => Ok(()),
+    }
+}
+
+fn as_str<'a>(value: &'a Value, key: &str) -> Result<&'a str, String> {
+    value
+        .as_str()
+        .ok_or_else(|| format!("{} must be a string", key))
+}
+
+fn as_array<'a>(value: &'a Value, key: &str) -> Result<&'a Vec<Value>, String> {
+    value
+        .as_array()
+        .ok_or_else(|| format!("{} must be an array", key))
+}
+
+fn as_integer(value: &Value, key: &str) -> Result<i64, String> {
+    match value.as_integer() {
+        Some(x) if x >= 0 => Ok(x),
+        _ => Err(format!("{} must be a non-negative integer", key)),
+    }
+}
+
+fn too_large(key: &str) -> String {
+    format!("{} is too large", key)
+}
+
+fn as_float(value: &Value, key: &str) -> Result<f64, String> {
+    match value {
+        Value::Float(x) => Ok(*x),
+        Value::Integer(x) => Ok(*x as f64),
+        _ => Err(format!("{} must be a number", key)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::PageReport;
+
+    const CONFIG: &str = r#"
+name = "small"
+seeds = [1, 2]
+frames = [2, 3]
+formats = ["csv", "json"]
+
+[cost]
+hit = 1.0
+fault = 100
+
+[[workload]]
+kind = "uniform"
+length = 30
+pages = 6
+
+[[workload]]
+kind = "trace"
+path = "traces/a.txt"
+
+[[policy]]
+algorithm = "fifo"
+
+[[policy]]
+algorithm = "lru"
+label = "LRU"
+"#;
+
+    #[test]
+    fn parse_should_return_expected() {
+        let res = Experiment::parse(CONFIG, Path::new("suite")).unwrap();
+        assert_eq!(res.name, "small");
+        assert_eq!(res.seeds, vec![1, 2]);
+        assert_eq!(res.frames, vec![2, 3]);
+        assert_eq!(res.formats, vec![Format::Csv, Format::Json]);
+        assert_eq!(res.output, Path::new("suite/results/small"));
+        assert!(!res.check);
+        assert_eq!(
+            res.cost,
+            Some(CostModel {
+                hit: 1.0,
+                fault: 100.0,
+                eviction: 0.0
+            })
+        );
+        assert_eq!(
+            res.workloads,
+            vec![
+                Source::Generate(Workload::Uniform {
+                    length: 30,
+                    pages: 6
+                }),
+                Source::Trace(PathBuf::from("suite/traces/a.txt")),
+            ]
+        );
+        assert_eq!(res.policies[1].label, "LRU");
+    }
+
+    #[test]
+    fn workload_processes_should_interleave() {
+        let config = "frames = [2]\n[[workload]]\nkind = \"loop\"\nprocesses = 2\n[[policy]]\nalgorithm = \"fifo\"\n";
+        let res = Experiment::parse(config, Path::new("")).unwrap();
+        let workload = Workload::Loop {
+            length: 100,
+            pages: 20,
+        };
+        assert_eq!(
+            res.workloads,
+            vec![Source::Generate(Workload::Interleave(vec![workload; 2]))]
+        );
+        let bad = config.replace("processes = 2", "processes = 0");
+        assert!(Experiment::parse(&bad, Path::new("")).is_err());
+    }
+
+    #[test]
+    fn every_axis_should_multiply_cells() {
+        // axis, its two values, the rest of the config, a broken value with
+        // its replacement, and how the second run differs from the first
+        type Differs = fn(&PageReport, &PageReport) -> bool;
+        type Axis = (
+            &'static str,
+            &'static str,
+            &'static str,
+            (&'static str, &'static str),
+            Differs,
+        );
+        let axes: [Axis; 9] = [
+            (
+                "scopes",
+                r#""global", "local:proportional""#,
+                "frames = [4]\n[[workload]]\nkind = \"zipf\"\nprocesses = 2\n[[policy]]\nalgorithm = \"lru\"\n",
+                ("local:proportional", "local:largest"),
+                |off, on| {
+                    let shares = on.processes().filter_map(|(_, x)| x.frames()).sum::<u32>();
+                    off.process(0).unwrap().frames().is_none() && shares == 4
+                },
+            ),
+            (
+                "load_controls",
+                r#""off", "working_set:window=10,tau=16""#,
+                "frames = [5]\n[[workload]]\nkind = \"loop\"\npages = 4\nlength = 200\nprocesses = 2\n[[policy]]\nalgorithm = \"fifo\"\n",
+                ("tau=16", "tau=0"),
+                |off, on| off.suspensions() == 0 && on.suspensions() > 0,
+            ),
+            (
+                "tlbs",
+                r#""off", "entries=4,ways=4""#,
+                "frames = [3]\n[[workload]]\nkind = \"zipf\"\npages = 30\nlength = 200\n[[policy]]\nalgorithm = \"lru\"\n",
+                ("ways=4", "ways=3"),
+                |off, on| {
+                    let translation = on.translation().unwrap();
+                    off.translation().is_none()
+                        && translation.hits() + translation.misses() == 200
+                        && off.faults() == on.faults()
+                },
+            ),
+            (
+                "prefetches",
+                r#""off", "adaptive""#,
+                "frames = [4]\n[[workload]]\nkind = \"loop\"\npages = 30\nlength = 300\n[[policy]]\nalgorithm = \"lru\"\n",
+                ("adaptive", "ahead"),
+                |off, on| off.prefetches() == 0 && on.faults() < off.faults(),
+            ),
+            (
+                "swaps",
+                r#""off", "queue_depth=4""#,
+                "frames = [3]\n[[workload]]\nkind = \"uniform\"\npages = 10\nlength = 100\n[[policy]]\nalgorithm = \"clock\"\n",
+                ("=4", "=0"),
+                |off, on| off.swap().is_none() && on.swap().unwrap().reads() == on.faults(),
+            ),
+            (
+                "watermarks",
+                r#""off", "low=1,high=2""#,
+                "frames = [6]\n[[workload]]\nkind = \"uniform\"\npages = 20\nlength = 100\n[[policy]]\nalgorithm = \"fifo\"\n",
+                ("low=1", "low=3"),
+                |off, on| {
+                    off.background_reclaims() == 0
+                        && on.background_reclaims() > 0
+                        && on.direct_reclaims() == 0
+                },
+            ),
+            (
+                "tiers",
+                r#""off", "tiers=8@300""#,
+                "frames = [4]\n[[workload]]\nkind = \"zipf\"\npages = 20\nlength = 200\n[[policy]]\nalgorithm = \"clock\"\n",
+                ("8@300", "8"),
+                |off, on| {
+                    off.tiers().is_none()
+                        && on.tiers().unwrap().demotions() > 0
+                        && on.faults() < off.faults()
+                },
+            ),
+            (
+                "zswap",
+                r#""off", "size=16384,ratio=2""#,
+                "frames = [4]\n[[workload]]\nkind = \"zipf\"\npages = 20\nlength = 200\n[[policy]]\nalgorithm = \"clock\"\n",
+                ("ratio=2", "ratio=x"),
+                |off, on| {
+                    off.pool().is_none() && on.pool().unwrap().hits() > 0 && on.faults() == off.faults()
+                },
+            ),
+            (
+                "huge",
+                r#""off", "span=4,promote=2,window=50""#,
+                "frames = [16]\ntlbs = [\"on\"]\n[[workload]]\nkind = \"zipf\"\npages = 20\nlength = 400\n[[policy]]\nalgorithm = \"clock\"\n",
+                ("span=4", "span=3"),
+                |off, on| off.huge().is_none() && on.huge().unwrap().collapses() > 0,
+            ),
+        ];
+        for (key, values, rest, (from, to), differs) in axes.iter() {
+            let config = format!("{} = [{}]\n{}", key, values, rest);
+            let res = Experiment::parse(&config, Path::new("")).unwrap();
+            let results = res.run(1, &sweep::quiet).unwrap();
+            assert_eq!(results.len(), 2, "{}", key);
+            assert!(differs(&results[0].report, &results[1].report), "{}", key);
+            let bad = config.replace(from, to);
+            assert!(Experiment::parse(&bad, Path::new("")).is_err(), "{}", key);
+            let empty = format!("{} = []\n{}", key, rest);
+            let res = Experiment::parse(&empty, Path::new(""));
+            assert_eq!(res.err(), Some(format!("{} must not be empty", key)));
+        }
+    }
+
+    #[test]
+    fn cells_should_be_cartesian_product() {
+        let res = Experiment::parse(CONFIG, Path::new("")).unwrap();
+        let cells = res.cells();
+        assert_eq!(cells.len(), 2 * 2 * 2 * 2);
+        assert_eq!(
+            (
+                cells[0].settings.seed,
+                cells[0].policy.label.as_str(),
+                cells[0].settings.frames
+            ),
+            (1, "fifo", 2)
+        );
+        assert_eq!(
+            (
+                cells[7].settings.seed,
+                cells[7].policy.label.as_str(),
+                cells[7].settings.frames
+            ),
+            (2, "LRU", 3)
+        );
+    }
+
+    #[test]
+    fn check_should_apply_to_every_cell() {
+        let config = format!("check = true\n{}", CONFIG);
+        let res = Experiment::parse(&config, Path::new("")).unwrap();
+        assert!(res.cells().iter().all(|x| x.settings.check));
+        let bad = config.replace("check = true", "check = 1");
+        let res = Experiment::parse(&bad, Path::new(""));
+        assert_eq!(res.err(), Some("check must be a boolean".to_string()));
+    }
+
+    #[test]
+    fn parse_should_reject_bad_config() {
+        let base = Path::new("");
+        assert!(Experiment::parse("frames = [3]", base).is_err());
+        assert!(Experiment::parse(&CONFIG.replace("fifo", "belady"), base).is_err());
+        assert!(Experiment::parse(&CONFIG.replace("seeds", "seed"), base).is_err());
+        assert!(Experiment::parse(&CONFIG.replace("\"uniform\"", "\"normal\""), base).is_err());
+        assert!(Experiment::parse("frames = = 3", base).is_err());
+        let res = Experiment::parse(&CONFIG.replace("pages = 6", "pages = 4294967296"), base);
+        assert_eq!(res.err(), Some("pages is too large".to_string()));
+    }
+
+    #[test]
+    fn run_and_bundle_should_record_config() {
+        let dir = std::env::temp_dir().join(format!("page_replacement_{}", std::process::id()));
+        let config = CONFIG.replace(
+            "[[workload]]\nkind = \"trace\"\npath = \"traces/a.txt\"\n",
+            "",
+        );
+        let experiment = Experiment {
+            output: dir.clone(),
+            ..Experiment::parse(&config, Path::new("")).unwrap()
+        };
+        let results = experiment.run(2, &sweep::quiet).unwrap();
+        assert_eq!(results.len(), 8);
+        assert!(results.iter().all(|x| x.report.references() == 30));
+        assert!(results.iter().all(|x| x.cost.is_some()));
+
+        experiment.write_bundle(&config, &results).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("experiment.toml")).unwrap(),
+            config
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("results.csv"))
+                .unwrap()
+                .lines()
+                .count(),
+            9
+        );
+        assert!(dir.join("results.json").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,345 @@
+use crate::{PageId, Time};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+/// Huge pages next to base pages, as transparent huge pages and khugepaged
+/// make them
+///
+/// Every window references the scan looks at the aligned regions of span
+/// base pages referenced since the last scan, and collapses the ones with
+/// at least promote pages referenced into a huge page. Collapsing fills the
+/// rest of the region with pages no reference asked for, the memory a huge
+/// page wastes until they are used. A huge page is one page to the policy
+/// and one entry to the TLB while it takes span frames, when the policy
+/// picks it under memory pressure it is split and only its coldest page is
+/// evicted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HugePages {
+    /// Base pages per huge page, a power of two
+    pub span: u32,
+    pub promote: u32,
+    pub window: Time,
+    /// Bytes per base page
+    pub page_size: u64,
+}
+
+impl Default for HugePages {
+    /// 2 MiB huge pages of 4 KiB base pages, collapsed when half of them
+    /// were referenced in the last 1000 references
+    fn default() -> HugePages {
+        HugePages {
+            span: 512,
+            promote: 256,
+            window: 1000,
+            page_size: 4096,
+        }
+    }
+}
+
+impl HugePages {
+    pub fn validate(self) -> Result<HugePages, String> {
+        if self.span < 2 || !self.span.is_power_of_two() {
+            return Err(format!(
+                "huge span must be a power of two of at least 2, got {}",
+                self.span
+            ));
+        }
+        if self.promote == 0 || self.promote > self.span {
+            return Err(format!(
+                "huge needs 1 <= promote <= span, got promote={} span={}",
+                self.promote, self.span
+            ));
+        }
+        if self.window == 0 || self.page_size == 0 {
+            return Err("huge window and page_size must be at least 1".to_string());
+        }
+        Ok(self)
+    }
+
+    /// First page of the region page is in
+    pub fn head(&self, page: PageId) -> PageId {
+        PageId::new(page.process, page.number & !(self.span - 1))
+    }
+
+    /// Every page of the region starting at head, cut short at the last
+    /// page number when head is too close to it for a whole region
+    pub fn members(&self, head: PageId) -> impl Iterator<Item = PageId> {
+        let end = (head.number as u64 + self.span as u64).min(u32::MAX as u64 + 1);
+        (head.number as u64..end).map(move |x| PageId::new(head.process, x as u32))
+    }
+}
+
+impl fmt::Display for HugePages {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "huge(span={},promote={},window={},page_size={})",
+            self.span, self.promote, self.window, self.page_size
+        )
+    }
+}
+
+/// Comma separated span=N, promote=N, window=N and page_size=N, missing ones
+/// keep their defaults. A span without promote collapses at half of it
+impl FromStr for HugePages {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<HugePages, String> {
+        let mut huge = HugePages::default();
+        let mut promote = None;
+        for part in s.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("huge option '{}' needs a value", part))?;
+            let expected = || format!("huge {} expects a number, got '{}'", key, value);
+            match key {
+                "span" => huge.span = value.parse().map_err(|_| expected())?,
+                "promote" => promote = Some(value.parse().map_err(|_| expected())?),
+                "window" => huge.window = value.parse().map_err(|_| expected())?,
+                "page_size" => huge.page_size = value.parse().map_err(|_| expected())?,
+                _ => return Err(format!("unknown huge option '{}'", key)),
+            }
+        }
+        huge.promote = promote.unwrap_or(huge.span / 2);
+        huge.validate()
+    }
+}
+
+/// 'off', 'on' for the default huge pages, or their options as FromStr
+/// reads them
+pub fn parse_spec(spec: &str) -> Result<Option<HugePages>, String> {
+    match spec.trim() {
+        "off" => Ok(None),
+        "on" => Ok(Some(HugePages::default())),
+        options => options.parse().map(Some),
+    }
+}
+
+/// Collapses, splits and the memory huge pages wasted over a run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HugeReport {
+    pub(crate) huge: HugePages,
+    pub(crate) collapses: u64,
+    pub(crate) splits: u64,
+    /// Unused pages of huge pages summed over every reference
+    pub(crate) wasted: u64,
+    pub(crate) peak: u64,
+    pub(crate) references: u64,
+}
+
+impl HugeReport {
+    /// Regions collapsed into a huge page
+    pub fn collapses(&self) -> u64 {
+        self.collapses
+    }
+
+    /// Huge pages split back into base pages to evict one
+    pub fn splits(&self) -> u64 {
+        self.splits
+    }
+
+    /// Bytes of huge pages no reference has used, on average over the run
+    pub fn mean_wasted_bytes(&self) -> f64 {
+        if self.references == 0 {
+            return 0.0;
+        }
+        self.wasted as f64 * self.huge.page_size as f64 / self.references as f64
+    }
+
+    /// Most bytes of huge pages no reference had used at once
+    pub fn peak_wasted_bytes(&self) -> u64 {
+        self.peak * self.huge.page_size
+    }
+}
+
+/// The huge pages of a run and the references the next scan looks at
+pub struct Huge {
+    huge: HugePages,
+    /// First page of every huge page
+    heads: HashSet<PageId>,
+    /// Pages collapsing filled in that no reference has used since
+    untouched: HashSet<PageId>,
+    /// Pages referenced since the last scan, by region
+    referenced: HashMap<PageId, HashSet<u32>>,
+    collapses: u64,
+    splits: u64,
+    wasted: u64,
+    peak: u64,
+    references: u64,
+}
+
+impl Huge {
+    pub fn new(huge: HugePages) -> Huge {
+        Huge {
+            huge,
+            heads: HashSet::new(),
+            untouched: HashSet::new(),
+            referenced: HashMap::new(),
+            collapses: 0,
+            splits: 0,
+            wasted: 0,
+            peak: 0,
+            references: 0,
+        }
+    }
+
+    pub fn settings(&self) -> HugePages {
+        self.huge
+    }
+
+    /// First page of the huge page holding page, None when it is a base page
+    pub fn huge_head(&self, page: PageId) -> Option<PageId> {
+        let head = self.huge.head(page);
+        self.heads.get(&head).copied()
+    }
+
+    pub fn is_head(&self, page: PageId) -> bool {
+        self.heads.contains(&page)
+    }
+
+    /// First pages of every huge page, in order
+    pub fn heads(&self) -> Vec<PageId> {
+        let mut heads = self.heads.iter().copied().collect::<Vec<PageId>>();
+        heads.sort_unstable();
+        heads
+    }
+
+    /// page was referenced at time now. True when collapsing filled it in
+    /// and this is its first use. Once a window of references has passed,
+    /// returns the regions to collapse, in order
+    pub fn reference(&mut self, page: PageId, now: Time) -> (bool, Vec<PageId>) {
+        let first_use = self.untouched.remove(&page);
+        self.references += 1;
+        self.wasted += self.untouched.len() as u64;
+        self.peak = self.peak.max(self.untouched.len() as u64);
+        let head = self.huge.head(page);
+        if !self.heads.contains(&head) {
+            self.referenced.entry(head).or_default().insert(page.number);
+        }
+        if !(now + 1).is_multiple_of(self.huge.window) {
+            return (first_use, vec![]);
+        }
+        let promote = self.huge.promote;
+        let mut hot = self
+            .referenced
+            .drain()
+            .filter(|(_, pages)| pages.len() as u32 >= promote)
+            .map(|(head, _)| head)
+            .collect::<Vec<PageId>>();
+        hot.sort_unstable();
+        (first_use, hot)
+    }
+
+    /// The region at head became a huge page, collapsing filled in the pages
+    /// of filled
+    pub fn collapse(&mut self, head: PageId, filled: &[PageId]) {
+        self.heads.insert(head);
+        self.untouched.extend(filled.iter().copied());
+        self.collapses += 1;
+    }
+
+    /// Turn the huge page at head back into base pages, and return them
+    /// with the ones no reference used
+    pub fn split(&mut self, head: PageId) -> (Vec<PageId>, Vec<PageId>) {
+        if !self.heads.remove(&head) {
+            return (vec![], vec![]);
+        }
+        self.splits += 1;
+        let members = self.huge.members(head).collect::<Vec<PageId>>();
+        let unused = members
+            .iter()
+            .filter(|x| self.untouched.remove(x))
+            .copied()
+            .collect();
+        (members, unused)
+    }
+
+    pub fn report(&self) -> HugeReport {
+        HugeReport {
+            huge: self.huge,
+            collapses: self.collapses,
+            splits: self.splits,
+            wasted: self.wasted,
+            peak: self.peak,
+            references: self.references,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn huge(spec: &str) -> Huge {
+        Huge::new(spec.parse().unwrap())
+    }
+
+    #[test]
+    fn scans_should_pick_regions_with_enough_referenced_pages() {
+        let mut huge = huge("span=4,promote=2,window=4");
+        assert_eq!(huge.reference(1.into(), 0), (false, vec![]));
+        huge.reference(5.into(), 1);
+        huge.reference(2.into(), 2);
+        // 1 and 2 of the region at 0, only 5 of the one at 4
+        assert_eq!(huge.reference(1.into(), 3), (false, vec![0.into()]));
+        // the next window starts over
+        huge.reference(6.into(), 4);
+        assert_eq!(huge.reference(PageId::new(1, 6), 7).1, vec![]);
+    }
+
+    #[test]
+    fn filled_pages_should_count_as_wasted_until_used() {
+        let mut huge = huge("span=4,promote=2,window=100");
+        huge.collapse(4.into(), &[5.into(), 7.into()]);
+        assert_eq!(huge.huge_head(6.into()), Some(4.into()));
+        assert_eq!(huge.huge_head(3.into()), None);
+        assert!(huge.reference(5.into(), 0).0);
+        assert!(!huge.reference(5.into(), 1).0);
+        let (members, unused) = huge.split(4.into());
+        assert_eq!(members, vec![4.into(), 5.into(), 6.into(), 7.into()]);
+        assert_eq!(unused, vec![7.into()]);
+        assert!(huge.split(4.into()).0.is_empty());
+        huge.reference(0.into(), 2);
+        let report = huge.report();
+        assert_eq!((report.collapses(), report.splits()), (1, 1));
+        // 7 was the only unused page for the first two references
+        assert_eq!(report.mean_wasted_bytes(), 2.0 * 4096.0 / 3.0);
+        assert_eq!(report.peak_wasted_bytes(), 4096);
+    }
+
+    #[test]
+    fn members_should_stop_at_the_last_page_number() {
+        let settings = "span=4".parse::<HugePages>().unwrap();
+        let head = settings.head(PageId::new(1, u32::MAX));
+        assert_eq!(head, PageId::new(1, u32::MAX - 3));
+        assert_eq!(settings.members(head).count(), 4);
+        let members = settings.members(PageId::new(1, u32::MAX - 1));
+        assert_eq!(
+            members.collect::<Vec<PageId>>(),
+            vec![PageId::new(1, u32::MAX - 1), PageId::new(1, u32::MAX)]
+        );
+        let mut huge = Huge::new(settings);
+        huge.collapse(head, &[]);
+        assert_eq!(huge.split(head).0.len(), 4);
+    }
+
+    #[test]
+    fn parse_should_fill_defaults_and_reject_bad_settings() {
+        assert_eq!("".parse(), Ok(HugePages::default()));
+        let res = "span=8, window=50".parse::<HugePages>().unwrap();
+        assert_eq!(
+            res.to_string(),
+            "huge(span=8,promote=4,window=50,page_size=4096)"
+        );
+        assert_eq!(res.head(PageId::new(2, 13)), PageId::new(2, 8));
+        assert!("span=6".parse::<HugePages>().is_err());
+        assert!("span=1".parse::<HugePages>().is_err());
+        assert!("span=8,promote=9".parse::<HugePages>().is_err());
+        assert!("promote=0".parse::<HugePages>().is_err());
+        assert!("window=0".parse::<HugePages>().is_err());
+        assert!("order=9".parse::<HugePages>().is_err());
+        assert_eq!(parse_spec("off"), Ok(None));
+        assert_eq!(parse_spec("on"), Ok(Some(HugePages::default())));
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Free frame watermarks of a page-out daemon, as kswapd keeps them
+///
+/// When a reference leaves fewer than low frames of a partition free, the
+/// daemon wakes up and evicts by the partition's policy, at most batch pages
+/// after every reference, until high frames are free again. A fault then
+/// takes a free frame, and only reclaims one itself when the daemon fell
+/// behind
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Watermarks {
+    pub low: u32,
+    pub high: u32,
+    /// Pages the daemon evicts between two references
+    pub batch: u32,
+}
+
+impl Default for Watermarks {
+    fn default() -> Watermarks {
+        Watermarks {
+            low: 2,
+            high: 8,
+            batch: 4,
+        }
+    }
+}
+
+impl Watermarks {
+    pub fn new(low: u32, high: u32, batch: u32) -> Result<Watermarks, String> {
+        if low > high {
+            return Err(format!(
+                "watermarks need low <= high, got low={} high={}",
+                low, high
+            ));
+        }
+        if batch == 0 {
+            return Err("watermarks batch must be at least 1".to_string());
+        }
+        Ok(Watermarks { low, high, batch })
+    }
+
+    /// The watermarks of a partition of frames frames, which always keeps
+    /// one page resident
+    pub fn within(&self, frames: u32) -> (u32, u32) {
+        let high = self.high.min(frames.saturating_sub(1));
+        (self.low.min(high), high)
+    }
+}
+
+impl fmt::Display for Watermarks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "watermarks(low={},high={},batch={})",
+            self.low, self.high, self.batch
+        )
+    }
+}
+
+/// Comma separated low=N, high=N and batch=N, missing ones keep their
+/// defaults
+impl FromStr for Watermarks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Watermarks, String> {
+        let mut watermarks = Watermarks::default();
+        for part in s.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("watermarks option '{}' needs a value", part))?;
+            let number = value
+                .parse::<u32>()
+                .map_err(|_| format!("watermarks {} expects a number, got '{}'", key, value))?;
+            match key {
+                "low" => watermarks.low = number,
+                "high" => watermarks.high = number,
+                "batch" => watermarks.batch = number,
+                _ => return Err(format!("unknown watermarks option '{}'", key)),
+            }
+        }
+        Watermarks::new(watermarks.low, watermarks.high, watermarks.batch)
+    }
+}
+
+/// 'off', 'on' for the default watermarks, or their options as FromStr reads
+/// them
+pub fn parse_spec(spec: &str) -> Result<Option<Watermarks>, String> {
+    match spec.trim() {
+        "off" => Ok(None),
+        "on" => Ok(Some(Watermarks::default())),
+        options => options.parse().map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn within_should_leave_a_page_resident() {
+        let watermarks = Watermarks::new(2, 8, 1).unwrap();
+        assert_eq!(watermarks.within(20), (2, 8));
+        assert_eq!(watermarks.within(5), (2, 4));
+        assert_eq!(watermarks.within(2), (1, 1));
+        assert_eq!(watermarks.within(1), (0, 0));
+    }
+
+    #[test]
+    fn parse_should_fill_defaults_and_reject_bad_settings() {
+        assert_eq!("".parse(), Ok(Watermarks::default()));
+        let res = "low=1, high=3".parse::<Watermarks>().unwrap();
+        assert_eq!(res.to_string(), "watermarks(low=1,high=3,batch=4)");
+        assert!("low=4,high=3".parse::<Watermarks>().is_err());
+        assert!("batch=0".parse::<Watermarks>().is_err());
+        assert!("min=1".parse::<Watermarks>().is_err());
+        assert!("low=x".parse::<Watermarks>().is_err());
+        assert_eq!(parse_spec("off"), Ok(None));
+        assert_eq!(parse_spec("on"), Ok(Some(Watermarks::default())));
+    }
+}
@@ -1,118 +1,264 @@
 pub mod algorithms;
+pub mod check;
+pub mod cli;
+#[cfg(test)]
+mod differential;
+pub mod error;
+pub mod experiment;
+pub mod huge;
+pub mod kswapd;
+pub mod load;
+pub mod output;
+pub mod pff;
+pub mod policy;
+pub mod prefetch;
+pub mod report;
+pub mod scope;
+pub mod settings;
+pub mod simulation;
+pub mod swap;
+pub mod sweep;
+pub mod tier;
+pub mod tlb;
 pub mod utils;
+pub mod workload;
+pub mod zswap;
 
+pub use error::Error;
+pub use report::{CostModel, Histogram, PageReport, ProcessReport, Summary};
 use std::fmt;
+use std::str::FromStr;
 use utils::push;
 
+/// Identity of a process, every process numbers its pages from 0
+pub type Pid = u32;
+
+/// Identity of a page in a trace: the process it belongs to and its number
+/// in that process's address space
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PageId {
+    pub process: Pid,
+    pub number: u32,
+}
+
+impl PageId {
+    pub fn new(process: Pid, number: u32) -> PageId {
+        PageId { process, number }
+    }
+}
+
+/// Page number of process 0, all a single process trace needs
+impl From<u32> for PageId {
+    fn from(number: u32) -> PageId {
+        PageId::new(0, number)
+    }
+}
+
+/// Written the way traces spell it: 'number' for process 0, otherwise
+/// 'process:number'
+impl fmt::Display for PageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.process {
+            0 => write!(f, "{}", self.number),
+            process => write!(f, "{}:{}", process, self.number),
+        }
+    }
+}
+
+/// Parses the trace spelling, see Display
+impl FromStr for PageId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PageId, String> {
+        let number = |x: &str| {
+            x.parse::<u32>()
+                .map_err(|_| format!("'{}' is not a page number", s))
+        };
+        match s.split_once(':') {
+            Some((process, page)) => Ok(PageId::new(number(process)?, number(page)?)),
+            None => Ok(PageId::from(number(s)?)),
+        }
+    }
+}
+
+/// One entry of a trace: a page access, or a directive changing how a page
+/// is held in memory
+///
+/// Pinning references the page like an access and keeps it resident until
+/// it is unpinned. Unpinning is not a reference, it only lets the page be
+/// evicted again. Sharing is not a reference either, it maps page onto the
+/// frame of with until either of them is written
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Reference {
+    Access(PageId),
+    Write(PageId),
+    Pin(PageId),
+    Unpin(PageId),
+    Share { page: PageId, with: PageId },
+}
+
+impl Reference {
+    pub fn page(&self) -> PageId {
+        match self {
+            Reference::Access(page)
+            | Reference::Write(page)
+            | Reference::Pin(page)
+            | Reference::Unpin(page)
+            | Reference::Share { page, .. } => *page,
+        }
+    }
+}
+
+impl From<PageId> for Reference {
+    fn from(page: PageId) -> Reference {
+        Reference::Access(page)
+    }
+}
+
+/// Access to a page of process 0
+impl From<u32> for Reference {
+    fn from(number: u32) -> Reference {
+        Reference::Access(PageId::from(number))
+    }
+}
+
+/// Written the way traces spell it, see workload::TraceReader
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reference::Access(page) => write!(f, "{}", page),
+            Reference::Write(page) => write!(f, "write {}", page),
+            Reference::Pin(page) => write!(f, "pin {}", page),
+            Reference::Unpin(page) => write!(f, "unpin {}", page),
+            Reference::Share { page, with } => write!(f, "share {} {}", with, page),
+        }
+    }
+}
+
+/// Position of a reference in a trace, the n-th reference happens at time n
+pub type Time = u64;
+
 #[derive(Copy, Clone, Debug)]
 pub struct MemoryPage {
+    process: Pid,
     number: u32,
-    present: bool,
     referenced: bool,
     modified: bool,
+    /// Never picked as a victim while set
+    pinned: bool,
+    loaded_at: Time,
+    last_used: Time,
 }
 
 impl MemoryPage {
     fn new(number: u32) -> MemoryPage {
+        MemoryPage::of(PageId::from(number))
+    }
+
+    pub fn id(&self) -> PageId {
+        PageId::new(self.process, self.number)
+    }
+
+    fn of(page: PageId) -> MemoryPage {
         MemoryPage {
-            number,
-            present: false,
+            process: page.process,
+            number: page.number,
             referenced: false,
             modified: false,
+            pinned: false,
+            loaded_at: 0,
+            last_used: 0,
         }
     }
 
+    pub fn is_pinned(&self) -> bool {
+        self.pinned
+    }
+
+    /// Written since it was loaded or last written back
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    fn pinned(self, pinned: bool) -> MemoryPage {
+        MemoryPage { pinned, ..self }
+    }
+
     fn referenced(self) -> MemoryPage {
         MemoryPage {
-            number: self.number,
-            present: self.present,
             referenced: true,
-            modified: false,
+            ..self
         }
     }
 
+    #[cfg(test)]
     fn modified(self) -> MemoryPage {
         MemoryPage {
-            number: self.number,
-            present: self.present,
             referenced: false,
             modified: true,
+            ..self
         }
     }
 
     fn modified_and_referenced(self) -> MemoryPage {
         MemoryPage {
-            number: self.number,
-            present: self.present,
             referenced: true,
             modified: true,
+            ..self
         }
     }
 
-    fn clear(self) -> MemoryPage {
+    /// Page was written to the backing store while it stays resident
+    fn written_back(self) -> MemoryPage {
         MemoryPage {
-            number: self.number,
-            present: self.present,
-            referenced: false,
             modified: false,
+            ..self
         }
     }
-}
-
-impl PartialEq for MemoryPage {
-    fn eq(&self, rhs: &MemoryPage) -> bool {
-        self.number == rhs.number
-    }
-}
-
-#[derive(PartialEq, Debug)]
-pub struct PageReport {
-    hits: u32,
-    faults: u32,
-    removed: u32,
-}
 
-impl PageReport {
-    pub fn new() -> PageReport {
-        PageReport {
-            faults: 0,
-            hits: 0,
-            removed: 0,
+    fn unreferenced(self) -> MemoryPage {
+        MemoryPage {
+            referenced: false,
+            ..self
         }
     }
-    fn fault(self) -> PageReport {
-        PageReport {
-            hits: self.hits,
-            faults: self.faults + 1,
-            removed: self.removed,
+
+    fn clear(self) -> MemoryPage {
+        MemoryPage {
+            referenced: false,
+            modified: false,
+            ..self
         }
     }
-    fn hit(self) -> PageReport {
-        PageReport {
-            hits: self.hits + 1,
-            faults: self.faults,
-            removed: self.removed,
+
+    /// Page was brought into memory at time
+    fn loaded_at(self, time: Time) -> MemoryPage {
+        MemoryPage {
+            loaded_at: time,
+            last_used: time,
+            ..self
         }
     }
-    fn removed(self) -> PageReport {
-        PageReport {
-            hits: self.hits,
-            faults: self.faults,
-            removed: self.removed + 1,
+
+    /// Page was referenced at time
+    fn used_at(self, time: Time) -> MemoryPage {
+        MemoryPage {
+            last_used: time,
+            ..self
         }
     }
 }
 
-impl fmt::Display for PageReport {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "| hits: {} | faults: {} | removed: {} |",
-            self.hits, self.faults, self.removed
-        )
+impl PartialEq for MemoryPage {
+    fn eq(&self, rhs: &MemoryPage) -> bool {
+        self.id() == rhs.id()
     }
 }
 
+/// Signature every replacement algorithm in `algorithms` shares
+/// Fails when there is nothing to evict or frames and history disagree
+pub type Algorithm = fn(Vec<MemoryPage>, MemoryPage, Vec<u32>) -> Result<Vec<MemoryPage>, Error>;
+
 pub fn load_page<F>(
     handle_loading: F,
     page_frames: Vec<MemoryPage>,
@@ -120,39 +266,273 @@ pub fn load_page<F>(
     page_hit: u32,
     report: PageReport,
     past_pages: Vec<u32>,
-) -> (Vec<MemoryPage>, PageReport)
+) -> Result<(Vec<MemoryPage>, PageReport), Error>
 where
-    F: Fn(Vec<MemoryPage>, MemoryPage, Vec<u32>) -> Vec<MemoryPage>,
+    F: Fn(Vec<MemoryPage>, MemoryPage, Vec<u32>) -> Result<Vec<MemoryPage>, Error>,
 {
-    let page = MemoryPage::new(page_hit);
+    if frame_size == 0 {
+        return Err(Error::NoFrames);
+    }
+    let now = past_pages.len() as Time;
+    let page = MemoryPage::new(page_hit).loaded_at(now);
     // Page is in Memory
     if page_frames.contains(&page) {
         let page_frames = page_frames
             .iter()
             .map(|x| {
-                if x.number == page_hit {
-                    return x.referenced();
+                if *x == page {
+                    return x.referenced().used_at(now);
                 }
                 *x
             })
             .collect();
-        return (page_frames, report.hit());
+        return Ok((page_frames, report.hit(page.id())));
     }
 
+    let first_touch = !past_pages.contains(&page_hit);
+    let report = report.fault(page.id(), now, first_touch);
+    let report = if first_touch {
+        report
+    } else {
+        report.swapped_in()
+    };
+
     // Room to load page in memory
     if (page_frames.len() as u32) < frame_size {
-        return (push(page_frames, page), report.fault());
+        return Ok((push(page_frames, page), report));
     }
 
     // No Room, replace a page
-    let page_frames = handle_loading(page_frames, page, past_pages);
-    (page_frames, report.fault().removed())
+    let before = page_frames.clone();
+    let page_frames = handle_loading(page_frames, page, past_pages)?;
+    if !page_frames.contains(&page) {
+        return Err(Error::InconsistentState(format!(
+            "page {} is not in memory after loading it",
+            page_hit
+        )));
+    }
+    if page_frames.len() as u32 > frame_size {
+        return Err(Error::InconsistentState(format!(
+            "{} pages in {} frames",
+            page_frames.len(),
+            frame_size
+        )));
+    }
+    let report = before
+        .iter()
+        .filter(|x| !page_frames.contains(x))
+        .fold(report, |report, victim| report.removed(victim, now));
+    Ok((page_frames, report))
+}
+
+/// Run every page in page_hit_order through algorithm with frame_size frames
+/// The algorithms rebuild the frames and history on every reference, making
+/// this quadratic in the trace length; see simulation::run for long traces
+pub fn simulate<F>(
+    handle_loading: F,
+    page_hit_order: &[u32],
+    frame_size: u32,
+) -> Result<PageReport, Error>
+where
+    F: Fn(Vec<MemoryPage>, MemoryPage, Vec<u32>) -> Result<Vec<MemoryPage>, Error>,
+{
+    simulate_with(handle_loading, page_hit_order, frame_size, false, None)
+}
+
+/// Like simulate, but checks the frames after every reference and fails on
+/// the first step that breaks an invariant, see check::step
+pub fn simulate_checked<F>(
+    handle_loading: F,
+    page_hit_order: &[u32],
+    frame_size: u32,
+) -> Result<PageReport, Error>
+where
+    F: Fn(Vec<MemoryPage>, MemoryPage, Vec<u32>) -> Result<Vec<MemoryPage>, Error>,
+{
+    simulate_with(handle_loading, page_hit_order, frame_size, true, None)
+}
+
+/// Like simulate, for traces that write, pin and unpin pages, see Reference
+/// The algorithms know a single process, only page numbers are used, and
+/// sharing pages fails
+pub fn simulate_references<F>(
+    handle_loading: F,
+    references: &[Reference],
+    frame_size: u32,
+) -> Result<PageReport, Error>
+where
+    F: Fn(Vec<MemoryPage>, MemoryPage, Vec<u32>) -> Result<Vec<MemoryPage>, Error>,
+{
+    simulate_with(handle_loading, references, frame_size, false, None)
+}
+
+/// Like simulate_references, checking every step as simulate_checked does
+/// and that no pinned page was evicted
+pub fn simulate_references_checked<F>(
+    handle_loading: F,
+    references: &[Reference],
+    frame_size: u32,
+) -> Result<PageReport, Error>
+where
+    F: Fn(Vec<MemoryPage>, MemoryPage, Vec<u32>) -> Result<Vec<MemoryPage>, Error>,
+{
+    simulate_with(handle_loading, references, frame_size, true, None)
+}
+
+/// Set or clear the pin of page_number, nothing happens when it is not
+/// resident
+pub fn pin_page(page_frames: Vec<MemoryPage>, page_number: u32, pinned: bool) -> Vec<MemoryPage> {
+    page_frames
+        .into_iter()
+        .map(|x| {
+            if x.number == page_number {
+                return x.pinned(pinned);
+            }
+            x
+        })
+        .collect()
+}
+
+/// Like simulate_references, with every reference looked up in the TLB of
+/// mmu, and the page table walked on a miss, before load_page sees it
+pub fn simulate_translated<F>(
+    handle_loading: F,
+    references: &[Reference],
+    frame_size: u32,
+    mmu: tlb::Mmu,
+) -> Result<PageReport, Error>
+where
+    F: Fn(Vec<MemoryPage>, MemoryPage, Vec<u32>) -> Result<Vec<MemoryPage>, Error>,
+{
+    simulate_with(handle_loading, references, frame_size, false, Some(mmu))
+}
+
+fn simulate_with<F, R>(
+    handle_loading: F,
+    references: &[R],
+    frame_size: u32,
+    checked: bool,
+    mut mmu: Option<tlb::Mmu>,
+) -> Result<PageReport, Error>
+where
+    F: Fn(Vec<MemoryPage>, MemoryPage, Vec<u32>) -> Result<Vec<MemoryPage>, Error>,
+    R: Copy + Into<Reference>,
+{
+    let ids = |frame: &[MemoryPage]| frame.iter().map(|x| x.id()).collect::<Vec<PageId>>();
+    let pins = |frame: &[MemoryPage]| {
+        frame
+            .iter()
+            .filter(|x| x.pinned)
+            .map(|x| x.id())
+            .collect::<Vec<PageId>>()
+    };
+    let (page_frames, report, past) = references.iter().try_fold(
+        (vec![], PageReport::new(), vec![]),
+        |(frame, report, past), x| {
+            let reference = (*x).into();
+            let number = reference.page().number;
+            match reference {
+                Reference::Unpin(_) => return Ok((pin_page(frame, number, false), report, past)),
+                Reference::Share { .. } => {
+                    return Err(Error::Trace(format!(
+                        "'{}' needs a simulation, the algorithms know a single process",
+                        reference
+                    )))
+                }
+                _ => {}
+            }
+            if let Some(mmu) = &mut mmu {
+                mmu.translate(PageId::from(number))?;
+            }
+            let step = past.len() as Time;
+            let before = if checked || mmu.is_some() {
+                ids(&frame)
+            } else {
+                vec![]
+            };
+            let pinned = if checked { pins(&frame) } else { vec![] };
+            let (frame, report) = load_page(
+                &handle_loading,
+                frame,
+                frame_size,
+                number,
+                report,
+                past.clone(),
+            )
+            .map_err(|err| if checked { err.at(step) } else { err })?;
+            let frame = match reference {
+                Reference::Pin(_) => pin_page(frame, number, true),
+                Reference::Write(_) => frame
+                    .into_iter()
+                    .map(|x| {
+                        if x.number == number {
+                            return x.modified_and_referenced();
+                        }
+                        x
+                    })
+                    .collect(),
+                _ => frame,
+            };
+            let after = if checked || mmu.is_some() {
+                ids(&frame)
+            } else {
+                vec![]
+            };
+            if let Some(mmu) = &mut mmu {
+                for evicted in before.iter().filter(|x| !after.contains(x)) {
+                    mmu.invalidate(*evicted);
+                }
+            }
+            if checked {
+                check::step(step, PageId::from(number), frame_size, &before, &after)?;
+                check::pinned(step, &pinned, &after)?;
+            }
+            Ok((frame, report, push(past, number)))
+        },
+    )?;
+    let report = report.finish(&page_frames, past.len() as Time);
+    Ok(match mmu {
+        Some(mmu) => report.with_translation(mmu.report()),
+        None => report,
+    })
+}
+
+/// Page numbers of process 0, for writing traces in tests
+#[cfg(test)]
+pub(crate) fn pages(numbers: &[u32]) -> Vec<PageId> {
+    numbers.iter().map(|x| PageId::from(*x)).collect()
+}
+
+/// Accesses to pages of process 0, for writing traces in tests
+#[cfg(test)]
+pub(crate) fn accesses(numbers: &[u32]) -> Vec<Reference> {
+    numbers.iter().map(|x| Reference::from(*x)).collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::{algorithms, *};
 
+    fn counters(report: &PageReport) -> (u64, u64, u64) {
+        (report.hits, report.faults, report.removed)
+    }
+
+    #[test]
+    fn simulate_references_should_reject_shared_pages() {
+        let share = Reference::Share {
+            page: PageId::new(1, 0),
+            with: 0.into(),
+        };
+        let res = simulate_references(algorithms::fifo, &[0.into(), share], 2);
+        assert_eq!(
+            res,
+            Err(Error::Trace(
+                "'share 0 1:0' needs a simulation, the algorithms know a single process"
+                    .to_string()
+            ))
+        );
+    }
+
     #[test]
     fn page_report_fifo_should_return_expected() {
         let page_hit = 0;
@@ -162,6 +542,7 @@ mod tests {
             hits: 0,
             faults: 1,
             removed: 0,
+            ..PageReport::new()
         };
         let report = PageReport::new();
         let (_, res) = load_page(
@@ -171,8 +552,9 @@ mod tests {
             page_hit,
             report,
             vec![],
-        );
-        assert_eq!(res, expected_page_report);
+        )
+        .unwrap();
+        assert_eq!(counters(&res), counters(&expected_page_report));
     }
 
     #[test]
@@ -184,11 +566,13 @@ mod tests {
             hits: 5,
             faults: 2,
             removed: 0,
+            ..PageReport::new()
         };
         let report = PageReport {
             hits: 4,
             faults: 2,
             removed: 0,
+            ..PageReport::new()
         };
         let (_, res) = load_page(
             algorithms::fifo,
@@ -197,8 +581,9 @@ mod tests {
             page_hit,
             report,
             vec![],
-        );
-        assert_eq!(res, expected_page_report);
+        )
+        .unwrap();
+        assert_eq!(counters(&res), counters(&expected_page_report));
     }
 
     #[test]
@@ -211,11 +596,13 @@ mod tests {
             hits: 1,
             faults: 5,
             removed: 2,
+            ..PageReport::new()
         };
         let expected_page_report = PageReport {
             hits: 1,
             faults: 6,
             removed: 3,
+            ..PageReport::new()
         };
         let (_, res) = load_page(
             algorithms::fifo,
@@ -224,8 +611,9 @@ mod tests {
             page_hit,
             report,
             past_pages,
-        );
-        assert_eq!(res, expected_page_report);
+        )
+        .unwrap();
+        assert_eq!(counters(&res), counters(&expected_page_report));
     }
 
     #[test]
@@ -238,11 +626,13 @@ mod tests {
             hits: 2,
             faults: 4,
             removed: 1,
+            ..PageReport::new()
         };
         let expected_page_report = PageReport {
             hits: 2,
             faults: 5,
             removed: 2,
+            ..PageReport::new()
         };
         let (_, res) = load_page(
             algorithms::lru,
@@ -251,8 +641,9 @@ mod tests {
             page_hit,
             report,
             past_pages,
-        );
-        assert_eq!(res, expected_page_report);
+        )
+        .unwrap();
+        assert_eq!(counters(&res), counters(&expected_page_report));
     }
 
     #[test]
@@ -270,11 +661,13 @@ mod tests {
             hits: 7,
             faults: 6,
             removed: 2,
+            ..PageReport::new()
         };
         let expected_page_report = PageReport {
             hits: 8,
             faults: 6,
             removed: 2,
+            ..PageReport::new()
         };
         let (_, res) = load_page(
             algorithms::lru,
@@ -283,8 +676,9 @@ mod tests {
             page_hit,
             report,
             past_pages,
-        );
-        assert_eq!(res, expected_page_report);
+        )
+        .unwrap();
+        assert_eq!(counters(&res), counters(&expected_page_report));
     }
 
     #[test]
@@ -301,11 +695,13 @@ mod tests {
             hits: 5,
             faults: 5,
             removed: 2,
+            ..PageReport::new()
         };
         let expected_page_report = PageReport {
             hits: 5,
             faults: 6,
             removed: 3,
+            ..PageReport::new()
         };
         let (_, res) = load_page(
             algorithms::second_chance,
@@ -314,7 +710,96 @@ mod tests {
             page_hit,
             report,
             past_pages,
+        )
+        .unwrap();
+        assert_eq!(counters(&res), counters(&expected_page_report));
+    }
+
+    #[test]
+    fn simulate_fifo_should_track_victims() {
+        let res = simulate(algorithms::fifo, &[0, 1, 2, 0, 3, 0], 3).unwrap();
+        assert_eq!(counters(&res), (1, 5, 2));
+        assert_eq!(res.compulsory_faults(), 4);
+        assert_eq!(res.capacity_faults(), 1);
+        assert_eq!(
+            res.victim_age_summary().map(|x| (x.min, x.max)),
+            Some((1, 4))
+        );
+        assert_eq!(res.premature_evictions(), 1);
+        assert_eq!(res.resident_time(0.into()), 4 + 1);
+    }
+
+    #[test]
+    fn simulate_clock_should_load_page_after_sweep() {
+        let res = simulate(algorithms::clock, &[0, 1, 0, 1, 2], 2).unwrap();
+        assert_eq!(counters(&res), (2, 3, 1));
+    }
+
+    #[test]
+    fn zero_frame_size_should_be_rejected() {
+        let res = load_page(algorithms::fifo, vec![], 0, 1, PageReport::new(), vec![]);
+        assert_eq!(res.err(), Some(Error::NoFrames));
+        assert_eq!(simulate(algorithms::lru, &[0, 1], 0), Err(Error::NoFrames));
+    }
+
+    #[test]
+    fn algorithm_dropping_page_should_be_inconsistent() {
+        let frame = vec![MemoryPage::new(0), MemoryPage::new(1)];
+        let res = load_page(
+            |frames, _, _| Ok(frames),
+            frame,
+            2,
+            5,
+            PageReport::new(),
+            vec![0, 1],
+        );
+        assert_eq!(
+            res.err(),
+            Some(Error::InconsistentState(
+                "page 5 is not in memory after loading it".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn simulate_checked_should_pass_reference_algorithms() {
+        let trace = [0, 1, 2, 0, 3, 0, 4, 2, 3, 0, 3, 2, 1, 2, 0];
+        let algorithms: [Algorithm; 3] =
+            [algorithms::fifo, algorithms::lru, algorithms::second_chance];
+        for algorithm in algorithms.iter() {
+            for frame_size in 1..5 {
+                assert_eq!(
+                    simulate_checked(algorithm, &trace, frame_size),
+                    simulate(algorithm, &trace, frame_size)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn simulate_checked_should_catch_duplicate_frames() {
+        // Loads the page but keeps the oldest around twice instead of evicting it
+        let duplicate = |frames: Vec<MemoryPage>, page: MemoryPage, _| {
+            let oldest = frames[0];
+            Ok(vec![oldest, oldest, page][..frames.len()].to_vec())
+        };
+        let res = simulate_checked(duplicate, &[0, 1, 2, 3], 3);
+        assert_eq!(
+            res.err().map(|x| x.to_string()),
+            Some("step 3: page 0 is resident 2 times".to_string())
+        );
+        assert!(simulate(duplicate, &[0, 1, 2, 3], 3).is_ok());
+    }
+
+    #[test]
+    fn simulate_checked_should_number_inconsistent_steps() {
+        let res = simulate_checked(|frames, _, _| Ok(frames), &[0, 1, 2], 2);
+        assert_eq!(
+            res,
+            Err(Error::Invariant {
+                step: 2,
+                message: "page 2 is not in memory after loading it".to_string()
+            })
         );
-        assert_eq!(res, expected_page_report);
     }
 }
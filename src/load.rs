@@ -0,0 +1,438 @@
+use crate::error::Error;
+use crate::simulation::{Outcome, Simulation};
+use crate::{PageId, PageReport, Pid, Reference};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+
+/// Whether and how to detect thrashing and suspend processes to end it
+///
+/// Every window references the controller looks at memory. When it is
+/// thrashing and more than one process runs, the process with the most
+/// resident pages is swapped out and its references wait. When pressure is
+/// low again the process suspended longest resumes and catches up on its
+/// references before the trace goes on
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LoadControl {
+    #[default]
+    Off,
+    /// Thrashing is a fault rate over the window above suspend, and a rate
+    /// below resume lets a process back in
+    FaultRate {
+        window: u64,
+        suspend: f64,
+        resume: f64,
+    },
+    /// Thrashing is the working sets of the running processes, the pages
+    /// each referenced in the last tau references, adding up to more than
+    /// the frames. A process comes back once the working set it had when
+    /// suspended fits next to the running ones
+    WorkingSet { window: u64, tau: u64 },
+}
+
+impl LoadControl {
+    /// Run references through simulation, buffering the references of
+    /// suspended processes until they resume
+    pub fn run<I, R>(&self, simulation: Simulation, references: I) -> Result<PageReport, Error>
+    where
+        I: IntoIterator<Item = Result<R, Error>>,
+        R: Into<Reference>,
+    {
+        if *self == LoadControl::Off {
+            return simulation.run(references);
+        }
+        let mut controller = Controller::new(*self, simulation);
+        for reference in references {
+            controller.feed(reference?.into())?;
+            controller.catch_up()?;
+        }
+        while let Some((pid, _)) = controller.suspended.pop_front() {
+            controller.resume(pid);
+            controller.catch_up()?;
+        }
+        let thrashing = controller.thrashing;
+        Ok(controller
+            .simulation
+            .finish()
+            .with_thrashing_windows(thrashing))
+    }
+
+    fn window(&self) -> u64 {
+        match self {
+            LoadControl::Off => u64::MAX,
+            LoadControl::FaultRate { window, .. } | LoadControl::WorkingSet { window, .. } => {
+                *window
+            }
+        }
+    }
+
+    fn validate(self) -> Result<LoadControl, String> {
+        match self {
+            LoadControl::FaultRate { window: 0, .. }
+            | LoadControl::WorkingSet { window: 0, .. } => {
+                Err("load control window must be at least 1".to_string())
+            }
+            LoadControl::WorkingSet { tau: 0, .. } => {
+                Err("load control tau must be at least 1".to_string())
+            }
+            LoadControl::FaultRate {
+                suspend, resume, ..
+            } if !(0.0..=1.0).contains(&suspend)
+                || !(0.0..=1.0).contains(&resume)
+                || resume > suspend =>
+            {
+                Err(format!(
+                    "load control needs 0 <= resume <= suspend <= 1, got suspend={} resume={}",
+                    suspend, resume
+                ))
+            }
+            _ => Ok(self),
+        }
+    }
+}
+
+/// Distinct pages each process referenced in the last tau references
+struct WorkingSets {
+    tau: u64,
+    recent: VecDeque<PageId>,
+    counts: HashMap<PageId, u64>,
+    sizes: HashMap<Pid, u64>,
+}
+
+impl WorkingSets {
+    fn new(tau: u64) -> WorkingSets {
+        WorkingSets {
+            tau,
+            recent: VecDeque::new(),
+            counts: HashMap::new(),
+            sizes: HashMap::new(),
+        }
+    }
+
+    fn push(&mut self, page: PageId) {
+        self.recent.push_back(page);
+        let count = self.counts.entry(page).or_insert(0);
+        *count += 1;
+        if *count == 1 {
+            *self.sizes.entry(page.process).or_insert(0) += 1;
+        }
+        if self.recent.len() as u64 > self.tau {
+            let old = self.recent.pop_front().unwrap();
+            let count = self.counts.get_mut(&old).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&old);
+                *self.sizes.get_mut(&old.process).unwrap() -= 1;
+            }
+        }
+    }
+
+    fn size(&self, process: Pid) -> u64 {
+        *self.sizes.get(&process).unwrap_or(&0)
+    }
+}
+
+struct Controller {
+    control: LoadControl,
+    simulation: Simulation,
+    /// Processes that have made references and are not suspended
+    running: BTreeSet<Pid>,
+    /// Suspended processes, longest suspended first, with their working set
+    /// size when they were suspended
+    suspended: VecDeque<(Pid, u64)>,
+    /// References of suspended processes, in order, waiting for them
+    queued: HashMap<Pid, VecDeque<Reference>>,
+    /// References of resumed processes to catch up on
+    ready: VecDeque<Reference>,
+    working_sets: WorkingSets,
+    window_references: u64,
+    window_faults: u64,
+    thrashing: u64,
+}
+
+impl Controller {
+    fn new(control: LoadControl, simulation: Simulation) -> Controller {
+        let tau = match control {
+            LoadControl::WorkingSet { tau, .. } => tau,
+            _ => control.window(),
+        };
+        Controller {
+            control,
+            simulation,
+            running: BTreeSet::new(),
+            suspended: VecDeque::new(),
+            queued: HashMap::new(),
+            ready: VecDeque::new(),
+            working_sets: WorkingSets::new(tau),
+            window_references: 0,
+            window_faults: 0,
+            thrashing: 0,
+        }
+    }
+
+    fn feed(&mut self, reference: Reference) -> Result<(), Error> {
+        let page = reference.page();
+        if self.simulation.is_suspended(page.process) {
+            self.queued
+                .entry(page.process)
+                .or_default()
+                .push_back(reference);
+            return Ok(());
+        }
+        self.running.insert(page.process);
+        let outcome = match self.simulation.apply(reference)? {
+            Some(outcome) => outcome,
+            None => return Ok(()),
+        };
+        self.working_sets.push(page);
+        self.window_references += 1;
+        self.window_faults += (outcome != Outcome::Hit) as u64;
+        if self.window_references >= self.control.window() {
+            self.decide()?;
+        }
+        Ok(())
+    }
+
+    fn catch_up(&mut self) -> Result<(), Error> {
+        while let Some(reference) = self.ready.pop_front() {
+            self.feed(reference)?;
+        }
+        Ok(())
+    }
+
+    /// Suspend a process when memory thrashes, resume one when it is calm
+    fn decide(&mut self) -> Result<(), Error> {
+        let rate = self.window_faults as f64 / self.window_references as f64;
+        self.window_references = 0;
+        self.window_faults = 0;
+        let frames = self.simulation.frames() as u64;
+        let demand = self
+            .running
+            .iter()
+            .map(|x| self.working_sets.size(*x))
+            .sum::<u64>();
+        let (thrashing, calm) = match self.control {
+            LoadControl::Off => (false, false),
+            LoadControl::FaultRate {
+                suspend, resume, ..
+            } => (rate > suspend, rate < resume),
+            LoadControl::WorkingSet { .. } => {
+                let waiting = self.suspended.front().map_or(0, |(_, x)| *x);
+                (demand > frames, demand + waiting <= frames)
+            }
+        };
+        if thrashing {
+            self.thrashing += 1;
+            if self.running.len() > 1 {
+                let victim = self
+                    .running
+                    .iter()
+                    .copied()
+                    .max_by_key(|x| (self.simulation.resident_count_of(*x), *x))
+                    .unwrap();
+                self.suspend(victim)?;
+            }
+        } else if calm {
+            if let Some((pid, _)) = self.suspended.pop_front() {
+                self.resume(pid);
+            }
+        }
+        Ok(())
+    }
+
+    fn suspend(&mut self, process: Pid) -> Result<(), Error> {
+        self.simulation.suspend(process)?;
+        self.running.remove(&process);
+        let size = self.working_sets.size(process);
+        self.suspended.push_back((process, size));
+        Ok(())
+    }
+
+    /// process takes its waiting references before anything new
+    fn resume(&mut self, process: Pid) {
+        self.simulation.resume(process);
+        self.running.insert(process);
+        if let Some(queued) = self.queued.remove(&process) {
+            self.ready.extend(queued);
+        }
+    }
+}
+
+impl fmt::Display for LoadControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadControl::Off => write!(f, "off"),
+            LoadControl::FaultRate {
+                window,
+                suspend,
+                resume,
+            } => write!(
+                f,
+                "fault_rate(window={},suspend={},resume={})",
+                window, suspend, resume
+            ),
+            LoadControl::WorkingSet { window, tau } => {
+                write!(f, "working_set(window={},tau={})", window, tau)
+            }
+        }
+    }
+}
+
+/// 'off', or 'fault_rate' or 'working_set' optionally followed by settings
+/// as in 'fault_rate:window=100,suspend=0.5,resume=0.2' and
+/// 'working_set:window=100,tau=200'
+impl FromStr for LoadControl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LoadControl, String> {
+        let (kind, settings) = s.split_once(':').unwrap_or((s, ""));
+        let mut control = match kind {
+            "off" if settings.is_empty() => return Ok(LoadControl::Off),
+            "fault_rate" => LoadControl::FaultRate {
+                window: 100,
+                suspend: 0.5,
+                resume: 0.2,
+            },
+            "working_set" => LoadControl::WorkingSet {
+                window: 100,
+                tau: 200,
+            },
+            _ => {
+                return Err(format!(
+                    "unknown load control '{}', expected off, fault_rate or working_set",
+                    s
+                ))
+            }
+        };
+        for part in settings.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("load control option '{}' needs a value", part))?;
+            let bad = || format!("load control {} expects a number, got '{}'", key, value);
+            match (&mut control, key) {
+                (LoadControl::FaultRate { window, .. }, "window")
+                | (LoadControl::WorkingSet { window, .. }, "window") => {
+                    *window = value.parse().map_err(|_| bad())?
+                }
+                (LoadControl::FaultRate { suspend, .. }, "suspend") => {
+                    *suspend = value.parse().map_err(|_| bad())?
+                }
+                (LoadControl::FaultRate { resume, .. }, "resume") => {
+                    *resume = value.parse().map_err(|_| bad())?
+                }
+                (LoadControl::WorkingSet { tau, .. }, "tau") => {
+                    *tau = value.parse().map_err(|_| bad())?
+                }
+                _ => return Err(format!("unknown {} option '{}'", kind, key)),
+            }
+        }
+        control.validate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::{by_name, Fifo, POLICIES};
+    use crate::workload::Workload;
+    use crate::CostModel;
+
+    /// Two processes each looping over 4 pages, taking turns
+    fn loops(length: usize) -> Vec<PageId> {
+        let workload = Workload::Loop { length, pages: 4 };
+        Workload::Interleave(vec![workload; 2]).generate(0)
+    }
+
+    fn run(control: &str, trace: &[PageId], frames: u32) -> PageReport {
+        let simulation = Simulation::new(Box::new(Fifo::new()), frames)
+            .unwrap()
+            .checked();
+        let control = control.parse::<LoadControl>().unwrap();
+        control
+            .run(simulation, trace.iter().copied().map(Ok))
+            .unwrap()
+    }
+
+    #[test]
+    fn off_should_match_plain_run() {
+        let trace = loops(100);
+        let plain = Simulation::new(Box::new(Fifo::new()), 5)
+            .unwrap()
+            .run(trace.iter().copied().map(Ok));
+        assert_eq!(Ok(run("off", &trace, 5)), plain);
+    }
+
+    #[test]
+    fn fault_rate_should_suspend_a_thrashing_process() {
+        // 8 pages in 5 frames, every reference faults under fifo
+        let trace = loops(400);
+        let off = run("off", &trace, 5);
+        assert_eq!(off.hits(), 0);
+
+        let res = run("fault_rate:window=20,suspend=0.5,resume=0.1", &trace, 5);
+        assert_eq!(res.references(), off.references());
+        assert!(res.thrashing_windows() >= 1);
+        assert!(res.suspensions() >= 1);
+        assert!(res.faults() < off.faults() / 2, "{} faults", res.faults());
+        let model = CostModel::default();
+        assert!(res.throughput(&model) > 2.0 * off.throughput(&model));
+        // every reference still belongs to its process
+        for (_, process) in res.processes() {
+            assert_eq!(process.references(), 400);
+        }
+    }
+
+    #[test]
+    fn working_set_should_suspend_until_the_sets_fit() {
+        let trace = loops(400);
+        let res = run("working_set:window=10,tau=16", &trace, 5);
+        assert_eq!(res.references(), 800);
+        assert!(res.suspensions() >= 1);
+        assert!(res.faults() < run("off", &trace, 5).faults() / 2);
+        // working sets that fit never count as thrashing
+        let res = run("working_set:window=10,tau=16", &trace, 8);
+        assert_eq!((res.thrashing_windows(), res.suspensions()), (0, 0));
+    }
+
+    #[test]
+    fn every_policy_should_run_under_load_control() {
+        let workload = Workload::Zipf {
+            length: 300,
+            pages: 20,
+            exponent: 0.8,
+        };
+        let trace = Workload::Interleave(vec![workload; 3]).generate(3);
+        for name in POLICIES.iter() {
+            for control in ["fault_rate:window=30", "working_set:window=30,tau=60"].iter() {
+                let simulation = Simulation::new(by_name(name, 1).unwrap(), 8)
+                    .unwrap()
+                    .checked();
+                let res = control
+                    .parse::<LoadControl>()
+                    .unwrap()
+                    .run(simulation, trace.iter().copied().map(Ok))
+                    .unwrap();
+                assert_eq!(res.references(), 900, "{} {}", name, control);
+            }
+        }
+    }
+
+    #[test]
+    fn parse_should_fill_defaults_and_reject_bad_settings() {
+        assert_eq!("off".parse::<LoadControl>(), Ok(LoadControl::Off));
+        let res = "fault_rate:suspend=0.6".parse::<LoadControl>().unwrap();
+        assert_eq!(
+            res.to_string(),
+            "fault_rate(window=100,suspend=0.6,resume=0.2)"
+        );
+        let res = "working_set:tau=50".parse::<LoadControl>().unwrap();
+        assert_eq!(res.to_string(), "working_set(window=100,tau=50)");
+        assert!("fault_rate:suspend=0.1,resume=0.3"
+            .parse::<LoadControl>()
+            .is_err());
+        assert!("fault_rate:tau=3".parse::<LoadControl>().is_err());
+        assert!("working_set:window=0".parse::<LoadControl>().is_err());
+        assert!("off:window=3".parse::<LoadControl>().is_err());
+        assert!("panic".parse::<LoadControl>().is_err());
+    }
+}
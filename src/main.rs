@@ -1,49 +1,88 @@
 extern crate page_replacement;
 
-use page_replacement::{algorithms, load_page, utils, MemoryPage, PageReport};
-use rand::prelude::*;
+use page_replacement::cli::{execute, parse, Command, USAGE};
+use page_replacement::experiment::Experiment;
+use page_replacement::output::render;
+use page_replacement::policy::POLICIES;
+use page_replacement::sweep::{self, Progress};
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
 
 fn main() {
-    let mut rng = rand::thread_rng();
-    let mut page_hit_order = Vec::new();
-    for x in 0..100 {
-        page_hit_order.insert(x, rng.gen_range(0, 20));
-    }
-    let algorithms: Vec<(
-        &str,
-        fn(Vec<MemoryPage>, MemoryPage, Vec<u32>) -> Vec<MemoryPage>,
-    )> = vec![
-        ("Fifo", algorithms::fifo),
-        ("Second Chance", algorithms::second_chance),
-        ("Least Recently Use", algorithms::lru),
-        ("Not Recently Use", algorithms::nru),
-        ("Clock", algorithms::clock),
-    ];
-    let buffer_sizes = vec![3, 5, 10];
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    let command = parse(&args).unwrap_or_else(|err| {
+        eprintln!("error: {}\n\n{}", err, USAGE);
+        process::exit(2);
+    });
+
+    let options = match command {
+        Command::Help => {
+            print!("{}", USAGE);
+            return;
+        }
+        Command::List => {
+            for name in POLICIES.iter() {
+                println!("{}", name);
+            }
+            return;
+        }
+        Command::Experiment { path, output, jobs } => {
+            run_experiment(&path, output, jobs).unwrap_or_else(|err| {
+                eprintln!("error: {}", err);
+                process::exit(1);
+            });
+            return;
+        }
+        Command::Run(options) => options,
+    };
 
-    let algorithms_result = algorithms
-        .into_iter()
-        .flat_map(|(name, algorithm)| {
-            buffer_sizes
-                .iter()
-                .map(|frame_size| {
-                    let (_, page_report, _) = page_hit_order.iter().fold(
-                        (vec![], PageReport::new(), vec![]),
-                        |(frame, report, past), x| {
-                            let (frame, report) =
-                                load_page(algorithm, frame, *frame_size, *x, report, past.clone());
-                            (frame, report, utils::push(past, *x))
-                        },
-                    );
-                    (name, page_report, *frame_size)
-                })
-                .collect::<Vec<(&str, PageReport, u32)>>()
-        })
-        .collect::<Vec<(&str, PageReport, u32)>>();
+    let results = execute(&options, progress()).unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    });
+    let rendered = render(&results, options.format);
+    match &options.output {
+        Some(path) => fs::write(path, rendered).unwrap_or_else(|err| {
+            eprintln!("error: could not write {}: {}", path.display(), err);
+            process::exit(1);
+        }),
+        None => print!("{}", rendered),
+    }
+}
 
-    println!("Ran With: {:?}", page_hit_order);
+/// Progress on stderr when it is a terminal, none when it is redirected
+fn progress() -> Progress<'static> {
+    if io::stderr().is_terminal() {
+        &show_progress
+    } else {
+        &sweep::quiet
+    }
+}
 
-    for (name, report, frame_size) in algorithms_result {
-        println!("| {} | frame_size: {} {}", name, frame_size, report);
+/// Overwrite a single progress line on stderr, ending it once the sweep is done
+fn show_progress(done: usize, total: usize) {
+    let mut stderr = io::stderr().lock();
+    let _ = write!(stderr, "\r{}/{} simulations", done, total);
+    if done == total {
+        let _ = writeln!(stderr);
     }
 }
+
+fn run_experiment(path: &Path, output: Option<PathBuf>, jobs: usize) -> Result<(), String> {
+    let (experiment, config) = Experiment::load(path)?;
+    let experiment = Experiment {
+        output: output.unwrap_or(experiment.output),
+        ..experiment
+    };
+    let results = experiment.run(jobs, progress())?;
+    experiment.write_bundle(&config, &results)?;
+    eprintln!(
+        "{} simulations written to {}",
+        results.len(),
+        experiment.output.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,752 @@
+use crate::huge::HugePages;
+use crate::kswapd::Watermarks;
+use crate::load::LoadControl;
+use crate::prefetch::Prefetch;
+use crate::scope::Scope;
+use crate::swap::Device;
+use crate::tier::Tiering;
+use crate::tlb::Translation;
+use crate::zswap::Zswap;
+use crate::{CostModel, PageReport, Pid, ProcessReport};
+use std::fmt;
+use std::str::FromStr;
+
+/// One simulation: an algorithm run over a workload with a fixed number of frames
+#[derive(Debug, Clone)]
+pub struct RunResult {
+    pub algorithm: String,
+    pub frames: u32,
+    pub workload: String,
+    pub seed: u64,
+    /// Which pages a fault could evict
+    pub scope: Scope,
+    pub load_control: LoadControl,
+    /// TLB and page table every reference went through, if any
+    pub tlb: Option<Translation>,
+    pub prefetch: Prefetch,
+    /// Backing store faults and write-backs waited on, if any
+    pub swap: Option<Device>,
+    /// Free frames a page-out daemon kept, if one ran
+    pub watermarks: Option<Watermarks>,
+    /// Slower memory below the frames, if any
+    pub tiers: Option<Tiering>,
+    /// Compressed pool below memory, if any
+    pub zswap: Option<Zswap>,
+    /// Huge pages next to base pages, if any
+    pub huge: Option<HugePages>,
+    pub report: PageReport,
+    /// Prices the report's events when set
+    pub cost: Option<CostModel>,
+}
+
+/// Ways results can be written out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// The human readable lines the binary has always printed
+    Table,
+    Json,
+    Csv,
+    Markdown,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s.to_lowercase().as_str() {
+            "table" => Ok(Format::Table),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "markdown" | "md" => Ok(Format::Markdown),
+            _ => Err(format!(
+                "unknown format '{}', expected table, json, csv or markdown",
+                s
+            )),
+        }
+    }
+}
+
+/// A single metric in a result row
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Count(u64),
+    Real(f64),
+    /// Metric has no value for this run, e.g. victim ages when nothing was evicted
+    Missing,
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Count(x) => write!(f, "{}", x),
+            Value::Real(x) => write!(f, "{:.6}", x),
+            Value::Missing => Ok(()),
+        }
+    }
+}
+
+/// Every metric of a result, in column order
+pub fn metrics(result: &RunResult) -> Vec<(&'static str, Value)> {
+    let report = &result.report;
+    let count = Value::Count;
+    let victim_ages = report.victim_age_summary();
+    let residency = report.residency_summary();
+    let translation = report.translation();
+    let swap = report.swap();
+    let tiers = report.tiers();
+    let tier_hits = |tier: usize| {
+        tiers
+            .and_then(|x| x.hits(tier))
+            .map_or(Value::Missing, count)
+    };
+    let pool = report.pool();
+    let huge = report.huge();
+    vec![
+        ("references", count(report.references())),
+        ("hits", count(report.hits())),
+        ("faults", count(report.faults())),
+        ("removed", count(report.removed_count())),
+        ("hit_ratio", Value::Real(report.hit_ratio())),
+        ("fault_ratio", Value::Real(report.fault_ratio())),
+        ("compulsory_faults", count(report.compulsory_faults())),
+        ("capacity_faults", count(report.capacity_faults())),
+        ("cow_faults", count(report.cow_faults())),
+        ("premature_evictions", count(report.premature_evictions())),
+        ("premature_window", count(report.premature_window())),
+        (
+            "victim_age_mean",
+            victim_ages.map_or(Value::Missing, |x| Value::Real(x.mean)),
+        ),
+        (
+            "victim_age_median",
+            victim_ages.map_or(Value::Missing, |x| count(x.median)),
+        ),
+        (
+            "victim_age_max",
+            victim_ages.map_or(Value::Missing, |x| count(x.max)),
+        ),
+        (
+            "residency_mean",
+            residency.map_or(Value::Missing, |x| Value::Real(x.mean)),
+        ),
+        (
+            "residency_median",
+            residency.map_or(Value::Missing, |x| count(x.median)),
+        ),
+        (
+            "residency_max",
+            residency.map_or(Value::Missing, |x| count(x.max)),
+        ),
+        (
+            "total_cost",
+            result
+                .cost
+                .map_or(Value::Missing, |x| Value::Real(report.cost(&x))),
+        ),
+        (
+            "effective_access_time",
+            result.cost.map_or(Value::Missing, |x| {
+                Value::Real(report.effective_access_time(&x))
+            }),
+        ),
+        ("suspensions", count(report.suspensions())),
+        ("thrashing_windows", count(report.thrashing_windows())),
+        // the default cost model prices runs that have none
+        (
+            "throughput",
+            Value::Real(report.throughput(&result.cost.unwrap_or_default())),
+        ),
+        (
+            "tlb_hits",
+            translation.map_or(Value::Missing, |x| count(x.hits())),
+        ),
+        (
+            "tlb_misses",
+            translation.map_or(Value::Missing, |x| count(x.misses())),
+        ),
+        (
+            "tlb_hit_ratio",
+            translation.map_or(Value::Missing, |x| Value::Real(x.hit_ratio())),
+        ),
+        (
+            "walk_references",
+            translation.map_or(Value::Missing, |x| count(x.walk_references())),
+        ),
+        (
+            "page_table_pages",
+            translation.map_or(Value::Missing, |x| count(x.table_pages())),
+        ),
+        (
+            "tlb_reach",
+            translation.map_or(Value::Missing, |x| count(x.reach())),
+        ),
+        // like throughput, priced by the default cost model without one
+        (
+            "combined_access_time",
+            report
+                .combined_access_time(&result.cost.unwrap_or_default())
+                .map_or(Value::Missing, Value::Real),
+        ),
+        ("prefetches", count(report.prefetches())),
+        ("useful_prefetches", count(report.useful_prefetches())),
+        ("wasted_prefetches", count(report.wasted_prefetches())),
+        ("prefetch_accuracy", Value::Real(report.prefetch_accuracy())),
+        ("write_backs", count(report.write_backs())),
+        (
+            "swap_reads",
+            swap.map_or(Value::Missing, |x| count(x.reads())),
+        ),
+        (
+            "background_writes",
+            swap.map_or(Value::Missing, |x| count(x.cleaned())),
+        ),
+        (
+            "queue_waits",
+            swap.map_or(Value::Missing, |x| count(x.waits())),
+        ),
+        (
+            "io_stall_time",
+            swap.map_or(Value::Missing, |x| Value::Real(x.stall())),
+        ),
+        (
+            "simulated_time",
+            swap.map_or(Value::Missing, |x| Value::Real(x.elapsed())),
+        ),
+        (
+            "write_back_stall",
+            swap.map_or(Value::Missing, |x| Value::Real(x.write_back_stall())),
+        ),
+        (
+            "device_throughput",
+            swap.map_or(Value::Missing, |x| {
+                Value::Real(x.throughput(report.references()))
+            }),
+        ),
+        ("direct_reclaims", count(report.direct_reclaims())),
+        ("background_reclaims", count(report.background_reclaims())),
+        ("kswapd_wakeups", count(report.kswapd_wakeups())),
+        (
+            "fast_tier_hits",
+            tiers.map_or(Value::Missing, |_| count(report.fast_hits())),
+        ),
+        ("tier1_hits", tier_hits(0)),
+        ("tier2_hits", tier_hits(1)),
+        ("tier3_hits", tier_hits(2)),
+        (
+            "promotions",
+            tiers.map_or(Value::Missing, |x| count(x.promotions())),
+        ),
+        (
+            "demotions",
+            tiers.map_or(Value::Missing, |x| count(x.demotions())),
+        ),
+        (
+            "migration_bytes",
+            tiers.map_or(Value::Missing, |x| count(x.migration_bytes())),
+        ),
+        (
+            "tiered_access_time",
+            tiers.map_or(Value::Missing, |x| {
+                Value::Real(x.access_time(report.fast_hits()))
+            }),
+        ),
+        (
+            "pool_hits",
+            pool.map_or(Value::Missing, |x| count(x.hits())),
+        ),
+        (
+            "swap_ins",
+            pool.map_or(Value::Missing, |_| count(report.swap_ins())),
+        ),
+        (
+            "pool_stores",
+            pool.map_or(Value::Missing, |x| count(x.stores())),
+        ),
+        (
+            "pool_evictions",
+            pool.map_or(Value::Missing, |x| count(x.evictions())),
+        ),
+        (
+            "compression_ratio",
+            pool.map_or(Value::Missing, |x| Value::Real(x.compression_ratio())),
+        ),
+        (
+            "pool_peak_bytes",
+            pool.map_or(Value::Missing, |x| count(x.peak_bytes())),
+        ),
+        (
+            "decompress_time",
+            pool.map_or(Value::Missing, |x| Value::Real(x.decompress_time())),
+        ),
+        (
+            "huge_collapses",
+            huge.map_or(Value::Missing, |x| count(x.collapses())),
+        ),
+        (
+            "huge_splits",
+            huge.map_or(Value::Missing, |x| count(x.splits())),
+        ),
+        (
+            "wasted_bytes_mean",
+            huge.map_or(Value::Missing, |x| Value::Real(x.mean_wasted_bytes())),
+        ),
+        (
+            "wasted_bytes_peak",
+            huge.map_or(Value::Missing, |x| count(x.peak_wasted_bytes())),
+        ),
+        (
+            "tlb_reach_gain",
+            translation.map_or(Value::Missing, |x| Value::Real(x.reach_gain())),
+        ),
+    ]
+}
+
+/// The metrics a single process of a result has, in column order
+pub fn process_metrics(report: &ProcessReport) -> Vec<(&'static str, Value)> {
+    let count = Value::Count;
+    vec![
+        ("references", count(report.references())),
+        ("hits", count(report.hits())),
+        ("faults", count(report.faults())),
+        ("removed", count(report.removed_count())),
+        ("hit_ratio", Value::Real(report.hit_ratio())),
+        ("fault_ratio", Value::Real(report.fault_ratio())),
+        ("compulsory_faults", count(report.compulsory_faults())),
+        ("capacity_faults", count(report.capacity_faults())),
+        ("cow_faults", count(report.cow_faults())),
+        (
+            "frames_min",
+            report
+                .min_frames()
+                .map_or(Value::Missing, |x| count(x as u64)),
+        ),
+        (
+            "frames_max",
+            report
+                .max_frames()
+                .map_or(Value::Missing, |x| count(x as u64)),
+        ),
+        (
+            "allocation_changes",
+            count(report.allocations().len().saturating_sub(1) as u64),
+        ),
+        ("suspensions", count(report.suspensions())),
+    ]
+}
+
+const IDENTITY_COLUMNS: [&str; 14] = [
+    "algorithm",
+    "frames",
+    "workload",
+    "seed",
+    "scope",
+    "load_control",
+    "tlb",
+    "prefetch",
+    "swap",
+    "watermarks",
+    "tiers",
+    "zswap",
+    "huge",
+    "process",
+];
+
+/// process is empty on the row of the whole run, the frames of a process
+/// row are the ones it could use: its own share under local replacement
+fn identity(result: &RunResult, process: Option<Pid>) -> [String; 14] {
+    let frames = process
+        .and_then(|x| result.report.process(x))
+        .and_then(|x| x.frames())
+        .unwrap_or(result.frames);
+    [
+        result.algorithm.clone(),
+        frames.to_string(),
+        result.workload.clone(),
+        result.seed.to_string(),
+        result.scope.to_string(),
+        result.load_control.to_string(),
+        result
+            .tlb
+            .as_ref()
+            .map_or("off".to_string(), |x| x.to_string()),
+        result.prefetch.to_string(),
+        result.swap.map_or("off".to_string(), |x| x.to_string()),
+        result
+            .watermarks
+            .map_or("off".to_string(), |x| x.to_string()),
+        result
+            .tiers
+            .as_ref()
+            .map_or("off".to_string(), |x| x.to_string()),
+        result
+            .zswap
+            .as_ref()
+            .map_or("off".to_string(), |x| x.to_string()),
+        result.huge.map_or("off".to_string(), |x| x.to_string()),
+        process.map_or(String::new(), |x| x.to_string()),
+    ]
+}
+
+/// The row of the whole run, followed by one row per process when more than
+/// one process made references. Every row leaves the metrics it does not
+/// have missing
+fn rows(result: &RunResult) -> Vec<([String; 14], Vec<Value>)> {
+    let row = |known: Vec<(&str, Value)>| {
+        metric_columns()
+            .iter()
+            .map(|name| {
+                known
+                    .iter()
+                    .find(|(x, _)| x == name)
+                    .map_or(Value::Missing, |(_, x)| *x)
+            })
+            .collect()
+    };
+    let mut rows = vec![(identity(result, None), row(metrics(result)))];
+    if result.report.processes().count() > 1 {
+        for (pid, report) in result.report.processes() {
+            rows.push((identity(result, Some(pid)), row(process_metrics(report))));
+        }
+    }
+    rows
+}
+
+/// Metrics of the whole run, then the ones only processes have
+fn metric_columns() -> Vec<&'static str> {
+    let empty = RunResult {
+        algorithm: String::new(),
+        frames: 0,
+        workload: String::new(),
+        seed: 0,
+        scope: Scope::Global,
+        load_control: LoadControl::Off,
+        tlb: None,
+        prefetch: Prefetch::Off,
+        swap: None,
+        watermarks: None,
+        tiers: None,
+        zswap: None,
+        huge: None,
+        report: PageReport::new(),
+        cost: None,
+    };
+    let mut columns = metrics(&empty)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<&str>>();
+    for (name, _) in process_metrics(&ProcessReport::default()) {
+        if !columns.contains(&name) {
+            columns.push(name);
+        }
+    }
+    columns
+}
+
+pub fn render(results: &[RunResult], format: Format) -> String {
+    match format {
+        Format::Table => to_table(results),
+        Format::Json => to_json(results),
+        Format::Csv => to_csv(results),
+        Format::Markdown => to_markdown(results),
+    }
+}
+
+/// Results compared with and without load control also get a line with
+/// their throughput
+pub fn to_table(results: &[RunResult]) -> String {
+    let compare = results.iter().any(|x| x.load_control != LoadControl::Off);
+    results
+        .iter()
+        .map(|x| {
+            let mut line = format!("| {} | frame_size: {} {}", x.algorithm, x.frames, x.report);
+            if compare {
+                line += &format!(
+                    "\n    | load control: {} | suspensions: {} | thrashing windows: {} | throughput: {:.3} |",
+                    x.load_control,
+                    x.report.suspensions(),
+                    x.report.thrashing_windows(),
+                    x.report.throughput(&x.cost.unwrap_or_default())
+                );
+            }
+            line + "\n"
+        })
+        .collect()
+}
+
+pub fn to_json(results: &[RunResult]) -> String {
+    let rows = results
+        .iter()
+        .map(|result| {
+            let [algorithm, frames, workload, seed, scope, load_control, tlb, prefetch, swap, watermarks, tiers, zswap, huge, _] =
+                identity(result, None);
+            let processes = result
+                .report
+                .processes()
+                .map(|(pid, report)| {
+                    let allocations = report
+                        .allocations()
+                        .iter()
+                        .map(|(time, frames)| format!("[{},{}]", time, frames))
+                        .collect::<Vec<String>>();
+                    format!(
+                        "{{\"process\":{},\"frames\":{},{},\"allocations\":[{}]}}",
+                        pid,
+                        report.frames().unwrap_or(result.frames),
+                        json_fields(process_metrics(report)),
+                        allocations.join(",")
+                    )
+                })
+                .collect::<Vec<String>>();
+            format!(
+                "  {{\"algorithm\":{},\"frames\":{},\"workload\":{},\"seed\":{},\"scope\":{},\"load_control\":{},\"tlb\":{},\"prefetch\":{},\"swap\":{},\"watermarks\":{},\"tiers\":{},\"zswap\":{},\"huge\":{},{},\"processes\":[{}]}}",
+                json_string(&algorithm),
+                frames,
+                json_string(&workload),
+                seed,
+                json_string(&scope),
+                json_string(&load_control),
+                json_string(&tlb),
+                json_string(&prefetch),
+                json_string(&swap),
+                json_string(&watermarks),
+                json_string(&tiers),
+                json_string(&zswap),
+                json_string(&huge),
+                json_fields(metrics(result)),
+                processes.join(",")
+            )
+        })
+        .collect::<Vec<String>>();
+    if rows.is_empty() {
+        return "[]\n".to_string();
+    }
+    format!("[\n{}\n]\n", rows.join(",\n"))
+}
+
+pub fn to_csv(results: &[RunResult]) -> String {
+    let header = IDENTITY_COLUMNS
+        .iter()
+        .copied()
+        .chain(metric_columns())
+        .collect::<Vec<&str>>()
+        .join(",");
+    let rows = results.iter().flat_map(rows).map(|(identity, values)| {
+        identity
+            .iter()
+            .map(|x| csv_field(x))
+            .chain(values.iter().map(|x| x.to_string()))
+            .collect::<Vec<String>>()
+            .join(",")
+    });
+    std::iter::once(header)
+        .chain(rows)
+        .map(|x| x + "\n")
+        .collect()
+}
+
+pub fn to_markdown(results: &[RunResult]) -> String {
+    let columns = IDENTITY_COLUMNS
+        .iter()
+        .copied()
+        .chain(metric_columns())
+        .collect::<Vec<&str>>();
+    let header = format!("| {} |\n", columns.join(" | "));
+    let divider = format!("|{}\n", columns.iter().map(|_| "---|").collect::<String>());
+    let rows = results.iter().flat_map(rows).map(|(identity, values)| {
+        let cells = identity
+            .iter()
+            .map(|x| x.replace('|', "\\|"))
+            .chain(values.iter().map(|x| match x {
+                Value::Missing => "-".to_string(),
+                _ => x.to_string(),
+            }))
+            .collect::<Vec<String>>();
+        format!("| {} |\n", cells.join(" | "))
+    });
+    std::iter::once(header)
+        .chain(std::iter::once(divider))
+        .chain(rows)
+        .collect()
+}
+
+fn json_fields(metrics: Vec<(&str, Value)>) -> String {
+    metrics
+        .into_iter()
+        .map(|(name, value)| match value {
+            Value::Missing => format!("\"{}\":null", name),
+            _ => format!("\"{}\":{}", name, value),
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn json_string(s: &str) -> String {
+    let escaped = s
+        .chars()
+        .map(|c| match c {
+            '"' => "\\\"".to_string(),
+            '\\' => "\\\\".to_string(),
+            '\n' => "\\n".to_string(),
+            '\r' => "\\r".to_string(),
+            '\t' => "\\t".to_string(),
+            c if (c as u32) < 0x20 => format!("\\u{:04x}", c as u32),
+            c => c.to_string(),
+        })
+        .collect::<String>();
+    format!("\"{}\"", escaped)
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains(',') || s.contains('"') || s.contains('\n') {
+        return format!("\"{}\"", s.replace('"', "\"\""));
+    }
+    s.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::Fifo;
+    use crate::{pages, simulation, PageId};
+
+    fn results() -> Vec<RunResult> {
+        vec![RunResult {
+            algorithm: "Fifo".to_string(),
+            frames: 3,
+            workload: "uniform(length=6,pages=4)".to_string(),
+            seed: 42,
+            scope: Scope::Global,
+            load_control: LoadControl::Off,
+            tlb: None,
+            prefetch: Prefetch::Off,
+            swap: None,
+            watermarks: None,
+            tiers: None,
+            zswap: None,
+            huge: None,
+            report: simulation::run(Box::new(Fifo::new()), &pages(&[0, 1, 2, 0, 3, 0]), 3).unwrap(),
+            cost: None,
+        }]
+    }
+
+    #[test]
+    fn format_should_parse_names() {
+        assert_eq!("JSON".parse::<Format>(), Ok(Format::Json));
+        assert_eq!("md".parse::<Format>(), Ok(Format::Markdown));
+        assert!("xml".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn csv_should_quote_identity_and_match_header() {
+        let csv = to_csv(&results());
+        let lines = csv.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(
+            "algorithm,frames,workload,seed,scope,load_control,tlb,prefetch,swap,watermarks,tiers,zswap,huge,process,references,hits"
+        ));
+        assert!(lines[1].starts_with(
+            "Fifo,3,\"uniform(length=6,pages=4)\",42,global,off,off,off,off,off,off,off,off,,6,1,5,2,"
+        ));
+    }
+
+    #[test]
+    fn json_should_contain_every_metric() {
+        let json = to_json(&results());
+        assert!(json.starts_with("[\n  {\"algorithm\":\"Fifo\",\"frames\":3,"));
+        for name in metric_columns() {
+            assert!(json.contains(&format!("\"{}\":", name)), "missing {}", name);
+        }
+        assert_eq!(to_json(&[]), "[]\n");
+    }
+
+    #[test]
+    fn json_should_escape_strings() {
+        assert_eq!(json_string("a\"b\\c\n"), "\"a\\\"b\\\\c\\n\"");
+    }
+
+    #[test]
+    fn markdown_should_have_one_row_per_result() {
+        let markdown = to_markdown(&results());
+        let lines = markdown.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("|---|"));
+        assert!(lines[2].starts_with(
+            "| Fifo | 3 | uniform(length=6,pages=4) | 42 | global | off | off | off | off | off | off | off | off |  | 6 | 1 | 5 |"
+        ));
+    }
+
+    #[test]
+    fn missing_metrics_should_be_null_in_json() {
+        let result = RunResult {
+            report: PageReport::new(),
+            ..results().remove(0)
+        };
+        assert!(to_json(&[result]).contains("\"victim_age_mean\":null"));
+    }
+
+    #[test]
+    fn cost_model_should_fill_cost_columns() {
+        let result = RunResult {
+            cost: Some(CostModel {
+                hit: 1.0,
+                fault: 10.0,
+                eviction: 0.0,
+            }),
+            ..results().remove(0)
+        };
+        let json = to_json(&[result]);
+        assert!(json.contains("\"total_cost\":51.000000"));
+        assert!(json.contains("\"effective_access_time\":8.500000"));
+        assert!(to_json(&results()).contains("\"total_cost\":null"));
+    }
+
+    #[test]
+    fn translation_should_fill_tlb_columns() {
+        let translation = "entries=4,ways=4,levels=4/4"
+            .parse::<Translation>()
+            .unwrap();
+        let report = simulation::Simulation::new(Box::new(Fifo::new()), 3)
+            .unwrap()
+            .with_mmu(crate::tlb::Mmu::new(&translation, 0))
+            .run(pages(&[0, 1, 2, 0, 3, 0]).into_iter().map(Ok))
+            .unwrap();
+        let result = RunResult {
+            tlb: Some(translation),
+            report,
+            ..results().remove(0)
+        };
+        let json = to_json(&[result]);
+        assert!(json.contains("\"tlb\":\"tlb(entries=4,ways=4,policy=lru,levels=4/4,"));
+        assert!(json.contains("\"tlb_hits\":1,\"tlb_misses\":5,"));
+        assert!(json.contains("\"walk_references\":10,\"page_table_pages\":2,"));
+        assert!(to_json(&results()).contains("\"tlb\":\"off\""));
+        assert!(to_json(&results()).contains("\"tlb_hits\":null"));
+    }
+
+    #[test]
+    fn processes_should_get_their_own_rows() {
+        let trace = [(1, 0), (2, 0), (1, 0), (2, 1)]
+            .iter()
+            .map(|(pid, page)| PageId::new(*pid, *page))
+            .collect::<Vec<PageId>>();
+        let result = RunResult {
+            report: simulation::run(Box::new(Fifo::new()), &trace, 2).unwrap(),
+            ..results().remove(0)
+        };
+        let runs = [result];
+        let csv = to_csv(&runs);
+        let lines = csv.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].starts_with(
+            "Fifo,3,\"uniform(length=6,pages=4)\",42,global,off,off,off,off,off,off,off,off,1,2,1,1,1,"
+        ));
+        assert!(lines[3].contains(",42,global,off,off,off,off,off,off,off,off,2,2,0,2,0,"));
+        // run-wide metrics and the allocation range are missing, shared
+        // frames never change
+        assert!(lines[3].ends_with(&format!("{}0{}0", ",".repeat(11), ",".repeat(47))));
+        assert!(lines[1].ends_with(",,,"));
+        assert!(to_json(&runs)
+            .contains("\"processes\":[{\"process\":1,\"frames\":3,\"references\":2,\"hits\":1,"));
+        assert_eq!(to_csv(&results()).lines().count(), 2);
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Page fault frequency control: every window references of a process, its
+/// fault rate over them decides whether it gets a frame from the free pool,
+/// gives one back, or keeps what it has
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pff {
+    /// References of a process between two decisions
+    pub window: u64,
+    /// Give a frame back when the fault rate drops below this
+    pub lower: f64,
+    /// Ask for another frame when the fault rate rises above this
+    pub upper: f64,
+}
+
+/// What a process's allocation should do after a window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    Grow,
+    Shrink,
+    Keep,
+}
+
+impl Default for Pff {
+    fn default() -> Pff {
+        Pff {
+            window: 100,
+            lower: 0.05,
+            upper: 0.25,
+        }
+    }
+}
+
+impl Pff {
+    pub fn new(window: u64, lower: f64, upper: f64) -> Result<Pff, String> {
+        if window == 0 {
+            return Err("pff window must be at least 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&lower) || !(0.0..=1.0).contains(&upper) || lower > upper {
+            return Err(format!(
+                "pff thresholds need 0 <= lower <= upper <= 1, got lower={} upper={}",
+                lower, upper
+            ));
+        }
+        Ok(Pff {
+            window,
+            lower,
+            upper,
+        })
+    }
+
+    /// The change for a window with faults faults in references references
+    pub fn change(&self, faults: u64, references: u64) -> Change {
+        let rate = faults as f64 / references.max(1) as f64;
+        if rate > self.upper {
+            Change::Grow
+        } else if rate < self.lower {
+            Change::Shrink
+        } else {
+            Change::Keep
+        }
+    }
+}
+
+impl fmt::Display for Pff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "pff(window={},lower={},upper={})",
+            self.window, self.lower, self.upper
+        )
+    }
+}
+
+/// Comma separated window=N, lower=R and upper=R, missing ones keep their
+/// defaults
+impl FromStr for Pff {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Pff, String> {
+        let mut pff = Pff::default();
+        for part in s.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("pff option '{}' needs a value", part))?;
+            let number = || {
+                value
+                    .parse::<f64>()
+                    .map_err(|_| format!("pff {} expects a number, got '{}'", key, value))
+            };
+            match key {
+                "window" => {
+                    pff.window = value
+                        .parse()
+                        .map_err(|_| format!("pff window expects a number, got '{}'", value))?
+                }
+                "lower" => pff.lower = number()?,
+                "upper" => pff.upper = number()?,
+                _ => return Err(format!("unknown pff option '{}'", key)),
+            }
+        }
+        Pff::new(pff.window, pff.lower, pff.upper)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn change_should_follow_thresholds() {
+        let pff = Pff::new(10, 0.1, 0.5).unwrap();
+        assert_eq!(pff.change(6, 10), Change::Grow);
+        assert_eq!(pff.change(5, 10), Change::Keep);
+        assert_eq!(pff.change(1, 10), Change::Keep);
+        assert_eq!(pff.change(0, 10), Change::Shrink);
+    }
+
+    #[test]
+    fn parse_should_fill_defaults() {
+        assert_eq!("".parse::<Pff>(), Ok(Pff::default()));
+        let res = "window=50, upper=0.4".parse::<Pff>().unwrap();
+        assert_eq!(res, Pff::new(50, 0.05, 0.4).unwrap());
+        assert_eq!(res.to_string(), "pff(window=50,lower=0.05,upper=0.4)");
+    }
+
+    #[test]
+    fn bad_settings_should_be_rejected() {
+        assert!("window=0".parse::<Pff>().is_err());
+        assert!("lower=0.5,upper=0.2".parse::<Pff>().is_err());
+        assert!("upper=2".parse::<Pff>().is_err());
+        assert!("speed=3".parse::<Pff>().is_err());
+        assert!("lower".parse::<Pff>().is_err());
+    }
+}
@@ -0,0 +1,1174 @@
+use crate::utils::LinkedSet;
+use crate::{PageId, Time};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::str::FromStr;
+
+/// A replacement policy that keeps its own bookkeeping of the resident pages
+///
+/// The simulation tells the policy about every hit and every page it loads,
+/// and asks it for a victim when memory is full. Each call should be O(1)
+/// or O(log n) in the number of frames, amortized over the calls and not
+/// counting the pages evict_where skips
+pub trait Policy {
+    /// page was referenced while resident
+    fn hit(&mut self, page: PageId);
+
+    /// page was loaded into memory
+    fn insert(&mut self, page: PageId);
+
+    /// Pick a resident page to evict and stop tracking it
+    /// None when the policy tracks no pages
+    fn evict(&mut self) -> Option<PageId>;
+
+    /// Like evict, but never picks a page skip holds for, e.g. a pinned one
+    /// None when every page is skipped
+    ///
+    /// By default skipped pages are evicted and go back as if they were just
+    /// loaded, once a victim is found. Putting them back any sooner could
+    /// have the policy pick them again and again
+    fn evict_where(&mut self, skip: &dyn Fn(PageId) -> bool) -> Option<PageId> {
+        let mut skipped = vec![];
+        let victim = loop {
+            match self.evict() {
+                Some(page) if skip(page) => skipped.push(page),
+                victim => break victim,
+            }
+        };
+        for page in skipped {
+            self.insert(page);
+        }
+        victim
+    }
+
+    /// page left memory without being picked, e.g. its process was swapped
+    /// out, stop tracking it
+    fn remove(&mut self, page: PageId);
+
+    /// page was written while resident, or written back when modified is
+    /// false. Only policies that prefer clean victims need to know
+    fn modified(&mut self, _page: PageId, _modified: bool) {}
+}
+
+/// Every policy by the name it is selected with
+pub const POLICIES: [&str; 7] = [
+    "fifo",
+    "second_chance",
+    "lru",
+    "nru",
+    "clock",
+    "active_inactive",
+    "mglru",
+];
+
+/// seed drives policies that pick victims at random
+pub fn by_name(name: &str, seed: u64) -> Option<Box<dyn Policy>> {
+    from_spec(name, seed).ok()
+}
+
+/// A name of POLICIES, followed by settings for the policies that have some
+/// as in 'active_inactive:swappiness=100,inactive_ratio=2' or
+/// 'mglru:generations=4'
+pub fn from_spec(spec: &str, seed: u64) -> Result<Box<dyn Policy>, String> {
+    let spec = spec.to_lowercase();
+    let (name, settings) = spec.split_once(':').unwrap_or((&spec, ""));
+    let policy: Box<dyn Policy> = match name {
+        "active_inactive" => return Ok(Box::new(ActiveInactive::new(settings.parse()?))),
+        "mglru" => return Ok(Box::new(Mglru::new(generations(settings)?))),
+        "fifo" => Box::new(Fifo::new()),
+        "second_chance" => Box::new(SecondChance::new()),
+        "lru" => Box::new(Lru::new()),
+        "nru" => Box::new(Nru::new(seed)),
+        "clock" => Box::new(Clock::new()),
+        _ => return Err(format!("unknown algorithm '{}'", spec)),
+    };
+    if !settings.is_empty() {
+        return Err(format!("{} takes no settings, got '{}'", name, settings));
+    }
+    Ok(policy)
+}
+
+/// First In First Out
+/// Evict the page that was loaded the longest time ago, hits do not matter
+#[derive(Debug, Default)]
+pub struct Fifo {
+    queue: LinkedSet<PageId>,
+}
+
+impl Fifo {
+    pub fn new() -> Fifo {
+        Fifo::default()
+    }
+}
+
+impl Policy for Fifo {
+    fn hit(&mut self, _page: PageId) {}
+
+    fn insert(&mut self, page: PageId) {
+        self.queue.push_back(page);
+    }
+
+    fn evict(&mut self) -> Option<PageId> {
+        self.queue.pop_front()
+    }
+
+    fn remove(&mut self, page: PageId) {
+        self.queue.remove(&page);
+    }
+}
+
+/// Least Recently Used
+/// Pages are kept in order of last use, a hit moves the page to the back
+#[derive(Debug, Default)]
+pub struct Lru {
+    order: LinkedSet<PageId>,
+}
+
+impl Lru {
+    pub fn new() -> Lru {
+        Lru::default()
+    }
+}
+
+impl Policy for Lru {
+    fn hit(&mut self, page: PageId) {
+        self.order.push_back(page);
+    }
+
+    fn insert(&mut self, page: PageId) {
+        self.order.push_back(page);
+    }
+
+    fn evict(&mut self) -> Option<PageId> {
+        self.order.pop_front()
+    }
+
+    /// Skipped pages keep their place in the order
+    fn evict_where(&mut self, skip: &dyn Fn(PageId) -> bool) -> Option<PageId> {
+        let victim = self.order.iter().find(|x| !skip(*x))?;
+        self.order.remove(&victim);
+        Some(victim)
+    }
+
+    fn remove(&mut self, page: PageId) {
+        self.order.remove(&page);
+    }
+}
+
+/// Second Chance
+/// FIFO, except a referenced oldest page has its bit cleared and goes to the back
+#[derive(Debug, Default)]
+pub struct SecondChance {
+    queue: LinkedSet<PageId>,
+    referenced: HashSet<PageId>,
+}
+
+impl SecondChance {
+    pub fn new() -> SecondChance {
+        SecondChance::default()
+    }
+}
+
+impl Policy for SecondChance {
+    fn hit(&mut self, page: PageId) {
+        self.referenced.insert(page);
+    }
+
+    fn insert(&mut self, page: PageId) {
+        self.queue.push_back(page);
+    }
+
+    fn evict(&mut self) -> Option<PageId> {
+        loop {
+            let oldest = self.queue.pop_front()?;
+            if !self.referenced.remove(&oldest) {
+                return Some(oldest);
+            }
+            self.queue.push_back(oldest);
+        }
+    }
+
+    fn remove(&mut self, page: PageId) {
+        self.queue.remove(&page);
+        self.referenced.remove(&page);
+    }
+}
+
+/// Clock
+/// Frames sit on a circle, the hand sweeps clearing reference bits until it
+/// finds an unreferenced page. The new page takes the victim's place and the
+/// hand moves past it
+#[derive(Debug, Default)]
+pub struct Clock {
+    slots: Vec<Option<(PageId, bool)>>,
+    slot_of: HashMap<PageId, usize>,
+    hand: usize,
+    /// Slot freed by the last eviction, filled by the next insert
+    free: Option<usize>,
+    /// Slots emptied by remove, filled before the circle grows
+    holes: Vec<usize>,
+}
+
+impl Clock {
+    pub fn new() -> Clock {
+        Clock::default()
+    }
+}
+
+impl Policy for Clock {
+    fn hit(&mut self, page: PageId) {
+        if let Some(slot) = self.slot_of.get(&page) {
+            self.slots[*slot] = Some((page, true));
+        }
+    }
+
+    fn insert(&mut self, page: PageId) {
+        let slot = match self.free.take() {
+            Some(slot) => {
+                self.hand = (slot + 1) % self.slots.len();
+                slot
+            }
+            None => match self.holes.pop() {
+                Some(slot) => slot,
+                None => {
+                    self.slots.push(None);
+                    self.slots.len() - 1
+                }
+            },
+        };
+        self.slots[slot] = Some((page, false));
+        self.slot_of.insert(page, slot);
+    }
+
+    fn evict(&mut self) -> Option<PageId> {
+        self.evict_where(&|_| false)
+    }
+
+    /// Skipped pages keep their slot and their reference bit, the hand
+    /// passes them over
+    fn evict_where(&mut self, skip: &dyn Fn(PageId) -> bool) -> Option<PageId> {
+        // The first sweep clears the bit of every page it does not skip, a
+        // second one without a victim means every page is skipped
+        for _ in 0..2 * self.slots.len() {
+            self.hand %= self.slots.len();
+            match self.slots[self.hand] {
+                Some((page, _)) if skip(page) => self.hand += 1,
+                Some((page, true)) => {
+                    self.slots[self.hand] = Some((page, false));
+                    self.hand += 1;
+                }
+                Some((page, false)) => {
+                    self.slots[self.hand] = None;
+                    self.slot_of.remove(&page);
+                    self.free = Some(self.hand);
+                    return Some(page);
+                }
+                None => self.hand += 1,
+            }
+        }
+        None
+    }
+
+    fn remove(&mut self, page: PageId) {
+        if let Some(slot) = self.slot_of.remove(&page) {
+            self.slots[slot] = None;
+            self.holes.push(slot);
+        }
+    }
+}
+
+/// Not Recently Used
+/// Pages fall in four classes by their referenced and modified bits, evict a
+/// random page from the lowest class: unreferenced before referenced, and
+/// clean before dirty within each
+#[derive(Debug)]
+pub struct Nru {
+    /// Indexed by 2 * referenced + modified
+    classes: [IndexedSet; 4],
+    referenced: HashSet<PageId>,
+    modified: HashSet<PageId>,
+    rng: StdRng,
+}
+
+impl Nru {
+    pub fn new(seed: u64) -> Nru {
+        Nru {
+            classes: Default::default(),
+            referenced: HashSet::new(),
+            modified: HashSet::new(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    fn class(&self, page: PageId) -> usize {
+        2 * self.referenced.contains(&page) as usize + self.modified.contains(&page) as usize
+    }
+
+    /// Move a tracked page to the class of its new bits
+    fn reclass(&mut self, page: PageId, referenced: bool, modified: bool) {
+        let from = self.class(page);
+        if !self.classes[from].contains(page) {
+            return;
+        }
+        self.classes[from].remove(page);
+        flag(&mut self.referenced, page, referenced);
+        flag(&mut self.modified, page, modified);
+        let to = self.class(page);
+        self.classes[to].insert(page);
+    }
+}
+
+impl Policy for Nru {
+    fn hit(&mut self, page: PageId) {
+        self.reclass(page, true, self.modified.contains(&page));
+    }
+
+    fn insert(&mut self, page: PageId) {
+        self.classes[0].insert(page);
+    }
+
+    fn evict(&mut self) -> Option<PageId> {
+        let class = self.classes.iter_mut().find(|x| !x.is_empty())?;
+        let page = class.remove_random(&mut self.rng);
+        self.referenced.remove(&page);
+        self.modified.remove(&page);
+        Some(page)
+    }
+
+    /// Skipped pages keep their class
+    fn evict_where(&mut self, skip: &dyn Fn(PageId) -> bool) -> Option<PageId> {
+        for class in 0..self.classes.len() {
+            let mut skipped = vec![];
+            let mut victim = None;
+            while !self.classes[class].is_empty() {
+                let page = self.classes[class].remove_random(&mut self.rng);
+                if !skip(page) {
+                    victim = Some(page);
+                    break;
+                }
+                skipped.push(page);
+            }
+            for page in skipped {
+                self.classes[class].insert(page);
+            }
+            if let Some(page) = victim {
+                self.referenced.remove(&page);
+                self.modified.remove(&page);
+                return victim;
+            }
+        }
+        None
+    }
+
+    fn remove(&mut self, page: PageId) {
+        let class = self.class(page);
+        self.classes[class].remove(page);
+        self.referenced.remove(&page);
+        self.modified.remove(&page);
+    }
+
+    fn modified(&mut self, page: PageId, modified: bool) {
+        self.reclass(page, self.referenced.contains(&page), modified);
+    }
+}
+
+/// Add page to or take it out of set
+fn flag(set: &mut HashSet<PageId>, page: PageId, on: bool) {
+    if on {
+        set.insert(page);
+    } else {
+        set.remove(&page);
+    }
+}
+
+/// Knobs of the Linux active/inactive lists
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reclaim {
+    /// Preference for reclaiming anonymous over file pages, 0 to 200: anon
+    /// pages are reclaimed swappiness for every 200 - swappiness file pages
+    pub swappiness: u32,
+    /// The active list may grow to inactive_ratio times the inactive one
+    /// before its oldest pages are deactivated
+    pub inactive_ratio: u32,
+    /// Page numbers from which pages are file backed rather than anonymous,
+    /// None when every page is anonymous
+    pub file_from: Option<u32>,
+}
+
+impl Default for Reclaim {
+    /// What Linux does on a small machine
+    fn default() -> Reclaim {
+        Reclaim {
+            swappiness: 60,
+            inactive_ratio: 1,
+            file_from: None,
+        }
+    }
+}
+
+/// Comma separated swappiness=N, inactive_ratio=N and file_from=N or none,
+/// missing ones keep their defaults
+impl FromStr for Reclaim {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Reclaim, String> {
+        let mut reclaim = Reclaim::default();
+        for part in s.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("active_inactive option '{}' needs a value", part))?;
+            let number = || {
+                value.parse::<u32>().map_err(|_| {
+                    format!("active_inactive {} expects a number, got '{}'", key, value)
+                })
+            };
+            match key {
+                "swappiness" => reclaim.swappiness = number()?,
+                "inactive_ratio" => reclaim.inactive_ratio = number()?,
+                "file_from" if value == "none" => reclaim.file_from = None,
+                "file_from" => reclaim.file_from = Some(number()?),
+                _ => return Err(format!("unknown active_inactive option '{}'", key)),
+            }
+        }
+        if reclaim.swappiness > 200 {
+            return Err(format!(
+                "swappiness must be at most 200, got {}",
+                reclaim.swappiness
+            ));
+        }
+        if reclaim.inactive_ratio == 0 {
+            return Err("inactive_ratio must be at least 1".to_string());
+        }
+        Ok(reclaim)
+    }
+}
+
+/// Lists of anonymous pages, then of file pages
+const ANON: usize = 0;
+const FILE: usize = 1;
+
+/// Active/Inactive lists, the two list approximation of LRU in Linux
+/// New pages start on the inactive list, a second reference while inactive
+/// promotes them to the active list. Reclaim keeps the inactive list at
+/// least 1 / inactive_ratio of the active one by deactivating the oldest
+/// active pages, then evicts the oldest inactive page, activating it
+/// instead if it was referenced. Anonymous and file pages have lists of
+/// their own, swappiness sets how often each is reclaimed
+#[derive(Debug, Default)]
+pub struct ActiveInactive {
+    reclaim: Reclaim,
+    /// Oldest first, by ANON and FILE
+    active: [LinkedSet<PageId>; 2],
+    inactive: [LinkedSet<PageId>; 2],
+    referenced: HashSet<PageId>,
+    /// Pages evicted from each kind so far
+    reclaimed: [u64; 2],
+}
+
+impl ActiveInactive {
+    pub fn new(reclaim: Reclaim) -> ActiveInactive {
+        ActiveInactive {
+            reclaim,
+            ..ActiveInactive::default()
+        }
+    }
+
+    fn kind(&self, page: PageId) -> usize {
+        match self.reclaim.file_from {
+            Some(from) if page.number >= from => FILE,
+            _ => ANON,
+        }
+    }
+
+    /// Kind to reclaim from next, keeping the pages evicted of each in
+    /// proportion to swappiness. None when no page is tracked
+    fn scan(&self) -> Option<usize> {
+        let tracked =
+            |kind: usize| !self.active[kind].is_empty() || !self.inactive[kind].is_empty();
+        let weight = [
+            self.reclaim.swappiness as u64,
+            200 - self.reclaim.swappiness as u64,
+        ];
+        match (tracked(ANON), tracked(FILE)) {
+            (false, false) => None,
+            (true, false) => Some(ANON),
+            (false, true) => Some(FILE),
+            _ if weight[ANON] == 0 => Some(FILE),
+            _ if weight[FILE] == 0 => Some(ANON),
+            // the kind furthest behind its share, file pages on a tie
+            _ if self.reclaimed[ANON] * weight[FILE] < self.reclaimed[FILE] * weight[ANON] => {
+                Some(ANON)
+            }
+            _ => Some(FILE),
+        }
+    }
+
+    /// Deactivate the oldest active pages until the inactive list is large
+    /// enough, they lose their reference bit on the way
+    fn balance(&mut self, kind: usize) {
+        let ratio = self.reclaim.inactive_ratio as usize;
+        while self.inactive[kind].len() * ratio < self.active[kind].len() {
+            let page = self.active[kind].pop_front().unwrap();
+            self.referenced.remove(&page);
+            self.inactive[kind].push_back(page);
+        }
+    }
+}
+
+impl Policy for ActiveInactive {
+    fn hit(&mut self, page: PageId) {
+        let kind = self.kind(page);
+        if self.inactive[kind].contains(&page) && self.referenced.remove(&page) {
+            self.inactive[kind].remove(&page);
+            self.active[kind].push_back(page);
+        } else {
+            self.referenced.insert(page);
+        }
+    }
+
+    fn insert(&mut self, page: PageId) {
+        let kind = self.kind(page);
+        self.inactive[kind].push_back(page);
+    }
+
+    fn evict(&mut self) -> Option<PageId> {
+        loop {
+            let kind = self.scan()?;
+            self.balance(kind);
+            let oldest = self.inactive[kind].pop_front()?;
+            if self.referenced.remove(&oldest) {
+                self.active[kind].push_back(oldest);
+                continue;
+            }
+            self.reclaimed[kind] += 1;
+            return Some(oldest);
+        }
+    }
+
+    /// Skipped pages keep their list, place and reference bit. When every
+    /// inactive page of a kind is skipped the oldest active one is taken,
+    /// then pages of the other kind
+    fn evict_where(&mut self, skip: &dyn Fn(PageId) -> bool) -> Option<PageId> {
+        let first = self.scan()?;
+        for kind in [first, 1 - first].iter().copied() {
+            loop {
+                self.balance(kind);
+                let oldest = match self.inactive[kind].iter().find(|x| !skip(*x)) {
+                    Some(page) => page,
+                    None => break,
+                };
+                self.inactive[kind].remove(&oldest);
+                if self.referenced.remove(&oldest) {
+                    self.active[kind].push_back(oldest);
+                    continue;
+                }
+                self.reclaimed[kind] += 1;
+                return Some(oldest);
+            }
+            let oldest = self.active[kind].iter().find(|x| !skip(*x));
+            if let Some(oldest) = oldest {
+                self.active[kind].remove(&oldest);
+                self.referenced.remove(&oldest);
+                self.reclaimed[kind] += 1;
+                return Some(oldest);
+            }
+        }
+        None
+    }
+
+    fn remove(&mut self, page: PageId) {
+        let kind = self.kind(page);
+        self.active[kind].remove(&page);
+        self.inactive[kind].remove(&page);
+        self.referenced.remove(&page);
+    }
+}
+
+/// Generations of mglru set by generations=N, 4 by default as in Linux
+fn generations(settings: &str) -> Result<u32, String> {
+    let mut generations = 4;
+    for part in settings.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+        generations = match part.split_once('=') {
+            Some(("generations", value)) => value
+                .parse()
+                .map_err(|_| format!("mglru generations expects a number, got '{}'", value))?,
+            _ => return Err(format!("unknown mglru option '{}'", part)),
+        };
+    }
+    if generations < 2 {
+        return Err(format!(
+            "mglru needs at least 2 generations, got {}",
+            generations
+        ));
+    }
+    Ok(generations)
+}
+
+/// Multi-Generational LRU, the reclaim Linux ships since 6.1
+/// Pages sit in generations, a hit only sets the reference bit of the page.
+/// Aging opens a new youngest generation and moves every page whose bit is
+/// set into it, clearing the bit. It runs when the youngest generation holds
+/// its share of the pages and fewer than generations exist, and whenever
+/// eviction would otherwise have to take from the youngest. Eviction takes
+/// the oldest page of the oldest generation, promoting it to the youngest
+/// instead when its bit is set
+///
+/// Referenced pages are also kept in a list of their own, so aging moves
+/// them without walking the generations, one move per page referenced
+/// since the last aging
+#[derive(Debug)]
+pub struct Mglru {
+    generations: u32,
+    /// Oldest generation first, each oldest page first
+    lists: VecDeque<LinkedSet<PageId>>,
+    /// Sequence number of the oldest generation
+    min_seq: u64,
+    /// Sequence number of the generation of every tracked page
+    seq: HashMap<PageId, u64>,
+    /// Pages whose bit is set, in the order they were first referenced
+    referenced: LinkedSet<PageId>,
+}
+
+impl Mglru {
+    pub fn new(generations: u32) -> Mglru {
+        Mglru {
+            generations,
+            lists: VecDeque::from(vec![LinkedSet::new()]),
+            min_seq: 0,
+            seq: HashMap::new(),
+            referenced: LinkedSet::new(),
+        }
+    }
+
+    fn max_seq(&self) -> u64 {
+        self.min_seq + self.lists.len() as u64 - 1
+    }
+
+    fn list(&mut self, page: PageId) -> Option<&mut LinkedSet<PageId>> {
+        let seq = *self.seq.get(&page)?;
+        self.lists.get_mut((seq - self.min_seq) as usize)
+    }
+
+    /// Move page into the youngest generation
+    fn promote(&mut self, page: PageId) {
+        self.referenced.remove(&page);
+        self.seq.insert(page, self.max_seq());
+        self.lists.back_mut().unwrap().push_back(page);
+    }
+
+    /// Open a new youngest generation and promote every referenced page
+    fn age(&mut self) {
+        self.lists.push_back(LinkedSet::new());
+        while let Some(page) = self.referenced.front() {
+            self.list(page).unwrap().remove(&page);
+            self.promote(page);
+        }
+    }
+}
+
+impl Policy for Mglru {
+    fn hit(&mut self, page: PageId) {
+        if self.seq.contains_key(&page) && !self.referenced.contains(&page) {
+            self.referenced.push_back(page);
+        }
+    }
+
+    fn insert(&mut self, page: PageId) {
+        let youngest = self.lists.back().unwrap().len();
+        if youngest > 0
+            && self.lists.len() < self.generations as usize
+            && youngest * self.generations as usize >= self.seq.len()
+        {
+            self.age();
+        }
+        self.promote(page);
+    }
+
+    fn evict(&mut self) -> Option<PageId> {
+        if self.seq.is_empty() {
+            return None;
+        }
+        loop {
+            while self.lists.len() > 1 && self.lists[0].is_empty() {
+                self.lists.pop_front();
+                self.min_seq += 1;
+            }
+            if self.lists.len() == 1 {
+                self.age();
+            }
+            let oldest = match self.lists[0].pop_front() {
+                Some(page) => page,
+                None => continue,
+            };
+            if self.referenced.contains(&oldest) {
+                self.promote(oldest);
+                continue;
+            }
+            self.seq.remove(&oldest);
+            return Some(oldest);
+        }
+    }
+
+    fn remove(&mut self, page: PageId) {
+        if let Some(list) = self.list(page) {
+            list.remove(&page);
+        }
+        self.seq.remove(&page);
+        self.referenced.remove(&page);
+    }
+}
+
+/// Belady's optimal algorithm
+/// Evict the page whose next use is furthest in the future, or that is never
+/// used again. Needs the whole reference string up front, so it is not in
+/// POLICIES and only gives a lower bound for the others on a known trace
+#[derive(Debug, Default)]
+pub struct Opt {
+    /// Positions each page is still referenced at, soonest first
+    uses: HashMap<PageId, VecDeque<Time>>,
+    /// Next use of every resident page
+    next_use: HashMap<PageId, Time>,
+    /// Resident pages ordered by next use, the victim is last
+    by_next_use: BTreeSet<(Time, PageId)>,
+}
+
+/// Next use of a page that is never referenced again
+const NEVER: Time = Time::MAX;
+
+impl Opt {
+    /// Every hit and insert must follow page_hit_order
+    pub fn new(page_hit_order: &[PageId]) -> Opt {
+        let mut uses = HashMap::<PageId, VecDeque<Time>>::new();
+        for (time, page) in page_hit_order.iter().enumerate() {
+            uses.entry(*page).or_default().push_back(time as Time);
+        }
+        Opt {
+            uses,
+            ..Opt::default()
+        }
+    }
+
+    /// page is referenced now, move it to its next use
+    fn touch(&mut self, page: PageId) {
+        let next = match self.uses.get_mut(&page) {
+            Some(uses) => {
+                uses.pop_front();
+                uses.front().copied().unwrap_or(NEVER)
+            }
+            None => NEVER,
+        };
+        if let Some(previous) = self.next_use.insert(page, next) {
+            self.by_next_use.remove(&(previous, page));
+        }
+        self.by_next_use.insert((next, page));
+    }
+}
+
+impl Policy for Opt {
+    fn hit(&mut self, page: PageId) {
+        self.touch(page);
+    }
+
+    fn insert(&mut self, page: PageId) {
+        self.touch(page);
+    }
+
+    fn evict(&mut self) -> Option<PageId> {
+        let (_, page) = self.by_next_use.pop_last()?;
+        self.next_use.remove(&page);
+        Some(page)
+    }
+
+    fn remove(&mut self, page: PageId) {
+        if let Some(next) = self.next_use.remove(&page) {
+            self.by_next_use.remove(&(next, page));
+        }
+    }
+}
+
+/// Set with O(1) removal of a random element
+#[derive(Debug, Default)]
+struct IndexedSet {
+    items: Vec<PageId>,
+    position: HashMap<PageId, usize>,
+}
+
+impl IndexedSet {
+    fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    fn contains(&self, page: PageId) -> bool {
+        self.position.contains_key(&page)
+    }
+
+    fn insert(&mut self, page: PageId) {
+        self.position.insert(page, self.items.len());
+        self.items.push(page);
+    }
+
+    fn remove(&mut self, page: PageId) {
+        if let Some(at) = self.position.remove(&page) {
+            self.items.swap_remove(at);
+            if let Some(moved) = self.items.get(at) {
+                self.position.insert(*moved, at);
+            }
+        }
+    }
+
+    fn remove_random(&mut self, rng: &mut StdRng) -> PageId {
+        let page = self.items[rng.gen_range(0, self.items.len())];
+        self.remove(page);
+        page
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(policy: &mut dyn Policy, pages: &[u32]) {
+        for page in pages {
+            policy.insert(PageId::from(*page));
+        }
+    }
+
+    /// Next victim as a page number of process 0
+    fn evict(policy: &mut dyn Policy) -> Option<u32> {
+        policy.evict().map(|x| x.number)
+    }
+
+    #[test]
+    fn by_name_should_find_every_policy() {
+        for name in POLICIES.iter() {
+            assert!(by_name(name, 0).is_some());
+        }
+        assert!(by_name("LRU", 0).is_some());
+        assert!(by_name("belady", 0).is_none());
+    }
+
+    #[test]
+    fn removed_pages_should_never_be_evicted() {
+        let trace = [0, 1, 2, 3, 4]
+            .iter()
+            .map(|x| PageId::from(*x))
+            .collect::<Vec<_>>();
+        let mut policies = POLICIES
+            .iter()
+            .map(|x| by_name(x, 3).unwrap())
+            .collect::<Vec<Box<dyn Policy>>>();
+        policies.push(Box::new(Opt::new(&trace)));
+        for policy in policies.iter_mut() {
+            load(policy.as_mut(), &[0, 1, 2]);
+            policy.hit(1.into());
+            policy.remove(1.into());
+            policy.remove(7.into());
+            policy.insert(3.into());
+            policy.remove(0.into());
+            policy.insert(4.into());
+            let mut rest = vec![];
+            while let Some(page) = evict(policy.as_mut()) {
+                rest.push(page);
+            }
+            rest.sort_unstable();
+            assert_eq!(rest, vec![2, 3, 4]);
+        }
+    }
+
+    #[test]
+    fn fifo_should_evict_oldest_even_if_hit() {
+        let mut policy = Fifo::new();
+        load(&mut policy, &[0, 3, 5]);
+        policy.hit(0.into());
+        assert_eq!(evict(&mut policy), Some(0));
+        assert_eq!(evict(&mut policy), Some(3));
+    }
+
+    #[test]
+    fn lru_should_evict_least_recently_used() {
+        let mut policy = Lru::new();
+        load(&mut policy, &[2, 1, 0, 3]);
+        policy.hit(2.into());
+        policy.hit(0.into());
+        assert_eq!(evict(&mut policy), Some(1));
+        assert_eq!(evict(&mut policy), Some(3));
+    }
+
+    #[test]
+    fn second_chance_should_skip_referenced() {
+        let mut policy = SecondChance::new();
+        load(&mut policy, &[0, 1, 2]);
+        policy.hit(0.into());
+        policy.hit(1.into());
+        assert_eq!(evict(&mut policy), Some(2));
+        assert_eq!(evict(&mut policy), Some(0));
+    }
+
+    #[test]
+    fn second_chance_all_referenced_should_evict_oldest() {
+        let mut policy = SecondChance::new();
+        load(&mut policy, &[0, 1, 2]);
+        (0..3).for_each(|x| policy.hit(x.into()));
+        assert_eq!(evict(&mut policy), Some(0));
+    }
+
+    #[test]
+    fn clock_should_replace_in_place_and_advance_hand() {
+        let mut policy = Clock::new();
+        load(&mut policy, &[0, 1, 2]);
+        policy.hit(0.into());
+        assert_eq!(evict(&mut policy), Some(1));
+        policy.insert(3.into());
+        // hand moved past 3
+        assert_eq!(evict(&mut policy), Some(2));
+        policy.insert(4.into());
+        // 0 lost its bit on the first sweep
+        assert_eq!(evict(&mut policy), Some(0));
+    }
+
+    #[test]
+    fn clock_should_pass_over_skipped_pages_in_place() {
+        let mut policy = Clock::new();
+        load(&mut policy, &[0, 1, 2, 3]);
+        policy.hit(0.into());
+        let pinned = |page: PageId| page.number == 0;
+        for page in 4..2000 {
+            let victim = policy.evict_where(&pinned).unwrap();
+            assert_ne!(victim, 0.into());
+            policy.insert(page.into());
+        }
+        assert_eq!(policy.slots.len(), 4);
+        // 0 kept its slot and its bit
+        assert_eq!(policy.slots[0], Some((0.into(), true)));
+        assert_eq!(policy.evict_where(&|_| true), None);
+    }
+
+    #[test]
+    fn skipped_pages_should_keep_their_lru_place() {
+        let mut policy = Lru::new();
+        load(&mut policy, &[0, 1, 2]);
+        let pinned = |page: PageId| page.number == 0;
+        assert_eq!(policy.evict_where(&pinned), Some(1.into()));
+        // 0 is still the least recently used
+        assert_eq!(evict(&mut policy), Some(0));
+        assert_eq!(policy.evict_where(&|_| true), None);
+    }
+
+    #[test]
+    fn skipped_pages_should_keep_their_active_inactive_list() {
+        let mut policy = ActiveInactive::new(Reclaim::default());
+        load(&mut policy, &[0, 1, 2, 3]);
+        // 1 is activated by its second reference, 0 only referenced
+        policy.hit(0.into());
+        policy.hit(1.into());
+        policy.hit(1.into());
+        let pinned = |page: PageId| page.number < 2;
+        assert_eq!(policy.evict_where(&pinned), Some(2.into()));
+        let inactive = policy.inactive[ANON].iter().collect::<Vec<PageId>>();
+        assert_eq!(inactive, vec![0.into(), 3.into()]);
+        assert!(policy.active[ANON].contains(&1.into()));
+        assert!(policy.referenced.contains(&0.into()));
+        // with only skipped inactive pages left, the oldest active page goes
+        let pinned = |page: PageId| page.number != 1;
+        assert_eq!(policy.evict_where(&pinned), Some(1.into()));
+        assert_eq!(policy.evict_where(&|_| true), None);
+    }
+
+    #[test]
+    fn skipped_pages_should_keep_their_nru_class() {
+        for seed in 0..20 {
+            let mut policy = Nru::new(seed);
+            load(&mut policy, &[0, 1, 2]);
+            policy.hit(1.into());
+            let pinned = |page: PageId| page.number == 0;
+            assert_eq!(policy.evict_where(&pinned), Some(2.into()));
+            assert_eq!(policy.evict_where(&pinned), Some(1.into()));
+            assert_eq!(policy.evict_where(&pinned), None);
+            assert_eq!(evict(&mut policy), Some(0));
+        }
+    }
+
+    #[test]
+    fn nru_should_evict_unreferenced_first() {
+        let mut policy = Nru::new(1);
+        load(&mut policy, &[4, 2, 1, 0]);
+        policy.hit(4.into());
+        policy.hit(2.into());
+        policy.hit(1.into());
+        assert_eq!(evict(&mut policy), Some(0));
+        let next = evict(&mut policy).unwrap();
+        assert!([4, 2, 1].contains(&next));
+    }
+
+    #[test]
+    fn nru_should_evict_clean_pages_before_dirty_ones() {
+        for seed in 0..20 {
+            let mut policy = Nru::new(seed);
+            load(&mut policy, &[0, 1, 2, 3]);
+            policy.modified(0.into(), true);
+            policy.modified(1.into(), true);
+            policy.hit(1.into());
+            policy.hit(2.into());
+            // 3 is clean, 0 dirty, 2 referenced and 1 both
+            assert_eq!(evict(&mut policy), Some(3));
+            assert_eq!(evict(&mut policy), Some(0));
+            assert_eq!(evict(&mut policy), Some(2));
+            policy.modified(1.into(), false);
+            policy.insert(4.into());
+            policy.hit(4.into());
+            policy.modified(4.into(), true);
+            // 1 was written back
+            assert_eq!(evict(&mut policy), Some(1));
+            assert_eq!(evict(&mut policy), Some(4));
+        }
+    }
+
+    #[test]
+    fn opt_should_evict_furthest_next_use() {
+        let trace = [0, 1, 2, 0, 3, 1, 4];
+        let mut policy = Opt::new(&trace.map(PageId::from));
+        load(&mut policy, &[0, 1, 2]);
+        policy.hit(0.into());
+        // 2 is never used again
+        assert_eq!(evict(&mut policy), Some(2));
+        policy.insert(3.into());
+        // 0 and 3 are never used again, 1 is next at time 5
+        assert!([0, 3].contains(&evict(&mut policy).unwrap()));
+        assert_eq!(Opt::new(&[]).evict(), None);
+    }
+
+    #[test]
+    fn active_inactive_should_promote_pages_referenced_twice() {
+        let mut policy = ActiveInactive::new(Reclaim::default());
+        load(&mut policy, &[0, 1, 2, 3]);
+        policy.hit(0.into());
+        policy.hit(0.into());
+        policy.hit(1.into());
+        // 0 is active, 1 gets activated instead of evicted
+        assert_eq!(evict(&mut policy), Some(2));
+        assert_eq!(evict(&mut policy), Some(3));
+        // the inactive list is empty, so the oldest active page is deactivated
+        assert_eq!(evict(&mut policy), Some(0));
+        assert_eq!(evict(&mut policy), Some(1));
+        assert_eq!(evict(&mut policy), None);
+    }
+
+    #[test]
+    fn inactive_ratio_should_bound_the_active_list() {
+        let reclaim = "inactive_ratio=3".parse().unwrap();
+        let mut policy = ActiveInactive::new(reclaim);
+        load(&mut policy, &[0, 1, 2, 3, 4]);
+        for page in [0, 1, 2, 3].iter() {
+            policy.hit((*page).into());
+            policy.hit((*page).into());
+        }
+        // one inactive page may face three active ones
+        assert_eq!(evict(&mut policy), Some(4));
+        assert_eq!(evict(&mut policy), Some(0));
+        let mut policy = ActiveInactive::new(Reclaim::default());
+        load(&mut policy, &[0, 1, 2, 3, 4]);
+        for page in [0, 1, 2, 3].iter() {
+            policy.hit((*page).into());
+            policy.hit((*page).into());
+        }
+        // with a ratio of 1, 0 and 1 are deactivated first
+        assert_eq!(evict(&mut policy), Some(4));
+        assert_eq!(evict(&mut policy), Some(0));
+        assert_eq!(evict(&mut policy), Some(1));
+    }
+
+    #[test]
+    fn swappiness_should_split_reclaim_between_anon_and_file() {
+        let victims = |swappiness: u32| {
+            let spec = format!("swappiness={},file_from=100", swappiness);
+            let mut policy = ActiveInactive::new(spec.parse().unwrap());
+            load(&mut policy, &(0..20).collect::<Vec<u32>>());
+            load(&mut policy, &(100..120).collect::<Vec<u32>>());
+            (0..10)
+                .filter(|_| evict(&mut policy).unwrap() >= 100)
+                .count()
+        };
+        assert_eq!(victims(0), 10);
+        assert_eq!(victims(100), 5);
+        assert_eq!(victims(60), 7);
+        assert_eq!(victims(200), 0);
+    }
+
+    #[test]
+    fn mglru_should_evict_the_oldest_generation() {
+        let mut policy = Mglru::new(2);
+        load(&mut policy, &[0, 1, 2, 3]);
+        // 1 opened the second generation, the rest joined it
+        assert_eq!(policy.lists.len(), 2);
+        policy.hit(0.into());
+        policy.hit(2.into());
+        // 0 is promoted out of the oldest generation, 1 is next to go
+        assert_eq!(evict(&mut policy), Some(1));
+        assert_eq!(evict(&mut policy), Some(3));
+        // aging promoted 2 when the first generation ran out, 0 is older
+        assert_eq!(evict(&mut policy), Some(0));
+        assert_eq!(evict(&mut policy), Some(2));
+        assert_eq!(evict(&mut policy), None);
+    }
+
+    #[test]
+    fn mglru_aging_should_keep_referenced_pages() {
+        let mut policy = Mglru::new(4);
+        load(&mut policy, &(0..8).collect::<Vec<u32>>());
+        assert_eq!(policy.lists.len(), 4);
+        for page in [0, 1, 2].iter() {
+            policy.hit((*page).into());
+        }
+        let victims = (0..5)
+            .map(|_| evict(&mut policy).unwrap())
+            .collect::<Vec<u32>>();
+        assert_eq!(victims, vec![3, 4, 5, 6, 7]);
+        assert!(policy.lists.len() <= 4);
+    }
+
+    #[test]
+    fn mglru_aging_should_promote_in_reference_order() {
+        let mut policy = Mglru::new(2);
+        load(&mut policy, &[0, 1, 2, 3]);
+        policy.hit(3.into());
+        policy.hit(1.into());
+        let victims = (0..4)
+            .map(|_| evict(&mut policy).unwrap())
+            .collect::<Vec<u32>>();
+        // aging moved only 3 and then 1, leaving 2 oldest
+        assert_eq!(victims, vec![0, 2, 3, 1]);
+    }
+
+    #[test]
+    fn from_spec_should_read_settings() {
+        assert!(from_spec("active_inactive:swappiness=0,inactive_ratio=4", 0).is_ok());
+        assert!(from_spec("Active_Inactive", 0).is_ok());
+        let err = |spec: &str| from_spec(spec, 0).err().unwrap();
+        assert_eq!(
+            err("lru:swappiness=1"),
+            "lru takes no settings, got 'swappiness=1'"
+        );
+        assert_eq!(err("belady"), "unknown algorithm 'belady'");
+        assert_eq!(
+            err("active_inactive:swappiness=201"),
+            "swappiness must be at most 200, got 201"
+        );
+        assert!(from_spec("active_inactive:inactive_ratio=0", 0).is_err());
+        assert!(from_spec("active_inactive:ratio=2", 0).is_err());
+        assert!(from_spec("mglru:generations=8", 0).is_ok());
+        assert_eq!(
+            err("mglru:generations=1"),
+            "mglru needs at least 2 generations, got 1"
+        );
+        assert!(from_spec("mglru:tiers=2", 0).is_err());
+        assert_eq!("file_from=8".parse::<Reclaim>().unwrap().file_from, Some(8));
+    }
+
+    #[test]
+    fn empty_policy_should_have_no_victim() {
+        for name in POLICIES.iter() {
+            assert_eq!(by_name(name, 0).unwrap().evict(), None);
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use crate::simulation::SHARED_PAGES;
+use crate::{PageId, Pid};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Which pages to read along with the one a reference needs
+///
+/// Prefetched pages take frames like any other, evicting by the policy when
+/// memory is full. They save a fault when a reference finds them resident
+/// and pollute memory when they leave it unused
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Prefetch {
+    /// Demand paging, only the referenced page is ever read
+    #[default]
+    Off,
+    /// Every fault also reads the window pages that follow it
+    Sequential { window: u32 },
+    /// Readahead as Linux does it: a fault continuing the stream of its
+    /// process, at or just past the last window read, doubles the window up
+    /// to max. A fault anywhere else starts over at initial
+    Adaptive { initial: u32, max: u32 },
+    /// Once two references of a process in a row are the same distance
+    /// apart, every reference reads the degree pages further along that
+    /// stride
+    Stride { degree: u32 },
+}
+
+impl Prefetch {
+    fn validate(self) -> Result<Prefetch, String> {
+        match self {
+            Prefetch::Sequential { window: 0 } => {
+                Err("prefetch window must be at least 1".to_string())
+            }
+            Prefetch::Adaptive { initial, max } if initial == 0 || max < initial => Err(format!(
+                "prefetch needs 1 <= initial <= max, got initial={} max={}",
+                initial, max
+            )),
+            Prefetch::Stride { degree: 0 } => Err("prefetch degree must be at least 1".to_string()),
+            _ => Ok(self),
+        }
+    }
+}
+
+impl fmt::Display for Prefetch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Prefetch::Off => write!(f, "off"),
+            Prefetch::Sequential { window } => write!(f, "sequential(window={})", window),
+            Prefetch::Adaptive { initial, max } => {
+                write!(f, "adaptive(initial={},max={})", initial, max)
+            }
+            Prefetch::Stride { degree } => write!(f, "stride(degree={})", degree),
+        }
+    }
+}
+
+/// 'off', or 'sequential', 'adaptive' or 'stride' optionally followed by
+/// settings as in 'sequential:window=4', 'adaptive:initial=4,max=32' and
+/// 'stride:degree=2'
+impl FromStr for Prefetch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Prefetch, String> {
+        let (kind, settings) = s.split_once(':').unwrap_or((s, ""));
+        let mut prefetch = match kind {
+            "off" if settings.is_empty() => return Ok(Prefetch::Off),
+            "sequential" => Prefetch::Sequential { window: 4 },
+            "adaptive" => Prefetch::Adaptive {
+                initial: 4,
+                max: 32,
+            },
+            "stride" => Prefetch::Stride { degree: 2 },
+            _ => {
+                return Err(format!(
+                    "unknown prefetch '{}', expected off, sequential, adaptive or stride",
+                    s
+                ))
+            }
+        };
+        for part in settings.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("prefetch option '{}' needs a value", part))?;
+            let number = value
+                .parse::<u32>()
+                .map_err(|_| format!("prefetch {} expects a number, got '{}'", key, value))?;
+            match (&mut prefetch, key) {
+                (Prefetch::Sequential { window }, "window") => *window = number,
+                (Prefetch::Adaptive { initial, .. }, "initial") => *initial = number,
+                (Prefetch::Adaptive { max, .. }, "max") => *max = number,
+                (Prefetch::Stride { degree }, "degree") => *degree = number,
+                _ => return Err(format!("unknown {} option '{}'", kind, key)),
+            }
+        }
+        prefetch.validate()
+    }
+}
+
+/// What a process's references have shown so far
+#[derive(Debug, Default)]
+struct Stream {
+    /// Page of the last fault, or of the last reference for stride
+    last: Option<u32>,
+    /// Distance between the last two references
+    stride: i64,
+    /// First page after the last readahead window, and that window
+    next: u32,
+    window: u32,
+}
+
+/// Picks the pages to prefetch, learning the streams of every process
+#[derive(Debug)]
+pub struct Prefetcher {
+    prefetch: Prefetch,
+    streams: HashMap<Pid, Stream>,
+}
+
+impl Prefetcher {
+    pub fn new(prefetch: Prefetch) -> Prefetcher {
+        Prefetcher {
+            prefetch,
+            streams: HashMap::new(),
+        }
+    }
+
+    /// Pages of page's process to read after a reference to page, fault
+    /// tells whether it faulted. Some may be resident already
+    pub fn pages(&mut self, page: PageId, fault: bool) -> Vec<PageId> {
+        let stream = self.streams.entry(page.process).or_default();
+        let number = page.number;
+        let (first, count, step) = match self.prefetch {
+            Prefetch::Off => return vec![],
+            Prefetch::Sequential { window } if fault => (number as i64 + 1, window, 1),
+            Prefetch::Adaptive { initial, max } if fault => {
+                let sequential = stream
+                    .last
+                    .is_some_and(|last| last < number && number <= stream.next);
+                stream.window = if sequential {
+                    (stream.window * 2).min(max)
+                } else {
+                    initial
+                };
+                stream.last = Some(number);
+                stream.next = number.saturating_add(stream.window + 1);
+                (number as i64 + 1, stream.window, 1)
+            }
+            Prefetch::Stride { degree } => {
+                let stride = match stream.last {
+                    Some(last) => number as i64 - last as i64,
+                    None => 0,
+                };
+                // a page referenced again says nothing about the stride
+                if stride == 0 && stream.last.is_some() {
+                    return vec![];
+                }
+                let confirmed = stride != 0 && stride == stream.stride;
+                stream.last = Some(number);
+                stream.stride = stride;
+                if !confirmed {
+                    return vec![];
+                }
+                (number as i64 + stride, degree, stride)
+            }
+            _ => return vec![],
+        };
+        (0..count as i64)
+            .map(|i| first + i * step)
+            .take_while(|x| (0..SHARED_PAGES as i64).contains(x))
+            .map(|x| PageId::new(page.process, x as u32))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbers(pages: Vec<PageId>) -> Vec<u32> {
+        pages.into_iter().map(|x| x.number).collect()
+    }
+
+    #[test]
+    fn sequential_should_read_the_window_after_faults() {
+        let mut prefetcher = Prefetcher::new("sequential:window=3".parse().unwrap());
+        assert_eq!(numbers(prefetcher.pages(5.into(), true)), vec![6, 7, 8]);
+        assert!(prefetcher.pages(6.into(), false).is_empty());
+        let last = PageId::new(2, SHARED_PAGES - 2);
+        assert_eq!(
+            numbers(prefetcher.pages(last, true)),
+            vec![SHARED_PAGES - 1]
+        );
+    }
+
+    #[test]
+    fn adaptive_should_ramp_on_streams_and_reset_elsewhere() {
+        let mut prefetcher = Prefetcher::new("adaptive:initial=2,max=8".parse().unwrap());
+        assert_eq!(numbers(prefetcher.pages(0.into(), true)), vec![1, 2]);
+        assert_eq!(numbers(prefetcher.pages(3.into(), true)).len(), 4);
+        assert_eq!(numbers(prefetcher.pages(8.into(), true)).len(), 8);
+        assert_eq!(numbers(prefetcher.pages(17.into(), true)).len(), 8);
+        // process 1 has a stream of its own
+        assert_eq!(prefetcher.pages(PageId::new(1, 18), true).len(), 2);
+        assert_eq!(numbers(prefetcher.pages(100.into(), true)), vec![101, 102]);
+    }
+
+    #[test]
+    fn stride_should_wait_for_a_repeated_stride() {
+        let mut prefetcher = Prefetcher::new("stride:degree=2".parse().unwrap());
+        assert!(prefetcher.pages(10.into(), true).is_empty());
+        assert!(prefetcher.pages(7.into(), true).is_empty());
+        assert!(prefetcher.pages(7.into(), false).is_empty());
+        assert_eq!(numbers(prefetcher.pages(4.into(), false)), vec![1]);
+        assert!(prefetcher.pages(9.into(), true).is_empty());
+    }
+
+    #[test]
+    fn parse_should_fill_defaults_and_reject_bad_settings() {
+        assert_eq!("off".parse(), Ok(Prefetch::Off));
+        assert_eq!("sequential".parse(), Ok(Prefetch::Sequential { window: 4 }));
+        let res = "adaptive:max=64".parse::<Prefetch>().unwrap();
+        assert_eq!(res.to_string(), "adaptive(initial=4,max=64)");
+        assert_eq!(
+            "stride:degree=3".parse(),
+            Ok(Prefetch::Stride { degree: 3 })
+        );
+        assert!("sequential:window=0".parse::<Prefetch>().is_err());
+        assert!("adaptive:initial=8,max=4".parse::<Prefetch>().is_err());
+        assert!("stride:window=2".parse::<Prefetch>().is_err());
+        assert!("off:window=2".parse::<Prefetch>().is_err());
+        assert!("markov".parse::<Prefetch>().is_err());
+    }
+}
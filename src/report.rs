@@ -0,0 +1,954 @@
+use crate::huge::HugeReport;
+use crate::swap::SwapReport;
+use crate::tier::TierReport;
+use crate::tlb::TranslationReport;
+use crate::zswap::PoolReport;
+use crate::{MemoryPage, PageId, Pid, Time};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+
+/// How many references after an eviction a fault on the same page still
+/// counts as a premature eviction
+pub const DEFAULT_PREMATURE_WINDOW: Time = 10;
+
+/// Summary of a distribution of reference counts (ages, residency times)
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Summary {
+    pub count: u64,
+    pub min: u64,
+    pub max: u64,
+    pub mean: f64,
+    pub median: u64,
+}
+
+impl Summary {
+    /// None when there is nothing to summarize
+    pub fn of(values: &[u64]) -> Option<Summary> {
+        values
+            .iter()
+            .fold(Histogram::new(), |mut histogram, x| {
+                histogram.add(*x);
+                histogram
+            })
+            .summary()
+    }
+}
+
+/// How often each value occurred
+///
+/// Memory grows with the number of distinct values, not with how many were added
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Histogram {
+    counts: BTreeMap<u64, u64>,
+    count: u64,
+    sum: u128,
+}
+
+impl Histogram {
+    pub fn new() -> Histogram {
+        Histogram::default()
+    }
+
+    pub fn add(&mut self, value: u64) {
+        *self.counts.entry(value).or_insert(0) += 1;
+        self.count += 1;
+        self.sum += value as u128;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// (value, times it occurred) in increasing order of value
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.counts.iter().map(|(value, count)| (*value, *count))
+    }
+
+    pub fn summary(&self) -> Option<Summary> {
+        let min = *self.counts.keys().next()?;
+        let max = *self.counts.keys().next_back()?;
+        let mut seen = 0;
+        let median = self
+            .iter()
+            .find(|(_, count)| {
+                seen += count;
+                seen > self.count / 2
+            })
+            .map_or(max, |(value, _)| value);
+        Some(Summary {
+            count: self.count,
+            min,
+            max,
+            mean: self.sum as f64 / self.count as f64,
+            median,
+        })
+    }
+}
+
+/// Price of each event, in whatever unit the caller picks (usually nanoseconds)
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct CostModel {
+    pub hit: f64,
+    pub fault: f64,
+    /// Extra paid on a fault that had to evict a page first
+    pub eviction: f64,
+}
+
+impl Default for CostModel {
+    /// Memory access against a disk backed fault
+    fn default() -> Self {
+        CostModel {
+            hit: 100.0,
+            fault: 8_000_000.0,
+            eviction: 0.0,
+        }
+    }
+}
+
+/// Counters of the references a single process made
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct ProcessReport {
+    pub(crate) hits: u64,
+    pub(crate) faults: u64,
+    pub(crate) compulsory: u64,
+    /// Faults of writes to a shared page, which got a private copy
+    pub(crate) cow: u64,
+    pub(crate) removed: u64,
+    /// Frames set aside for the process under local replacement, each with
+    /// the time it took effect, empty when the process shared every frame
+    pub(crate) allocations: Vec<(Time, u32)>,
+    /// Times load control swapped the process out
+    pub(crate) suspensions: u64,
+}
+
+impl ProcessReport {
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn faults(&self) -> u64 {
+        self.faults
+    }
+
+    /// Pages of this process that were evicted, whichever process faulted
+    pub fn removed_count(&self) -> u64 {
+        self.removed
+    }
+
+    pub fn references(&self) -> u64 {
+        self.hits + self.faults
+    }
+
+    pub fn hit_ratio(&self) -> f64 {
+        ratio(self.hits, self.references())
+    }
+
+    pub fn fault_ratio(&self) -> f64 {
+        ratio(self.faults, self.references())
+    }
+
+    pub fn compulsory_faults(&self) -> u64 {
+        self.compulsory
+    }
+
+    /// Faults that were neither compulsory nor copies on write
+    pub fn capacity_faults(&self) -> u64 {
+        self.faults - self.compulsory - self.cow
+    }
+
+    pub fn cow_faults(&self) -> u64 {
+        self.cow
+    }
+
+    /// Frames the process had to itself at the end, None when it shared
+    /// every frame
+    pub fn frames(&self) -> Option<u32> {
+        self.allocations.last().map(|(_, x)| *x)
+    }
+
+    /// Every change of the process's own frames, the first one its starting
+    /// share at time 0
+    pub fn allocations(&self) -> &[(Time, u32)] {
+        &self.allocations
+    }
+
+    pub fn min_frames(&self) -> Option<u32> {
+        self.allocations.iter().map(|(_, x)| *x).min()
+    }
+
+    pub fn max_frames(&self) -> Option<u32> {
+        self.allocations.iter().map(|(_, x)| *x).max()
+    }
+
+    pub fn suspensions(&self) -> u64 {
+        self.suspensions
+    }
+}
+
+/// Running statistics of a simulation
+///
+/// Time is measured in references: the page loaded by the n-th reference
+/// of a trace was loaded at time n
+#[derive(PartialEq, Debug, Clone)]
+pub struct PageReport {
+    pub(crate) hits: u64,
+    pub(crate) faults: u64,
+    pub(crate) removed: u64,
+    pub(crate) compulsory: u64,
+    /// Faults of writes to a shared page, which got a private copy
+    pub(crate) cow: u64,
+    pub(crate) premature: u64,
+    pub(crate) premature_window: Time,
+    pub(crate) victim_ages: Histogram,
+    pub(crate) residency_times: Histogram,
+    /// One entry per distinct page that ever left memory
+    pub(crate) resident_per_page: BTreeMap<PageId, Time>,
+    /// Pages evicted within the premature window and not faulted back yet,
+    /// by the time they were evicted. Older evictions cannot be premature
+    pub(crate) evicted_at: HashMap<PageId, Time>,
+    /// The same evictions in the order they happened, to expire them
+    pub(crate) evictions: VecDeque<(Time, PageId)>,
+    pub(crate) processes: BTreeMap<Pid, ProcessReport>,
+    /// Load control windows that found memory thrashing
+    pub(crate) thrashing: u64,
+    /// TLB and page walk counters, when references were translated
+    pub(crate) translation: Option<TranslationReport>,
+    /// Pages read ahead of any reference, the ones a reference found
+    /// resident, and the ones that left memory or the run unused
+    pub(crate) prefetches: u64,
+    pub(crate) useful_prefetches: u64,
+    pub(crate) wasted_prefetches: u64,
+    /// Dirty pages evicted, each written to the backing store
+    pub(crate) write_backs: u64,
+    /// Faults that read a page evicted before back from the backing store
+    pub(crate) swap_ins: u64,
+    /// Device time and requests, when a swap device was simulated
+    pub(crate) swap: Option<SwapReport>,
+    /// Pages faults and their readahead evicted themselves for want of a
+    /// free frame, and pages the page-out daemon evicted ahead of them
+    pub(crate) direct_reclaims: u64,
+    pub(crate) background_reclaims: u64,
+    /// Times the page-out daemon woke up below its low watermark
+    pub(crate) kswapd_wakeups: u64,
+    /// Hits and migrations of the slower tiers, when memory had some
+    pub(crate) tiers: Option<TierReport>,
+    /// Hits and evictions of the compressed pool, when memory had one
+    pub(crate) pool: Option<PoolReport>,
+    /// Collapses, splits and waste of huge pages, when memory had them
+    pub(crate) huge: Option<HugeReport>,
+}
+
+impl PageReport {
+    pub fn new() -> PageReport {
+        PageReport {
+            faults: 0,
+            hits: 0,
+            removed: 0,
+            compulsory: 0,
+            cow: 0,
+            premature: 0,
+            premature_window: DEFAULT_PREMATURE_WINDOW,
+            victim_ages: Histogram::new(),
+            residency_times: Histogram::new(),
+            resident_per_page: BTreeMap::new(),
+            evicted_at: HashMap::new(),
+            evictions: VecDeque::new(),
+            processes: BTreeMap::new(),
+            thrashing: 0,
+            translation: None,
+            prefetches: 0,
+            useful_prefetches: 0,
+            wasted_prefetches: 0,
+            write_backs: 0,
+            swap_ins: 0,
+            swap: None,
+            direct_reclaims: 0,
+            background_reclaims: 0,
+            kswapd_wakeups: 0,
+            tiers: None,
+            pool: None,
+            huge: None,
+        }
+    }
+
+    pub fn with_premature_window(self, premature_window: Time) -> PageReport {
+        PageReport {
+            premature_window,
+            ..self
+        }
+    }
+
+    /// A fault on page at time now
+    /// first_touch marks a compulsory fault, the page has never been referenced before
+    pub(crate) fn fault(mut self, page: PageId, now: Time, first_touch: bool) -> PageReport {
+        let process = self.processes.entry(page.process).or_default();
+        process.faults += 1;
+        process.compulsory += first_touch as u64;
+        if let Some(evicted) = self.evicted_at.remove(&page) {
+            if now - evicted <= self.premature_window {
+                self.premature += 1;
+            }
+        }
+        PageReport {
+            faults: self.faults + 1,
+            compulsory: self.compulsory + first_touch as u64,
+            ..self
+        }
+    }
+
+    /// A write to page at time now found it shared and faulted to copy it
+    pub(crate) fn cow_fault(mut self, page: PageId, now: Time) -> PageReport {
+        self.processes.entry(page.process).or_default().cow += 1;
+        PageReport {
+            cow: self.cow + 1,
+            ..self.fault(page, now, false)
+        }
+    }
+
+    /// process runs in frames frames of its own from time now on
+    pub(crate) fn allocated(mut self, process: Pid, frames: u32, now: Time) -> PageReport {
+        let allocations = &mut self.processes.entry(process).or_default().allocations;
+        allocations.push((now, frames));
+        self
+    }
+
+    /// Load control swapped process out
+    pub(crate) fn suspended(mut self, process: Pid) -> PageReport {
+        self.processes.entry(process).or_default().suspensions += 1;
+        self
+    }
+
+    /// Load control found memory thrashing in thrashing of its windows
+    pub(crate) fn with_thrashing_windows(self, thrashing: u64) -> PageReport {
+        PageReport { thrashing, ..self }
+    }
+
+    pub(crate) fn with_translation(self, translation: TranslationReport) -> PageReport {
+        PageReport {
+            translation: Some(translation),
+            ..self
+        }
+    }
+
+    pub(crate) fn with_swap(self, swap: SwapReport) -> PageReport {
+        PageReport {
+            swap: Some(swap),
+            ..self
+        }
+    }
+
+    pub(crate) fn with_tiers(self, tiers: TierReport) -> PageReport {
+        PageReport {
+            tiers: Some(tiers),
+            ..self
+        }
+    }
+
+    pub(crate) fn with_pool(self, pool: PoolReport) -> PageReport {
+        PageReport {
+            pool: Some(pool),
+            ..self
+        }
+    }
+
+    pub(crate) fn with_huge(self, huge: HugeReport) -> PageReport {
+        PageReport {
+            huge: Some(huge),
+            ..self
+        }
+    }
+
+    /// page left the slowest tier or the pool, it was written back if dirty
+    pub(crate) fn pushed_out(self, page: &MemoryPage) -> PageReport {
+        PageReport {
+            write_backs: self.write_backs + page.is_modified() as u64,
+            ..self
+        }
+    }
+
+    /// A fault, or a page read ahead with it, had to evict a page to get a
+    /// frame
+    pub(crate) fn direct_reclaim(self) -> PageReport {
+        PageReport {
+            direct_reclaims: self.direct_reclaims + 1,
+            ..self
+        }
+    }
+
+    /// The page-out daemon evicted a page between references
+    pub(crate) fn background_reclaim(self) -> PageReport {
+        PageReport {
+            background_reclaims: self.background_reclaims + 1,
+            ..self
+        }
+    }
+
+    pub(crate) fn kswapd_woken(self) -> PageReport {
+        PageReport {
+            kswapd_wakeups: self.kswapd_wakeups + 1,
+            ..self
+        }
+    }
+
+    /// A page was read ahead of any reference to it
+    pub(crate) fn prefetched(self) -> PageReport {
+        PageReport {
+            prefetches: self.prefetches + 1,
+            ..self
+        }
+    }
+
+    /// The last fault read its page back from the backing store
+    pub(crate) fn swapped_in(self) -> PageReport {
+        PageReport {
+            swap_ins: self.swap_ins + 1,
+            ..self
+        }
+    }
+
+    /// A reference found a prefetched page resident
+    pub(crate) fn prefetch_used(self) -> PageReport {
+        PageReport {
+            useful_prefetches: self.useful_prefetches + 1,
+            ..self
+        }
+    }
+
+    /// count prefetched pages were never referenced while resident
+    pub(crate) fn prefetch_wasted(self, count: u64) -> PageReport {
+        PageReport {
+            wasted_prefetches: self.wasted_prefetches + count,
+            ..self
+        }
+    }
+
+    pub(crate) fn hit(mut self, page: PageId) -> PageReport {
+        self.processes.entry(page.process).or_default().hits += 1;
+        PageReport {
+            hits: self.hits + 1,
+            ..self
+        }
+    }
+
+    /// victim was evicted from memory at time now
+    pub(crate) fn removed(mut self, victim: &MemoryPage, now: Time) -> PageReport {
+        self.victim_ages.add(now - victim.last_used);
+        self.expire(now);
+        self.evicted_at.insert(victim.id(), now);
+        self.evictions.push_back((now, victim.id()));
+        self.processes
+            .entry(victim.id().process)
+            .or_default()
+            .removed += 1;
+        self.left_memory(victim, now);
+        PageReport {
+            removed: self.removed + 1,
+            write_backs: self.write_backs + victim.is_modified() as u64,
+            ..self
+        }
+    }
+
+    /// Close out the residency of every page still in memory at the end of a run
+    pub fn finish(mut self, page_frames: &[MemoryPage], now: Time) -> PageReport {
+        for page in page_frames {
+            self.left_memory(page, now);
+        }
+        self
+    }
+
+    /// Forget evictions too long ago for a fault at now to be premature
+    fn expire(&mut self, now: Time) {
+        while let Some((evicted, page)) = self.evictions.front().copied() {
+            if now - evicted <= self.premature_window {
+                break;
+            }
+            self.evictions.pop_front();
+            // the page may have been evicted again since
+            if self.evicted_at.get(&page) == Some(&evicted) {
+                self.evicted_at.remove(&page);
+            }
+        }
+    }
+
+    fn left_memory(&mut self, page: &MemoryPage, now: Time) {
+        let resident = now - page.loaded_at;
+        self.residency_times.add(resident);
+        *self.resident_per_page.entry(page.id()).or_insert(0) += resident;
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn faults(&self) -> u64 {
+        self.faults
+    }
+
+    pub fn removed_count(&self) -> u64 {
+        self.removed
+    }
+
+    pub fn references(&self) -> u64 {
+        self.hits + self.faults
+    }
+
+    pub fn hit_ratio(&self) -> f64 {
+        ratio(self.hits, self.references())
+    }
+
+    pub fn fault_ratio(&self) -> f64 {
+        ratio(self.faults, self.references())
+    }
+
+    /// Faults on pages touched for the very first time
+    pub fn compulsory_faults(&self) -> u64 {
+        self.compulsory
+    }
+
+    /// Faults that were neither compulsory nor copies on write
+    pub fn capacity_faults(&self) -> u64 {
+        self.faults - self.compulsory - self.cow
+    }
+
+    pub fn cow_faults(&self) -> u64 {
+        self.cow
+    }
+
+    /// Evictions whose page faulted back in within the premature window
+    pub fn premature_evictions(&self) -> u64 {
+        self.premature
+    }
+
+    pub fn premature_window(&self) -> Time {
+        self.premature_window
+    }
+
+    /// References since each evicted page was last used
+    pub fn victim_ages(&self) -> &Histogram {
+        &self.victim_ages
+    }
+
+    pub fn victim_age_summary(&self) -> Option<Summary> {
+        self.victim_ages.summary()
+    }
+
+    /// Length of every stay in memory, in references
+    pub fn residency_times(&self) -> &Histogram {
+        &self.residency_times
+    }
+
+    pub fn residency_summary(&self) -> Option<Summary> {
+        self.residency_times.summary()
+    }
+
+    pub fn cost(&self, model: &CostModel) -> f64 {
+        self.hits as f64 * model.hit
+            + self.faults as f64 * model.fault
+            + self.removed as f64 * model.eviction
+    }
+
+    /// Average cost of a single reference
+    pub fn effective_access_time(&self, model: &CostModel) -> f64 {
+        if self.references() == 0 {
+            return 0.0;
+        }
+        self.cost(model) / self.references() as f64
+    }
+
+    /// References completed per million units of simulated time, the time
+    /// being the cost of the run under model: per millisecond when the model
+    /// is in nanoseconds
+    pub fn throughput(&self, model: &CostModel) -> f64 {
+        let time = self.cost(model);
+        if time == 0.0 {
+            return 0.0;
+        }
+        self.references() as f64 * 1e6 / time
+    }
+
+    /// Times load control swapped a process out
+    pub fn suspensions(&self) -> u64 {
+        self.processes.values().map(|x| x.suspensions).sum()
+    }
+
+    /// Load control windows that found memory thrashing
+    pub fn thrashing_windows(&self) -> u64 {
+        self.thrashing
+    }
+
+    pub fn translation(&self) -> Option<&TranslationReport> {
+        self.translation.as_ref()
+    }
+
+    /// effective_access_time plus what translating costs: a TLB lookup for
+    /// every reference and a memory access for every entry a page walk read
+    /// None when references were not translated
+    pub fn combined_access_time(&self, model: &CostModel) -> Option<f64> {
+        let translation = self.translation?;
+        if self.references() == 0 {
+            return Some(0.0);
+        }
+        let walks = translation.walk_references as f64 * model.hit / self.references() as f64;
+        Some(self.effective_access_time(model) + translation.lookup + walks)
+    }
+
+    /// Dirty pages that had to be written back as they were evicted
+    pub fn write_backs(&self) -> u64 {
+        self.write_backs
+    }
+
+    pub fn swap(&self) -> Option<&SwapReport> {
+        self.swap.as_ref()
+    }
+
+    /// Pages faults evicted themselves, for their page or its readahead, no
+    /// frame being free
+    pub fn direct_reclaims(&self) -> u64 {
+        self.direct_reclaims
+    }
+
+    /// Pages the page-out daemon evicted while no fault waited
+    pub fn background_reclaims(&self) -> u64 {
+        self.background_reclaims
+    }
+
+    pub fn kswapd_wakeups(&self) -> u64 {
+        self.kswapd_wakeups
+    }
+
+    pub fn tiers(&self) -> Option<&TierReport> {
+        self.tiers.as_ref()
+    }
+
+    pub fn pool(&self) -> Option<&PoolReport> {
+        self.pool.as_ref()
+    }
+
+    pub fn huge(&self) -> Option<&HugeReport> {
+        self.huge.as_ref()
+    }
+
+    /// Faults that read a page evicted before back from the device, rather
+    /// than decompressing it from the pool
+    pub fn swap_ins(&self) -> u64 {
+        self.swap_ins
+    }
+
+    /// Hits that found their page in the frames rather than a slower tier
+    pub fn fast_hits(&self) -> u64 {
+        self.hits - self.tiers.as_ref().map_or(0, |x| x.slow_hits())
+    }
+
+    /// Pages read ahead of any reference to them
+    pub fn prefetches(&self) -> u64 {
+        self.prefetches
+    }
+
+    /// Prefetched pages a reference found resident, each a fault saved
+    pub fn useful_prefetches(&self) -> u64 {
+        self.useful_prefetches
+    }
+
+    /// Prefetched pages evicted, or still resident at the end, without ever
+    /// being referenced
+    pub fn wasted_prefetches(&self) -> u64 {
+        self.wasted_prefetches
+    }
+
+    /// Share of prefetches that saved a fault
+    pub fn prefetch_accuracy(&self) -> f64 {
+        ratio(self.useful_prefetches, self.prefetches)
+    }
+
+    /// Total references page spent in memory
+    pub fn resident_time(&self, page: PageId) -> Time {
+        *self.resident_per_page.get(&page).unwrap_or(&0)
+    }
+
+    /// Counters of every process that made a reference, by process id
+    pub fn processes(&self) -> impl Iterator<Item = (Pid, &ProcessReport)> + '_ {
+        self.processes.iter().map(|(pid, report)| (*pid, report))
+    }
+
+    pub fn process(&self, process: Pid) -> Option<&ProcessReport> {
+        self.processes.get(&process)
+    }
+}
+
+impl Default for PageReport {
+    fn default() -> Self {
+        PageReport::new()
+    }
+}
+
+fn ratio(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        return 0.0;
+    }
+    part as f64 / whole as f64
+}
+
+impl fmt::Display for PageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "| hits: {} | faults: {} | removed: {} | hit ratio: {:.3} | compulsory: {} | capacity: {} | premature: {} |",
+            self.hits,
+            self.faults,
+            self.removed,
+            self.hit_ratio(),
+            self.compulsory_faults(),
+            self.capacity_faults(),
+            self.premature
+        )?;
+        if self.cow > 0 {
+            write!(f, " cow: {} |", self.cow)?;
+        }
+        if self.kswapd_wakeups > 0 {
+            write!(
+                f,
+                " direct reclaims: {} | background reclaims: {} |",
+                self.direct_reclaims, self.background_reclaims
+            )?;
+        }
+        if self.write_backs > 0 {
+            write!(f, " write-backs: {} |", self.write_backs)?;
+        }
+        if let Some(swap) = self.swap {
+            write!(
+                f,
+                " elapsed: {:.3} ms | io stall: {:.3} ms |",
+                swap.elapsed / 1e6,
+                swap.stall / 1e6
+            )?;
+        }
+        if let Some(tiers) = &self.tiers {
+            let hits = tiers
+                .hits
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<String>>();
+            write!(
+                f,
+                " tier hits: {}/{} | promoted: {} | demoted: {} |",
+                self.fast_hits(),
+                hits.join("/"),
+                tiers.promotions,
+                tiers.demotions
+            )?;
+        }
+        if let Some(pool) = &self.pool {
+            write!(
+                f,
+                " pool hits: {} | swap-ins: {} |",
+                pool.hits,
+                self.swap_ins()
+            )?;
+        }
+        if let Some(huge) = &self.huge {
+            write!(
+                f,
+                " huge pages collapsed: {} | split: {} | wasted peak: {} B |",
+                huge.collapses,
+                huge.splits,
+                huge.peak_wasted_bytes()
+            )?;
+        }
+        if self.prefetches > 0 {
+            write!(
+                f,
+                " prefetched: {} | useful: {} | wasted: {} |",
+                self.prefetches, self.useful_prefetches, self.wasted_prefetches
+            )?;
+        }
+        if let Some(translation) = self.translation {
+            write!(
+                f,
+                " tlb hits: {} | tlb misses: {} | walk refs: {} |",
+                translation.hits, translation.misses, translation.walk_references
+            )?;
+            if self.huge.is_some() {
+                write!(f, " reach gain: {:.2} |", translation.reach_gain())?;
+            }
+        }
+        if self.processes.len() > 1 {
+            for (pid, process) in self.processes() {
+                write!(
+                    f,
+                    "\n    | process {} | hits: {} | faults: {} | removed: {} | fault ratio: {:.3} |",
+                    pid,
+                    process.hits,
+                    process.faults,
+                    process.removed,
+                    process.fault_ratio()
+                )?;
+                if process.cow > 0 {
+                    write!(f, " cow: {} |", process.cow)?;
+                }
+                match (process.min_frames(), process.max_frames()) {
+                    (Some(min), Some(max)) if min != max => write!(
+                        f,
+                        " frames: {} ({}..={}, {} changes) |",
+                        process.frames().unwrap_or(0),
+                        min,
+                        max,
+                        process.allocations.len() - 1
+                    )?,
+                    (Some(frames), _) => write!(f, " frames: {} |", frames)?,
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_of_empty_should_be_none() {
+        assert_eq!(Summary::of(&[]), None);
+    }
+
+    #[test]
+    fn summary_should_return_expected() {
+        let res = Summary::of(&[4, 1, 3, 2]).unwrap();
+        assert_eq!(res.count, 4);
+        assert_eq!(res.min, 1);
+        assert_eq!(res.max, 4);
+        assert_eq!(res.median, 3);
+        assert!((res.mean - 2.5).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn histogram_should_count_repeats() {
+        let mut histogram = Histogram::new();
+        for x in [5, 1, 5, 5, 2].iter() {
+            histogram.add(*x);
+        }
+        assert_eq!(histogram.count(), 5);
+        assert_eq!(
+            histogram.iter().collect::<Vec<_>>(),
+            vec![(1, 1), (2, 1), (5, 3)]
+        );
+        let summary = histogram.summary().unwrap();
+        assert_eq!((summary.min, summary.median, summary.max), (1, 5, 5));
+        assert!((summary.mean - 3.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ratios_should_return_expected() {
+        let report = PageReport::new()
+            .hit(0.into())
+            .hit(0.into())
+            .hit(0.into())
+            .fault(0.into(), 3, true);
+        assert!((report.hit_ratio() - 0.75).abs() < f64::EPSILON);
+        assert!((report.fault_ratio() - 0.25).abs() < f64::EPSILON);
+        assert_eq!(PageReport::new().hit_ratio(), 0.0);
+    }
+
+    #[test]
+    fn cost_should_price_every_event() {
+        let victim = MemoryPage::new(1);
+        let report = PageReport::new()
+            .hit(0.into())
+            .fault(0.into(), 1, true)
+            .fault(1.into(), 2, true)
+            .removed(&victim, 2);
+        let model = CostModel {
+            hit: 1.0,
+            fault: 10.0,
+            eviction: 5.0,
+        };
+        assert!((report.cost(&model) - 26.0).abs() < f64::EPSILON);
+        assert!((report.effective_access_time(&model) - 26.0 / 3.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn faults_should_split_compulsory_and_capacity() {
+        let report = PageReport::new()
+            .fault(0.into(), 0, true)
+            .fault(1.into(), 1, true)
+            .fault(0.into(), 2, false);
+        assert_eq!(report.compulsory_faults(), 2);
+        assert_eq!(report.capacity_faults(), 1);
+    }
+
+    #[test]
+    fn fault_soon_after_eviction_should_be_premature() {
+        let victim = MemoryPage::new(7).loaded_at(0);
+        let report = PageReport::new()
+            .with_premature_window(3)
+            .removed(&victim, 5)
+            .fault(7.into(), 8, false);
+        assert_eq!(report.premature_evictions(), 1);
+
+        let report = PageReport::new()
+            .with_premature_window(3)
+            .removed(&victim, 5)
+            .fault(7.into(), 9, false);
+        assert_eq!(report.premature_evictions(), 0);
+    }
+
+    #[test]
+    fn evictions_should_be_forgotten_after_premature_window() {
+        let report = (0..100).fold(PageReport::new().with_premature_window(3), |report, x| {
+            report.removed(&MemoryPage::new(x).loaded_at(x as Time), x as Time)
+        });
+        assert_eq!(report.evicted_at.len(), 4);
+        assert_eq!(report.evictions.len(), 4);
+        let report = report.fault(98.into(), 100, false);
+        assert_eq!(report.evicted_at.len(), 3);
+        assert_eq!(report.premature_evictions(), 1);
+    }
+
+    #[test]
+    fn removed_should_record_victim_age_and_residency() {
+        let victim = MemoryPage::new(2).loaded_at(1).used_at(4);
+        let report = PageReport::new()
+            .removed(&victim, 6)
+            .finish(&[MemoryPage::new(3).loaded_at(6)], 10);
+        assert_eq!(
+            report.victim_ages().iter().collect::<Vec<_>>(),
+            vec![(2, 1)]
+        );
+        assert_eq!(
+            report.residency_times().iter().collect::<Vec<_>>(),
+            vec![(4, 1), (5, 1)]
+        );
+        assert_eq!(report.resident_time(2.into()), 5);
+        assert_eq!(report.resident_time(3.into()), 4);
+        assert_eq!(report.resident_time(9.into()), 0);
+    }
+
+    #[test]
+    fn processes_should_be_counted_apart() {
+        let victim = MemoryPage::of(PageId::new(1, 4));
+        let report = PageReport::new()
+            .fault(PageId::new(1, 4), 0, true)
+            .fault(PageId::new(2, 4), 1, true)
+            .hit(PageId::new(2, 4))
+            .removed(&victim, 2)
+            .fault(PageId::new(2, 5), 2, true);
+        let process = |pid| {
+            let x = report.process(pid).unwrap();
+            (
+                x.hits(),
+                x.faults(),
+                x.removed_count(),
+                x.compulsory_faults(),
+            )
+        };
+        assert_eq!(process(1), (0, 1, 1, 1));
+        assert_eq!(process(2), (1, 2, 0, 2));
+        assert_eq!(report.process(3), None);
+        assert_eq!(
+            report.processes().map(|(pid, _)| pid).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(report.faults(), 3);
+    }
+}
@@ -0,0 +1,261 @@
+use crate::error::Error;
+use crate::pff::Pff;
+use crate::policy::Policy;
+use crate::simulation::Simulation;
+use crate::workload::Source;
+use crate::Pid;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Which resident pages a fault may take a frame from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scope {
+    /// Any page of any process, one policy sees every page
+    Global,
+    /// Only pages of the faulting process, every process gets a fixed share
+    /// of the frames and a policy of its own
+    Local(Allocation),
+    /// Local replacement starting from equal shares, with frames moved
+    /// between processes and a free pool by their page fault frequency
+    Pff(Pff),
+}
+
+/// How local replacement splits the frames between processes
+#[derive(Debug, Clone, PartialEq)]
+pub enum Allocation {
+    /// The same number of frames each
+    Equal,
+    /// In proportion to the distinct pages each process references
+    Proportional,
+    /// In proportion to a weight per process, in order of process id
+    /// Processes past the end of the list weigh 1
+    Priority(Vec<u32>),
+}
+
+impl Scope {
+    /// A simulation of policy under this scope with frames frames in total
+    /// Local scopes read source once up front to find the processes, and
+    /// make one policy per process
+    pub fn simulation(
+        &self,
+        policy: &dyn Fn() -> Result<Box<dyn Policy>, String>,
+        frames: u32,
+        source: &Source,
+        seed: u64,
+    ) -> Result<Simulation, String> {
+        let shares = match self {
+            Scope::Global => return Ok(Simulation::new(policy()?, frames)?),
+            Scope::Local(allocation) => allocation.shares(frames, &source.process_sizes(seed)?)?,
+            Scope::Pff(_) => Allocation::Equal.shares(frames, &source.process_sizes(seed)?)?,
+        };
+        // Simulation::local asks for exactly one policy per share
+        let mut policies = shares
+            .keys()
+            .map(|_| policy())
+            .collect::<Result<Vec<Box<dyn Policy>>, String>>()?;
+        let simulation = Simulation::local(&shares, |_| policies.remove(0))?;
+        Ok(match self {
+            Scope::Pff(pff) => simulation.with_pff(*pff),
+            _ => simulation,
+        })
+    }
+}
+
+impl Allocation {
+    /// Frames of every process, adding up to frames
+    ///
+    /// Each process gets at least one frame, what is left is split by weight
+    /// with the remainders rounded by largest remainder, ties to the lower id
+    pub fn shares(
+        &self,
+        frames: u32,
+        sizes: &BTreeMap<Pid, u64>,
+    ) -> Result<BTreeMap<Pid, u32>, Error> {
+        if frames == 0 {
+            return Err(Error::NoFrames);
+        }
+        if (frames as usize) < sizes.len() {
+            return Err(Error::TooFewFrames {
+                frames,
+                processes: sizes.len(),
+            });
+        }
+        let weights = sizes
+            .iter()
+            .enumerate()
+            .map(|(index, (pid, size))| {
+                let weight = match self {
+                    Allocation::Equal => 1,
+                    Allocation::Proportional => *size,
+                    Allocation::Priority(x) => x.get(index).map_or(1, |x| *x as u64),
+                };
+                (*pid, weight)
+            })
+            .collect::<Vec<(Pid, u64)>>();
+        let total = weights.iter().map(|(_, x)| *x as u128).sum::<u128>();
+        let spare = (frames as usize - sizes.len()) as u128;
+
+        let mut shares = weights
+            .iter()
+            .map(|(pid, weight)| {
+                // every weight 0 leaves the spare frames to the rounding
+                let exact = spare * *weight as u128;
+                let whole = exact.checked_div(total).unwrap_or(0);
+                let remainder = exact.checked_rem(total).unwrap_or(0);
+                (*pid, 1 + whole as u32, remainder)
+            })
+            .collect::<Vec<(Pid, u32, u128)>>();
+        let given = shares.iter().map(|(_, x, _)| *x).sum::<u32>();
+        let mut order = (0..shares.len()).collect::<Vec<usize>>();
+        order.sort_by(|a, b| shares[*b].2.cmp(&shares[*a].2).then(a.cmp(b)));
+        for index in order.into_iter().cycle().take((frames - given) as usize) {
+            shares[index].1 += 1;
+        }
+        Ok(shares.into_iter().map(|(pid, x, _)| (pid, x)).collect())
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Global => write!(f, "global"),
+            Scope::Local(allocation) => write!(f, "local({})", allocation),
+            Scope::Pff(pff) => write!(f, "{}", pff),
+        }
+    }
+}
+
+impl fmt::Display for Allocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Allocation::Equal => write!(f, "equal"),
+            Allocation::Proportional => write!(f, "proportional"),
+            Allocation::Priority(priorities) => {
+                let priorities = priorities
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<String>>();
+                write!(f, "priority({})", priorities.join(","))
+            }
+        }
+    }
+}
+
+/// 'global', 'local' (equal shares), 'local:proportional',
+/// 'local:priority=3,1,1', or 'pff' optionally followed by settings as in
+/// 'pff:window=200,lower=0.02,upper=0.3'
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Scope, String> {
+        let (scope, allocation) = match s.split_once(':') {
+            Some((scope, allocation)) => (scope, Some(allocation)),
+            None => (s, None),
+        };
+        match (scope, allocation) {
+            ("global", None) => Ok(Scope::Global),
+            ("local", None) => Ok(Scope::Local(Allocation::Equal)),
+            ("local", Some(allocation)) => Ok(Scope::Local(allocation.parse()?)),
+            ("pff", settings) => Ok(Scope::Pff(settings.unwrap_or("").parse()?)),
+            _ => Err(format!(
+                "unknown scope '{}', expected global, local, local:proportional, local:priority=... or pff",
+                s
+            )),
+        }
+    }
+}
+
+impl FromStr for Allocation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Allocation, String> {
+        match s.split_once('=') {
+            None if s == "equal" => Ok(Allocation::Equal),
+            None if s == "proportional" => Ok(Allocation::Proportional),
+            Some(("priority", priorities)) => priorities
+                .split(',')
+                .map(|x| {
+                    x.trim()
+                        .parse::<u32>()
+                        .map_err(|_| format!("'{}' is not a priority", x))
+                })
+                .collect::<Result<Vec<u32>, String>>()
+                .map(Allocation::Priority),
+            _ => Err(format!(
+                "unknown allocation '{}', expected equal, proportional or priority=...",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizes(sizes: &[(Pid, u64)]) -> BTreeMap<Pid, u64> {
+        sizes.iter().copied().collect()
+    }
+
+    fn shares(allocation: Allocation, frames: u32, processes: &[(Pid, u64)]) -> Vec<u32> {
+        let res = allocation.shares(frames, &sizes(processes)).unwrap();
+        assert_eq!(res.values().sum::<u32>(), frames);
+        res.values().copied().collect()
+    }
+
+    #[test]
+    fn equal_should_split_evenly() {
+        let processes = [(0, 10), (1, 50), (2, 5)];
+        assert_eq!(shares(Allocation::Equal, 9, &processes), vec![3, 3, 3]);
+        assert_eq!(shares(Allocation::Equal, 10, &processes), vec![4, 3, 3]);
+    }
+
+    #[test]
+    fn proportional_should_follow_size() {
+        let processes = [(1, 10), (4, 30)];
+        assert_eq!(shares(Allocation::Proportional, 10, &processes), vec![3, 7]);
+        // every process keeps one frame however small
+        let processes = [(1, 1), (2, 1000)];
+        assert_eq!(shares(Allocation::Proportional, 4, &processes), vec![1, 3]);
+    }
+
+    #[test]
+    fn priority_should_follow_weights() {
+        let processes = [(0, 5), (1, 5), (2, 5)];
+        let res = shares(Allocation::Priority(vec![4, 1]), 8, &processes);
+        assert_eq!(res, vec![4, 2, 2]);
+    }
+
+    #[test]
+    fn too_few_frames_should_fail() {
+        let res = Allocation::Equal.shares(2, &sizes(&[(0, 1), (1, 1), (2, 1)]));
+        assert_eq!(
+            res,
+            Err(Error::TooFewFrames {
+                frames: 2,
+                processes: 3
+            })
+        );
+        assert_eq!(
+            Allocation::Equal.shares(0, &BTreeMap::new()),
+            Err(Error::NoFrames)
+        );
+    }
+
+    #[test]
+    fn scope_should_parse_and_display() {
+        for text in ["global", "local(equal)", "local(proportional)"].iter() {
+            let parsed = text.replace("(", ":").replace(")", "");
+            assert_eq!(parsed.parse::<Scope>().unwrap().to_string(), *text);
+        }
+        let res = "local:priority=3,1".parse::<Scope>();
+        assert_eq!(res, Ok(Scope::Local(Allocation::Priority(vec![3, 1]))));
+        assert_eq!(res.unwrap().to_string(), "local(priority(3,1))");
+        assert!("local:priority=a".parse::<Scope>().is_err());
+        assert!("regional".parse::<Scope>().is_err());
+        let res = "pff:window=20".parse::<Scope>().unwrap();
+        assert_eq!(res.to_string(), "pff(window=20,lower=0.05,upper=0.25)");
+        assert_eq!("pff".parse::<Scope>(), Ok(Scope::Pff(Pff::default())));
+    }
+}
@@ -0,0 +1,180 @@
+use crate::huge::{Huge, HugePages};
+use crate::kswapd::Watermarks;
+use crate::load::LoadControl;
+use crate::output::RunResult;
+use crate::policy;
+use crate::prefetch::Prefetch;
+use crate::scope::Scope;
+use crate::simulation::Simulation;
+use crate::swap::{Device, Swap};
+use crate::tier::{Tiering, Tiers};
+use crate::tlb::{Mmu, Translation};
+use crate::workload::Source;
+use crate::zswap::{Pool, Zswap};
+use crate::CostModel;
+
+/// Everything one simulation is set up with, the command line and
+/// experiment files both run theirs from these
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// Policy spec, see policy::from_spec
+    pub algorithm: String,
+    pub frames: u32,
+    pub seed: u64,
+    pub scope: Scope,
+    pub load_control: LoadControl,
+    pub tlb: Option<Translation>,
+    pub prefetch: Prefetch,
+    pub swap: Option<Device>,
+    pub watermarks: Option<Watermarks>,
+    pub tiers: Option<Tiering>,
+    pub zswap: Option<Zswap>,
+    pub huge: Option<HugePages>,
+    /// Validate invariants after every reference
+    pub check: bool,
+}
+
+impl Settings {
+    /// Run the references of source and report them under label
+    pub fn run(
+        &self,
+        source: &Source,
+        label: &str,
+        cost: Option<CostModel>,
+    ) -> Result<RunResult, String> {
+        let simulation = build_simulation(self, source)?;
+        Ok(RunResult {
+            algorithm: label.to_string(),
+            frames: self.frames,
+            workload: source.to_string(),
+            seed: self.seed,
+            scope: self.scope.clone(),
+            load_control: self.load_control,
+            tlb: self.tlb.clone(),
+            prefetch: self.prefetch,
+            swap: self.swap,
+            watermarks: self.watermarks,
+            tiers: self.tiers.clone(),
+            zswap: self.zswap.clone(),
+            huge: self.huge,
+            report: self
+                .load_control
+                .run(simulation, source.references(self.seed)?)?,
+            cost,
+        })
+    }
+}
+
+/// A simulation set up as settings say, ready for the references of source
+/// Local scopes read source once up front to find the processes
+pub fn build_simulation(settings: &Settings, source: &Source) -> Result<Simulation, String> {
+    let name = &settings.algorithm;
+    let seed = settings.seed;
+    let policy = || policy::from_spec(name, seed);
+    let simulation = settings
+        .scope
+        .simulation(&policy, settings.frames, source, seed)?;
+    let simulation = match settings.check {
+        true => simulation.checked(),
+        false => simulation,
+    };
+    let simulation = match &settings.tlb {
+        Some(translation) => simulation.with_mmu(Mmu::new(translation, seed)),
+        None => simulation,
+    };
+    let simulation = simulation.with_prefetch(settings.prefetch);
+    let simulation = match &settings.swap {
+        Some(device) => simulation.with_swap(Swap::new(device)),
+        None => simulation,
+    };
+    let simulation = match settings.watermarks {
+        Some(watermarks) => simulation.with_watermarks(watermarks),
+        None => simulation,
+    };
+    let simulation = match &settings.tiers {
+        Some(tiering) => simulation.with_tiers(Tiers::new(tiering)),
+        None => simulation,
+    };
+    let simulation = match &settings.zswap {
+        Some(zswap) => simulation.with_pool(Pool::new(zswap, seed)),
+        None => simulation,
+    };
+    Ok(match settings.huge {
+        Some(huge) => simulation.with_huge(Huge::new(huge)),
+        None => simulation,
+    })
+}
+
+/// Every combination of one index per axis, for axes of the given lengths,
+/// in order with the last axis changing fastest
+pub fn product(lengths: &[usize]) -> Vec<Vec<usize>> {
+    lengths.iter().fold(vec![vec![]], |combinations, length| {
+        combinations
+            .into_iter()
+            .flat_map(|x| {
+                (0..*length).map(move |index| {
+                    let mut x = x.clone();
+                    x.push(index);
+                    x
+                })
+            })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workload::Workload;
+
+    fn settings(algorithm: &str) -> Settings {
+        Settings {
+            algorithm: algorithm.to_string(),
+            frames: 3,
+            seed: 1,
+            scope: Scope::Global,
+            load_control: LoadControl::Off,
+            tlb: None,
+            prefetch: Prefetch::Off,
+            swap: None,
+            watermarks: None,
+            tiers: None,
+            zswap: None,
+            huge: None,
+            check: true,
+        }
+    }
+
+    #[test]
+    fn run_should_report_under_label() {
+        let source = Source::Generate(Workload::Loop {
+            length: 20,
+            pages: 4,
+        });
+        let res = settings("fifo").run(&source, "FIFO", None).unwrap();
+        assert_eq!((res.algorithm.as_str(), res.frames), ("FIFO", 3));
+        assert_eq!(res.report.faults(), 20);
+        let res = build_simulation(&settings("belady"), &source);
+        assert_eq!(res.err(), Some("unknown algorithm 'belady'".to_string()));
+        let res = build_simulation(&settings("mglru:generations=1"), &source);
+        let err = "mglru needs at least 2 generations, got 1";
+        assert_eq!(res.err(), Some(err.to_string()));
+    }
+
+    #[test]
+    fn product_should_change_last_axis_fastest() {
+        assert_eq!(
+            product(&[2, 1, 3]),
+            vec![
+                vec![0, 0, 0],
+                vec![0, 0, 1],
+                vec![0, 0, 2],
+                vec![1, 0, 0],
+                vec![1, 0, 1],
+                vec![1, 0, 2],
+            ]
+        );
+        assert!(product(&[2, 0]).is_empty());
+        assert_eq!(product(&[]), vec![Vec::<usize>::new()]);
+    }
+}
@@ -0,0 +1,2360 @@
+use crate::check;
+use crate::error::Error;
+use crate::huge::Huge;
+use crate::kswapd::Watermarks;
+use crate::pff::{Change, Pff};
+use crate::policy::Policy;
+use crate::prefetch::{Prefetch, Prefetcher};
+use crate::swap::Swap;
+use crate::tier::Tiers;
+use crate::tlb::Mmu;
+use crate::zswap::Pool;
+use crate::{MemoryPage, PageId, PageReport, Pid, Reference, Time};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::mem;
+
+/// What a single reference did
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Hit,
+    /// evicted is the page that made room, None when a frame was free
+    Fault {
+        evicted: Option<PageId>,
+    },
+}
+
+/// Shared frames are tracked under page numbers from here up, owned by the
+/// process of the page they were shared from. Traces cannot use them
+pub const SHARED_PAGES: u32 = 1 << 31;
+
+/// Frames a set of processes compete for, with the policy that picks
+/// victims among their pages
+struct Partition {
+    policy: Box<dyn Policy>,
+    frames: u32,
+    used: u32,
+    /// Pinned frames among the used ones
+    pinned: u32,
+    /// References and faults since the last page fault frequency decision
+    window_references: u64,
+    window_faults: u64,
+    /// The page-out daemon is refilling the free frames of the partition
+    reclaiming: bool,
+}
+
+impl Partition {
+    fn new(policy: Box<dyn Policy>, frames: u32) -> Partition {
+        Partition {
+            policy,
+            frames,
+            used: 0,
+            pinned: 0,
+            window_references: 0,
+            window_faults: 0,
+            reclaiming: false,
+        }
+    }
+}
+
+/// Hand page to policy as just loaded, telling it when the page is dirty
+fn track(policy: &mut dyn Policy, page: PageId, modified: bool) {
+    policy.insert(page);
+    if modified {
+        policy.modified(page, true);
+    }
+}
+
+/// page is resident and written since it was loaded or written back
+fn is_dirty(resident: &HashMap<PageId, MemoryPage>, page: PageId) -> bool {
+    resident.get(&page).is_some_and(|x| x.is_modified())
+}
+
+/// Incremental simulation of one policy over a fixed number of frames
+///
+/// Every reference costs a couple of hash map lookups plus whatever the
+/// policy needs, nothing is proportional to the length of the trace
+pub struct Simulation {
+    partitions: Vec<Partition>,
+    /// Partition of every process under local replacement, empty when every
+    /// process shares partition 0
+    partition_of: HashMap<Pid, usize>,
+    /// Moves frames between partitions when set
+    pff: Option<Pff>,
+    /// Frames no partition holds, given out and taken back by pff
+    free: u32,
+    /// Processes swapped out by load control, only their pinned pages stay
+    /// resident
+    suspended: HashSet<Pid>,
+    /// Every page in memory by its frame, the page itself unless it is shared
+    resident: HashMap<PageId, MemoryPage>,
+    /// Frames no policy may evict
+    pinned: HashSet<PageId>,
+    /// Frame of every page that shares one, copy on write
+    mapped: HashMap<PageId, PageId>,
+    /// Pages mapping each shared frame
+    mappers: HashMap<PageId, u32>,
+    /// Frames ever referenced, to tell compulsory faults from the others
+    /// O(distinct pages) for the whole run, it is never pruned
+    seen: HashSet<PageId>,
+    /// Pages evicted to the backing store and not read back since, a fault
+    /// on one is a swap-in
+    swapped: HashSet<PageId>,
+    /// Translates every reference before it reaches the frames when set
+    mmu: Option<Mmu>,
+    /// Reads pages ahead of their references when set
+    prefetcher: Option<Prefetcher>,
+    /// Prefetched frames no reference has found yet
+    prefetched: HashSet<PageId>,
+    /// Clock and backing store every fault and write-back waits on when set
+    swap: Option<Swap>,
+    /// Free frames a page-out daemon keeps when set
+    watermarks: Option<Watermarks>,
+    /// Slower memory evicted pages are demoted to when set
+    tiers: Option<Tiers>,
+    /// Compressed pool pages leaving memory go to when set
+    pool: Option<Pool>,
+    /// Huge pages hot regions are collapsed into when set
+    huge: Option<Huge>,
+    report: PageReport,
+    time: Time,
+    checked: bool,
+}
+
+impl Simulation {
+    /// Global replacement, policy sees the pages of every process
+    pub fn new(policy: Box<dyn Policy>, frames: u32) -> Result<Simulation, Error> {
+        if frames == 0 {
+            return Err(Error::NoFrames);
+        }
+        Ok(Simulation {
+            partitions: vec![Partition::new(policy, frames)],
+            partition_of: HashMap::new(),
+            pff: None,
+            free: 0,
+            suspended: HashSet::new(),
+            resident: HashMap::new(),
+            pinned: HashSet::new(),
+            mapped: HashMap::new(),
+            mappers: HashMap::new(),
+            seen: HashSet::new(),
+            swapped: HashSet::new(),
+            mmu: None,
+            prefetcher: None,
+            prefetched: HashSet::new(),
+            swap: None,
+            watermarks: None,
+            tiers: None,
+            pool: None,
+            huge: None,
+            report: PageReport::new(),
+            time: 0,
+            checked: false,
+        })
+    }
+
+    /// Local replacement, every process of shares gets its frames and a
+    /// policy of its own, and only ever evicts its own pages
+    /// Referencing a page of any other process fails
+    pub fn local<F>(shares: &BTreeMap<Pid, u32>, mut policy: F) -> Result<Simulation, Error>
+    where
+        F: FnMut(Pid) -> Box<dyn Policy>,
+    {
+        if shares.is_empty() || shares.values().any(|x| *x == 0) {
+            return Err(Error::NoFrames);
+        }
+        let mut report = PageReport::new();
+        for (pid, frames) in shares {
+            report = report.allocated(*pid, *frames, 0);
+        }
+        Ok(Simulation {
+            partitions: shares
+                .iter()
+                .map(|(pid, frames)| Partition::new(policy(*pid), *frames))
+                .collect(),
+            partition_of: shares
+                .keys()
+                .enumerate()
+                .map(|(i, pid)| (*pid, i))
+                .collect(),
+            pff: None,
+            free: 0,
+            suspended: HashSet::new(),
+            resident: HashMap::new(),
+            pinned: HashSet::new(),
+            mapped: HashMap::new(),
+            mappers: HashMap::new(),
+            seen: HashSet::new(),
+            swapped: HashSet::new(),
+            mmu: None,
+            prefetcher: None,
+            prefetched: HashSet::new(),
+            swap: None,
+            watermarks: None,
+            tiers: None,
+            pool: None,
+            huge: None,
+            report,
+            time: 0,
+            checked: false,
+        })
+    }
+
+    pub fn with_report(self, report: PageReport) -> Simulation {
+        Simulation { report, ..self }
+    }
+
+    /// Let pff grow and shrink the frames of every process as its fault rate
+    /// changes, starting from the shares the simulation was made with
+    /// Only makes sense under local replacement
+    pub fn with_pff(self, pff: Pff) -> Simulation {
+        Simulation {
+            pff: Some(pff),
+            ..self
+        }
+    }
+
+    /// Look every reference up in the TLB of mmu first, walking its page
+    /// table on a miss
+    pub fn with_mmu(self, mmu: Mmu) -> Simulation {
+        Simulation {
+            mmu: Some(mmu),
+            ..self
+        }
+    }
+
+    /// Read pages ahead of their references as prefetch picks them
+    pub fn with_prefetch(self, prefetch: Prefetch) -> Simulation {
+        let prefetcher = match prefetch {
+            Prefetch::Off => None,
+            prefetch => Some(Prefetcher::new(prefetch)),
+        };
+        Simulation { prefetcher, ..self }
+    }
+
+    /// Take simulated time for every reference: faults wait on the device of
+    /// swap, and dirty pages are written back to it
+    pub fn with_swap(self, swap: Swap) -> Simulation {
+        Simulation {
+            swap: Some(swap),
+            ..self
+        }
+    }
+
+    /// Evict ahead of demand: a page-out daemon keeps the free frames of
+    /// every partition between the watermarks, so faults find a free frame
+    pub fn with_watermarks(self, watermarks: Watermarks) -> Simulation {
+        Simulation {
+            watermarks: Some(watermarks),
+            ..self
+        }
+    }
+
+    /// Demote the pages policies evict to the slower tiers of tiers, and
+    /// promote the ones that turn hot there back into the frames
+    pub fn with_tiers(self, tiers: Tiers) -> Simulation {
+        Simulation {
+            tiers: Some(tiers),
+            ..self
+        }
+    }
+
+    /// Compress the pages leaving memory into pool rather than writing them
+    /// to swap, faults on them decompress them from there
+    pub fn with_pool(self, pool: Pool) -> Simulation {
+        Simulation {
+            pool: Some(pool),
+            ..self
+        }
+    }
+
+    /// Collapse hot regions into the huge pages of huge, and split them
+    /// again when the policy picks one
+    pub fn with_huge(self, huge: Huge) -> Simulation {
+        Simulation {
+            huge: Some(huge),
+            ..self
+        }
+    }
+
+    /// Check the invariants of check::step after every reference, at the cost
+    /// of O(frames) work per reference
+    pub fn checked(self) -> Simulation {
+        Simulation {
+            checked: true,
+            ..self
+        }
+    }
+
+    /// Reference page, failing when the policy's bookkeeping no longer matches
+    /// memory. The simulation should not be used after an error
+    ///
+    /// Checked runs only look at the partition of the frame page ends up in,
+    /// the pages it competes with for a frame
+    pub fn access(&mut self, page: PageId) -> Result<Outcome, Error> {
+        self.reference(page, false)
+    }
+
+    /// Reference page to write it, a shared page gets a private copy first
+    pub fn write(&mut self, page: PageId) -> Result<Outcome, Error> {
+        self.reference(page, true)
+    }
+
+    fn reference(&mut self, page: PageId, write: bool) -> Result<Outcome, Error> {
+        if !self.checked {
+            self.adjust(page.process)?;
+            let outcome = self.step(page, write)?;
+            self.prefetch(page, outcome)?;
+            self.khugepaged(page)?;
+            self.kswapd()?;
+            self.clean();
+            return Ok(outcome);
+        }
+        let step = self.time;
+        self.adjust(page.process).map_err(|err| err.at(step))?;
+        let frame = if self.copies_on_write(page, write) {
+            page
+        } else {
+            self.frame(page)
+        };
+        let partition = self.partition(frame.process).map_err(|err| err.at(step))?;
+        let before = self.partition_pages(partition);
+        let pinned = self.pinned_pages(partition);
+        let outcome = self.step(page, write).map_err(|err| err.at(step))?;
+        let after = self.partition_pages(partition);
+        let frames = self.partitions[partition].frames;
+        // served from a slower tier without being promoted
+        let slow = self.tiers.as_ref().is_some_and(|x| x.contains(frame));
+        if slow {
+            check::slow_hit(step, frame, &before, &after)?;
+        } else {
+            check::step(step, frame, frames, &before, &after)?;
+        }
+        check::pinned(step, &pinned, &after)?;
+        if self.prefetcher.is_some() {
+            self.prefetch(page, outcome).map_err(|err| err.at(step))?;
+            let after = self.partition_pages(partition);
+            if !slow {
+                check::prefetch(step, frame, frames, &after)?;
+            }
+            check::pinned(step, &pinned, &after)?;
+        }
+        if self.huge.is_some() {
+            self.khugepaged(page).map_err(|err| err.at(step))?;
+            self.check_huge(step)?;
+            check::pinned(step, &pinned, &self.partition_pages(partition))?;
+        }
+        if self.watermarks.is_some() {
+            self.kswapd().map_err(|err| err.at(step))?;
+            check::pinned(step, &pinned, &self.partition_pages(partition))?;
+        }
+        self.clean();
+        Ok(outcome)
+    }
+
+    /// Reference page and keep it resident until it is unpinned
+    /// A page in a slower tier is promoted to the frames first
+    pub fn pin(&mut self, page: PageId) -> Result<Outcome, Error> {
+        let outcome = self.access(page)?;
+        let frame = self.frame(page);
+        if self.tiers.as_ref().is_some_and(|x| x.contains(frame)) {
+            let index = self.partition(frame.process)?;
+            self.promote(index, frame, self.time - 1)?;
+        }
+        self.set_pinned(frame, true);
+        Ok(outcome)
+    }
+
+    /// Let page be evicted again, it stays resident until it is
+    pub fn unpin(&mut self, page: PageId) {
+        self.set_pinned(self.frame(page), false);
+    }
+
+    /// Pin or unpin frame, counting it in the pinned frames of its partition
+    /// Whether that changed anything
+    fn set_pinned(&mut self, frame: PageId, pinned: bool) -> bool {
+        let changed = match pinned {
+            true => self.pinned.insert(frame),
+            false => self.pinned.remove(&frame),
+        };
+        if let (true, Ok(index)) = (changed, self.partition(frame.process)) {
+            let partition = &mut self.partitions[index];
+            match pinned {
+                true => partition.pinned += 1,
+                false => partition.pinned -= 1,
+            }
+        }
+        changed
+    }
+
+    pub fn is_pinned(&self, page: PageId) -> bool {
+        self.pinned.contains(&self.frame(page))
+    }
+
+    /// Map page onto the frame of with, copy on write: the pages read one
+    /// frame, which is resident once, until one of them writes and gets a
+    /// private copy. page must not be resident or shared yet
+    pub fn share(&mut self, page: PageId, with: PageId) -> Result<(), Error> {
+        if page == with || self.mapped.contains_key(&page) || self.resident.contains_key(&page) {
+            return Err(Error::InconsistentState(format!(
+                "page {} cannot share the frame of {}, it has one already",
+                page, with
+            )));
+        }
+        let frame = match self.mapped.get(&with) {
+            Some(frame) => *frame,
+            None => {
+                let frame = PageId::new(with.process, SHARED_PAGES + self.mappers.len() as u32);
+                self.move_frame(with, frame)?;
+                self.mapped.insert(with, frame);
+                self.mappers.insert(frame, 1);
+                frame
+            }
+        };
+        self.mapped.insert(page, frame);
+        *self.mappers.entry(frame).or_insert(0) += 1;
+        Ok(())
+    }
+
+    /// Apply one entry of a trace, only accesses, writes and pins are
+    /// references with an outcome
+    pub fn apply(&mut self, reference: Reference) -> Result<Option<Outcome>, Error> {
+        match reference {
+            Reference::Access(page) => self.access(page).map(Some),
+            Reference::Write(page) => self.write(page).map(Some),
+            Reference::Pin(page) => self.pin(page).map(Some),
+            Reference::Unpin(page) => {
+                self.unpin(page);
+                Ok(None)
+            }
+            Reference::Share { page, with } => self.share(page, with).map(|_| None),
+        }
+    }
+
+    /// The frame page is read from
+    fn frame(&self, page: PageId) -> PageId {
+        *self.mapped.get(&page).unwrap_or(&page)
+    }
+
+    /// Whether writing page breaks it away from a frame other pages share
+    fn copies_on_write(&self, page: PageId, write: bool) -> bool {
+        write && self.mappers.get(&self.frame(page)).is_some_and(|x| *x > 1)
+    }
+
+    /// Track the resident page from under the frame to, the policy sees it
+    /// as just loaded
+    fn move_frame(&mut self, from: PageId, to: PageId) -> Result<(), Error> {
+        if let Some(head) = self.huge.as_ref().and_then(|x| x.huge_head(from)) {
+            self.split(head)?;
+        }
+        if let Some(tiers) = &mut self.tiers {
+            tiers.rename(from, to);
+        }
+        if let Some(pool) = &mut self.pool {
+            pool.rename(from, to);
+        }
+        let page = match self.resident.remove(&from) {
+            Some(page) => page,
+            None => return Ok(()),
+        };
+        let index = self.partition(from.process)?;
+        let policy = &mut self.partitions[index].policy;
+        policy.remove(from);
+        track(policy.as_mut(), to, page.is_modified());
+        let page = MemoryPage {
+            process: to.process,
+            number: to.number,
+            ..page
+        };
+        self.resident.insert(to, page);
+        if self.set_pinned(from, false) {
+            self.set_pinned(to, true);
+        }
+        if self.prefetched.remove(&from) {
+            self.prefetched.insert(to);
+        }
+        Ok(())
+    }
+
+    fn step(&mut self, page: PageId, write: bool) -> Result<Outcome, Error> {
+        let now = self.time;
+        self.time += 1;
+        if self.suspended.contains(&page.process) {
+            return Err(Error::InconsistentState(format!(
+                "process {} is suspended",
+                page.process
+            )));
+        }
+        if page.number >= SHARED_PAGES {
+            return Err(Error::InconsistentState(format!(
+                "page {} is in the range kept for shared frames",
+                page
+            )));
+        }
+        let cow = self.copies_on_write(page, write);
+        if cow {
+            let frame = self.mapped.remove(&page).unwrap();
+            *self.mappers.get_mut(&frame).unwrap() -= 1;
+        }
+        if let Some(mmu) = &mut self.mmu {
+            // the private copy needs a translation of its own
+            if cow {
+                mmu.invalidate(page);
+            }
+            let huge = self
+                .huge
+                .as_ref()
+                .and_then(|x| x.huge_head(page).map(|head| (head, x.settings().span)));
+            match huge {
+                Some((head, span)) => mmu.translate_huge(head, span)?,
+                None => mmu.translate(page)?,
+            };
+        }
+        let frame = self.frame(page);
+        // Windows count the references a process makes, wherever the frame
+        // it reads lives
+        let own = self.partition(page.process)?;
+        self.partitions[own].window_references += 1;
+        let index = self.partition(frame.process)?;
+        if write && self.tier(frame).is_some() {
+            // the copy in the pool went stale
+            if let Some(pool) = &mut self.pool {
+                pool.invalidate(frame);
+            }
+        }
+        // the policy tracks a huge page by its first page
+        let tracked = self
+            .huge
+            .as_ref()
+            .and_then(|x| x.huge_head(frame))
+            .unwrap_or(frame);
+        let partition = &mut self.partitions[index];
+
+        // Page is in Memory
+        if let Some(resident) = self.resident.get_mut(&frame) {
+            let used = if write {
+                resident.modified_and_referenced()
+            } else {
+                resident.referenced()
+            };
+            *resident = used.used_at(now);
+            partition.policy.hit(tracked);
+            if write {
+                partition.policy.modified(tracked, true);
+            }
+            self.report = mem::take(&mut self.report).hit(page);
+            if self.prefetched.remove(&frame) {
+                // read ahead of this, its first reference
+                self.seen.insert(frame);
+                self.report = mem::take(&mut self.report).prefetch_used();
+            }
+            if let Some(swap) = &mut self.swap {
+                swap.hit();
+            }
+            return Ok(Outcome::Hit);
+        }
+
+        let slow = match &mut self.tiers {
+            Some(tiers) => tiers.reference(frame, write, now),
+            None => None,
+        };
+        if let Some((latency, hot)) = slow {
+            self.report = mem::take(&mut self.report).hit(page);
+            if let Some(swap) = &mut self.swap {
+                swap.access(latency);
+            }
+            if hot {
+                match self.promote(index, frame, now) {
+                    // every frame is pinned, the page stays where it is
+                    Ok(()) | Err(Error::OutOfFrames { .. }) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            return Ok(Outcome::Hit);
+        }
+
+        let pooled = match &mut self.pool {
+            Some(pool) => pool.load(frame, write).map(|x| (x, pool.latency())),
+            None => None,
+        };
+        let first_touch = self.seen.insert(frame);
+        let report = mem::take(&mut self.report);
+        self.report = if cow {
+            report.cow_fault(page, now)
+        } else {
+            report.fault(page, now, first_touch)
+        };
+        // a private copy comes from the shared frame, and a compressed one
+        // from the pool, see swap.fault below
+        if !cow && pooled.is_none() && self.swapped.remove(&frame) {
+            self.report = mem::take(&mut self.report).swapped_in();
+        }
+        self.partitions[own].window_faults += 1;
+
+        // No Room, replace a page
+        let partition = &self.partitions[index];
+        let evicted = if partition.used >= partition.frames {
+            Some(self.evict(index, now)?)
+        } else {
+            None
+        };
+        if evicted.is_some() {
+            self.report = mem::take(&mut self.report).direct_reclaim();
+        }
+        if let Some(swap) = &mut self.swap {
+            if let Some((_, latency)) = pooled {
+                swap.access(latency);
+            }
+            // a private copy comes from the shared frame, and a compressed
+            // one from the pool, not the device
+            swap.fault(
+                !cow && pooled.is_none(),
+                evicted.is_some_and(|x| x.is_modified()),
+            );
+        }
+
+        let partition = &mut self.partitions[index];
+        let loaded = match pooled {
+            // still dirty when the device has no copy
+            Some((page, _)) => MemoryPage {
+                modified: page.is_modified(),
+                ..MemoryPage::of(frame)
+            },
+            None => MemoryPage::of(frame),
+        };
+        let loaded = loaded.loaded_at(now);
+        let loaded = if write {
+            loaded.modified_and_referenced()
+        } else {
+            loaded
+        };
+        self.resident.insert(frame, loaded);
+        track(partition.policy.as_mut(), frame, loaded.is_modified());
+        partition.used += 1;
+        Ok(Outcome::Fault {
+            evicted: evicted.map(|x| x.id()),
+        })
+    }
+
+    /// Move frame up from its slower tier into the frames of a partition,
+    /// demoting a victim of the policy when they are full
+    fn promote(&mut self, index: usize, frame: PageId, now: Time) -> Result<(), Error> {
+        let partition = &self.partitions[index];
+        let full = partition.used >= partition.frames;
+        if full && partition.pinned >= partition.used {
+            return Err(Error::OutOfFrames {
+                frames: partition.frames,
+            });
+        }
+        let page = match self.tiers.as_mut().and_then(|x| x.promote(frame)) {
+            Some(page) => page,
+            None => return Ok(()),
+        };
+        if full {
+            self.evict(index, now)?;
+        }
+        let partition = &mut self.partitions[index];
+        self.resident.insert(frame, page.loaded_at(now));
+        track(partition.policy.as_mut(), frame, page.is_modified());
+        partition.used += 1;
+        Ok(())
+    }
+
+    /// Read the pages the prefetcher expects page's process to want next
+    /// into its partition, at most one frame short of all of them
+    ///
+    /// The referenced page counts as pinned meanwhile, a policy picking it
+    /// sees it as just loaded. Reading stops early when only pinned pages
+    /// are left to evict
+    fn prefetch(&mut self, page: PageId, outcome: Outcome) -> Result<(), Error> {
+        let pages = match &mut self.prefetcher {
+            Some(prefetcher) => prefetcher.pages(page, outcome != Outcome::Hit),
+            None => return Ok(()),
+        };
+        let index = self.partition(page.process)?;
+        let room = self.partitions[index].frames.saturating_sub(1) as usize;
+        let pages = pages
+            .into_iter()
+            // shared pages read their frame, they have nothing to read ahead
+            .filter(|x| {
+                !self.resident.contains_key(x)
+                    && !self.mapped.contains_key(x)
+                    && !self.tiers.as_ref().is_some_and(|tiers| tiers.contains(*x))
+            })
+            .take(room)
+            .collect::<Vec<PageId>>();
+        if pages.is_empty() {
+            return Ok(());
+        }
+        let frame = self.frame(page);
+        let pinned = self.set_pinned(frame, true);
+        let res = self.read_ahead(index, &pages);
+        if pinned {
+            self.set_pinned(frame, false);
+        }
+        res
+    }
+
+    fn read_ahead(&mut self, index: usize, pages: &[PageId]) -> Result<(), Error> {
+        // at the time of the reference that asked for them
+        let now = self.time - 1;
+        for page in pages {
+            let partition = &self.partitions[index];
+            if partition.used >= partition.frames {
+                match self.evict(index, now) {
+                    // made room for a read on the way back from a fault
+                    Ok(victim) => {
+                        self.write_back(&victim);
+                        self.report = mem::take(&mut self.report).direct_reclaim();
+                    }
+                    Err(Error::OutOfFrames { .. }) => return Ok(()),
+                    Err(err) => return Err(err),
+                }
+            }
+            let partition = &mut self.partitions[index];
+            self.resident
+                .insert(*page, MemoryPage::of(*page).loaded_at(now));
+            self.swapped.remove(page);
+            partition.policy.insert(*page);
+            partition.used += 1;
+            self.prefetched.insert(*page);
+            self.report = mem::take(&mut self.report).prefetched();
+            if let Some(swap) = &mut self.swap {
+                swap.prefetch();
+            }
+        }
+        Ok(())
+    }
+
+    /// Have the policy of a partition give up one of its pages
+    /// Fails with OutOfFrames when every page of the partition is pinned
+    fn evict(&mut self, index: usize, now: Time) -> Result<MemoryPage, Error> {
+        let partition = &mut self.partitions[index];
+        if partition.pinned > 0 && partition.pinned >= partition.used {
+            return Err(Error::OutOfFrames {
+                frames: partition.frames,
+            });
+        }
+        // Pinned pages are never picked, nor are huge pages with nothing but
+        // pinned pages. Some other page is, there are more than pinned ones
+        let (pinned, huge) = (&self.pinned, &self.huge);
+        let skip = |page: PageId| match huge {
+            Some(huge) if huge.is_head(page) => {
+                huge.settings().members(page).all(|x| pinned.contains(&x))
+            }
+            _ => pinned.contains(&page),
+        };
+        let victim = partition
+            .policy
+            .evict_where(&skip)
+            .ok_or(Error::NothingToEvict)?;
+        let (members, unused) = match &mut self.huge {
+            Some(huge) if huge.is_head(victim) => huge.split(victim),
+            _ => (vec![], vec![]),
+        };
+        let victim = if members.is_empty() {
+            victim
+        } else {
+            // A huge page is split and gives up a page no reference used,
+            // or else its coldest one. The rest go back to the policy as
+            // base pages
+            if let Some(mmu) = &mut self.mmu {
+                mmu.invalidate(victim);
+            }
+            let (pinned, resident) = (&self.pinned, &self.resident);
+            let coldest = unused
+                .iter()
+                .find(|x| !pinned.contains(x))
+                .or_else(|| {
+                    members
+                        .iter()
+                        .filter(|x| !pinned.contains(x))
+                        .min_by_key(|x| (resident.get(x).map_or(0, |x| x.last_used), **x))
+                })
+                .copied()
+                .ok_or_else(|| {
+                    Error::InconsistentState(format!(
+                        "policy evicted huge page {} with only pinned pages",
+                        victim
+                    ))
+                })?;
+            for page in members.into_iter().filter(|x| *x != coldest) {
+                track(partition.policy.as_mut(), page, is_dirty(resident, page));
+            }
+            coldest
+        };
+        let frame = self.resident.remove(&victim).ok_or_else(|| {
+            Error::InconsistentState(format!(
+                "policy evicted page {} which is not resident",
+                victim
+            ))
+        })?;
+        if self
+            .partition_of
+            .get(&victim.process)
+            .is_some_and(|x| *x != index)
+        {
+            return Err(Error::InconsistentState(format!(
+                "policy evicted page {} of another process",
+                victim
+            )));
+        }
+        partition.used -= 1;
+        if self.prefetched.remove(&victim) {
+            self.report = mem::take(&mut self.report).prefetch_wasted(1);
+        }
+        if self.tiers.is_none() && self.pool.is_none() {
+            self.report = mem::take(&mut self.report).removed(&frame, now);
+            self.forget(victim);
+            self.swapped.insert(victim);
+            return Ok(frame);
+        }
+        // Demoting copies the page down a tier and compressing it keeps it
+        // in memory, nothing is written back unless a page leaves both
+        let leaving = match &mut self.tiers {
+            Some(tiers) => tiers.demote(frame),
+            None => Some(frame),
+        };
+        let frame = frame.written_back();
+        self.report = mem::take(&mut self.report).removed(&frame, now);
+        if let Some(page) = leaving {
+            self.forget(page.id());
+            self.page_out(page);
+        }
+        Ok(frame)
+    }
+
+    /// page left the frames and the slower tiers, compress it into the pool
+    /// when there is one and write back whatever does not stay there
+    fn page_out(&mut self, page: MemoryPage) {
+        let dropped = match &mut self.pool {
+            Some(pool) => pool.store(page),
+            None => vec![page],
+        };
+        for page in dropped {
+            // the pool only dropped its copy of a page still in memory
+            if self.tier(page.id()).is_some() {
+                continue;
+            }
+            self.report = mem::take(&mut self.report).pushed_out(&page);
+            self.write_back(&page);
+            self.swapped.insert(page.id());
+        }
+    }
+
+    /// Record the reference to page for the next scan, and once a window of
+    /// references has passed collapse the regions it found hot
+    fn khugepaged(&mut self, page: PageId) -> Result<(), Error> {
+        let now = self.time - 1;
+        let frame = self.frame(page);
+        let (first_use, hot) = match &mut self.huge {
+            Some(huge) => huge.reference(frame, now),
+            None => return Ok(()),
+        };
+        if first_use {
+            // filled in by a collapse, this is its first reference
+            self.seen.insert(frame);
+        }
+        for head in hot {
+            self.collapse(head, now)?;
+        }
+        Ok(())
+    }
+
+    /// Make the region at head a huge page: its missing pages are filled in,
+    /// evicting by the policy for frames to hold them. Regions with missing
+    /// pages that were ever used, shared pages, or no room for a huge page
+    /// among the pinned ones are left alone
+    fn collapse(&mut self, head: PageId, now: Time) -> Result<(), Error> {
+        let settings = match &self.huge {
+            Some(huge) => huge.settings(),
+            None => return Ok(()),
+        };
+        let span = settings.span;
+        if head.number as u64 + span as u64 > SHARED_PAGES as u64
+            || self.suspended.contains(&head.process)
+        {
+            return Ok(());
+        }
+        let index = self.partition(head.process)?;
+        let members = settings.members(head).collect::<Vec<PageId>>();
+        let (resident, missing): (Vec<PageId>, Vec<PageId>) =
+            members.iter().partition(|x| self.resident.contains_key(*x));
+        let fresh = members.iter().all(|x| !self.mapped.contains_key(x))
+            && missing.iter().all(|x| {
+                !self.seen.contains(x)
+                    && self.tier(*x).is_none()
+                    && !self.pool.as_ref().is_some_and(|pool| pool.contains(*x))
+            });
+        let pinned = self
+            .pinned_pages(index)
+            .into_iter()
+            .filter(|x| settings.head(*x) != head)
+            .count() as u32;
+        let partition = &self.partitions[index];
+        let free = partition.frames.saturating_sub(partition.used);
+        let needed = (missing.len() as u32).saturating_sub(free);
+        let evictable = partition.used - resident.len() as u32 - pinned;
+        if !fresh || span > partition.frames || evictable < needed {
+            return Ok(());
+        }
+        for page in resident.iter() {
+            self.partitions[index].policy.remove(*page);
+        }
+        for _ in 0..needed {
+            let victim = self.evict(index, now)?;
+            self.write_back(&victim);
+        }
+        for page in missing.iter() {
+            self.resident
+                .insert(*page, MemoryPage::of(*page).loaded_at(now));
+            self.swapped.remove(page);
+        }
+        let modified = resident.iter().any(|x| is_dirty(&self.resident, *x));
+        let partition = &mut self.partitions[index];
+        partition.used += missing.len() as u32;
+        track(partition.policy.as_mut(), head, modified);
+        if let Some(mmu) = &mut self.mmu {
+            // one translation replaces the ones of its base pages
+            for page in resident.iter() {
+                mmu.invalidate(*page);
+            }
+        }
+        if let Some(huge) = &mut self.huge {
+            huge.collapse(head, &missing);
+        }
+        Ok(())
+    }
+
+    /// Turn the huge page at head back into base pages the policy tracks one
+    /// by one
+    fn split(&mut self, head: PageId) -> Result<(), Error> {
+        let members = match &mut self.huge {
+            Some(huge) => huge.split(head).0,
+            None => return Ok(()),
+        };
+        if members.is_empty() {
+            return Ok(());
+        }
+        let index = self.partition(head.process)?;
+        let policy = &mut self.partitions[index].policy;
+        policy.remove(head);
+        for page in members {
+            track(policy.as_mut(), page, is_dirty(&self.resident, page));
+        }
+        if let Some(mmu) = &mut self.mmu {
+            mmu.invalidate(head);
+        }
+        Ok(())
+    }
+
+    /// check::huge on every partition
+    fn check_huge(&self, step: Time) -> Result<(), Error> {
+        let huge = match &self.huge {
+            Some(huge) => huge,
+            None => return Ok(()),
+        };
+        let heads = huge.heads();
+        for index in 0..self.partitions.len() {
+            let heads = heads
+                .iter()
+                .filter(|x| self.partition(x.process).ok() == Some(index))
+                .copied()
+                .collect::<Vec<PageId>>();
+            let after = self.partition_pages(index);
+            let span = huge.settings().span;
+            check::huge(step, self.partitions[index].frames, span, &heads, &after)?;
+        }
+        Ok(())
+    }
+
+    /// Wake the page-out daemon of every partition that dropped below the
+    /// low watermark, and have every awake one evict a batch towards the
+    /// high watermark. A daemon that only finds pinned pages gives up
+    fn kswapd(&mut self) -> Result<(), Error> {
+        let watermarks = match self.watermarks {
+            Some(watermarks) => watermarks,
+            None => return Ok(()),
+        };
+        // between this reference and the next
+        let now = self.time - 1;
+        for index in 0..self.partitions.len() {
+            let partition = &mut self.partitions[index];
+            let (low, high) = watermarks.within(partition.frames);
+            let free = |x: &Partition| x.frames.saturating_sub(x.used);
+            if !partition.reclaiming {
+                if free(partition) >= low {
+                    continue;
+                }
+                partition.reclaiming = true;
+                self.report = mem::take(&mut self.report).kswapd_woken();
+            }
+            for _ in 0..watermarks.batch {
+                if free(&self.partitions[index]) >= high {
+                    break;
+                }
+                match self.evict(index, now) {
+                    Ok(victim) => {
+                        self.write_back(&victim);
+                        self.report = mem::take(&mut self.report).background_reclaim();
+                    }
+                    Err(Error::OutOfFrames { .. }) => {
+                        self.partitions[index].reclaiming = false;
+                        break;
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            let partition = &mut self.partitions[index];
+            if free(partition) >= high {
+                partition.reclaiming = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// page left memory with no fault waiting on its frame, a dirty one is
+    /// written back in the background
+    fn write_back(&mut self, page: &MemoryPage) {
+        if let Some(swap) = &mut self.swap {
+            if page.is_modified() {
+                swap.write_back();
+            }
+        }
+    }
+
+    /// While the device has an idle slot, write back the dirty pages unused
+    /// longest so evicting them later costs no write. O(resident pages)
+    fn clean(&mut self) {
+        let count = match &self.swap {
+            Some(swap) => swap.cleanable(),
+            None => return,
+        };
+        if count == 0 {
+            return;
+        }
+        let mut dirty = self
+            .resident
+            .values()
+            .filter(|x| x.is_modified())
+            .map(|x| (x.last_used, x.id()))
+            .collect::<Vec<(Time, PageId)>>();
+        dirty.sort_unstable();
+        for (_, frame) in dirty.into_iter().take(count as usize) {
+            let page = self.resident.get_mut(&frame).unwrap();
+            *page = page.written_back();
+            self.swap.as_mut().unwrap().clean();
+            // a huge page stays dirty while any of its pages is
+            let tracked = self.huge.as_ref().and_then(|x| x.huge_head(frame));
+            let modified = match tracked {
+                Some(head) => self
+                    .huge
+                    .iter()
+                    .flat_map(|x| x.settings().members(head))
+                    .any(|x| is_dirty(&self.resident, x)),
+                None => false,
+            };
+            if let Ok(index) = self.partition(frame.process) {
+                let policy = self.partitions[index].policy.as_mut();
+                policy.modified(tracked.unwrap_or(frame), modified);
+            }
+        }
+    }
+
+    /// Drop the translation of every page reading frame, it left memory
+    fn forget(&mut self, frame: PageId) {
+        let mmu = match &mut self.mmu {
+            Some(mmu) => mmu,
+            None => return,
+        };
+        if !self.mappers.contains_key(&frame) {
+            mmu.invalidate(frame);
+            return;
+        }
+        for (page, _) in self.mapped.iter().filter(|(_, x)| **x == frame) {
+            mmu.invalidate(*page);
+        }
+    }
+
+    /// Once process has made a whole pff window of references, move a frame
+    /// between it and the free pool as its fault rate asks
+    /// A process never drops below one frame, and cannot grow while the pool
+    /// is empty
+    fn adjust(&mut self, process: Pid) -> Result<(), Error> {
+        let pff = match self.pff {
+            Some(pff) => pff,
+            None => return Ok(()),
+        };
+        let index = self.partition(process)?;
+        let partition = &mut self.partitions[index];
+        if partition.window_references < pff.window {
+            return Ok(());
+        }
+        let change = pff.change(partition.window_faults, partition.window_references);
+        partition.window_references = 0;
+        partition.window_faults = 0;
+        match change {
+            Change::Grow if self.free > 0 => {
+                partition.frames += 1;
+                self.free -= 1;
+            }
+            Change::Shrink if partition.frames > 1 => {
+                partition.frames -= 1;
+                self.free += 1;
+                if partition.used > partition.frames {
+                    match self.evict(index, self.time) {
+                        Ok(victim) => self.write_back(&victim),
+                        // Pinned pages hold every frame, none can be given up
+                        Err(Error::OutOfFrames { .. }) => {
+                            self.partitions[index].frames += 1;
+                            self.free -= 1;
+                            return Ok(());
+                        }
+                        Err(err) => return Err(err),
+                    }
+                }
+            }
+            _ => return Ok(()),
+        }
+        let frames = self.partitions[index].frames;
+        self.report = mem::take(&mut self.report).allocated(process, frames, self.time);
+        Ok(())
+    }
+
+    /// Swap out every page of process but the pinned ones, and refuse its
+    /// references until it is resumed
+    pub fn suspend(&mut self, process: Pid) -> Result<(), Error> {
+        let heads = match &self.huge {
+            Some(huge) => huge.heads(),
+            None => vec![],
+        };
+        for head in heads.into_iter().filter(|x| x.process == process) {
+            self.split(head)?;
+        }
+        let index = self.partition(process)?;
+        let mut pages = self
+            .resident
+            .keys()
+            // shared frames stay for the other processes mapping them
+            .filter(|x| {
+                x.process == process && !self.pinned.contains(x) && !self.mappers.contains_key(x)
+            })
+            .copied()
+            .collect::<Vec<PageId>>();
+        pages.sort_unstable();
+        for page in pages {
+            let frame = self.resident.remove(&page).unwrap();
+            let partition = &mut self.partitions[index];
+            partition.policy.remove(page);
+            partition.used -= 1;
+            self.report = mem::take(&mut self.report).removed(&frame, self.time);
+            self.write_back(&frame);
+            if self.prefetched.remove(&page) {
+                self.report = mem::take(&mut self.report).prefetch_wasted(1);
+            }
+            if let Some(pool) = &mut self.pool {
+                pool.invalidate(page);
+            }
+            self.forget(page);
+            self.swapped.insert(page);
+        }
+        let demoted = match &mut self.tiers {
+            Some(tiers) => tiers.remove_process(process),
+            None => vec![],
+        };
+        for page in demoted {
+            self.forget(page.id());
+            self.page_out(page);
+        }
+        self.suspended.insert(process);
+        self.report = mem::take(&mut self.report).suspended(process);
+        Ok(())
+    }
+
+    /// Let a suspended process reference pages again, they fault back in
+    pub fn resume(&mut self, process: Pid) {
+        self.suspended.remove(&process);
+    }
+
+    pub fn is_suspended(&self, process: Pid) -> bool {
+        self.suspended.contains(&process)
+    }
+
+    /// Index of the partition process takes frames from
+    fn partition(&self, process: Pid) -> Result<usize, Error> {
+        if self.partition_of.is_empty() {
+            return Ok(0);
+        }
+        self.partition_of
+            .get(&process)
+            .copied()
+            .ok_or_else(|| Error::InconsistentState(format!("process {} has no frames", process)))
+    }
+
+    fn partition_pages(&self, partition: usize) -> Vec<PageId> {
+        self.resident
+            .keys()
+            .filter(|x| self.partition(x.process).ok() == Some(partition))
+            .copied()
+            .collect()
+    }
+
+    fn pinned_pages(&self, partition: usize) -> Vec<PageId> {
+        self.pinned
+            .iter()
+            .filter(|x| self.partition(x.process).ok() == Some(partition))
+            .copied()
+            .collect()
+    }
+
+    /// Frames process may currently fill
+    pub fn frames_of(&self, process: Pid) -> Option<u32> {
+        let index = self.partition(process).ok()?;
+        Some(self.partitions[index].frames)
+    }
+
+    pub fn is_resident(&self, page: PageId) -> bool {
+        self.resident.contains_key(&self.frame(page))
+    }
+
+    /// Tier page is in: 0 for the frames, 1 and up for the slower tiers,
+    /// None when it is in neither
+    pub fn tier(&self, page: PageId) -> Option<usize> {
+        let frame = self.frame(page);
+        if self.resident.contains_key(&frame) {
+            return Some(0);
+        }
+        self.tiers.as_ref()?.tier(frame).map(|x| x + 1)
+    }
+
+    pub fn resident_count(&self) -> usize {
+        self.resident.len()
+    }
+
+    /// Resident pages of process, O(resident pages)
+    pub fn resident_count_of(&self, process: Pid) -> usize {
+        self.resident
+            .keys()
+            .filter(|x| x.process == process)
+            .count()
+    }
+
+    /// Frames of every partition plus the free pool
+    pub fn frames(&self) -> u32 {
+        self.partitions.iter().map(|x| x.frames).sum::<u32>() + self.free
+    }
+
+    pub fn report(&self) -> &PageReport {
+        &self.report
+    }
+
+    /// The report with residency closed out for every page still in memory
+    pub fn finish(self) -> PageReport {
+        let mut frames = self.resident.values().copied().collect::<Vec<MemoryPage>>();
+        frames.sort_by_key(|x| x.loaded_at);
+        let report = self
+            .report
+            .finish(&frames, self.time)
+            .prefetch_wasted(self.prefetched.len() as u64);
+        let report = match self.swap {
+            Some(swap) => report.with_swap(swap.report()),
+            None => report,
+        };
+        let report = match self.tiers {
+            Some(tiers) => report.with_tiers(tiers.report()),
+            None => report,
+        };
+        let report = match self.pool {
+            Some(pool) => report.with_pool(pool.report()),
+            None => report,
+        };
+        let report = match self.huge {
+            Some(huge) => report.with_huge(huge.report()),
+            None => report,
+        };
+        match self.mmu {
+            Some(mmu) => report.with_translation(mmu.report()),
+            None => report,
+        }
+    }
+
+    /// Apply every reference in turn, stopping at the first that could not
+    /// be read or simulated
+    pub fn run<I, R>(mut self, references: I) -> Result<PageReport, Error>
+    where
+        I: IntoIterator<Item = Result<R, Error>>,
+        R: Into<Reference>,
+    {
+        for reference in references {
+            self.apply(reference?.into())?;
+        }
+        Ok(self.finish())
+    }
+}
+
+/// Run every page of page_hit_order through policy with frames frames
+pub fn run(
+    policy: Box<dyn Policy>,
+    page_hit_order: &[PageId],
+    frames: u32,
+) -> Result<PageReport, Error> {
+    run_stream(policy, page_hit_order.iter().copied().map(Ok), frames)
+}
+
+/// Like run, but pulls references one at a time so the trace never has to
+/// be in memory. Stops at the first reference that could not be read
+pub fn run_stream<I, R>(
+    policy: Box<dyn Policy>,
+    references: I,
+    frames: u32,
+) -> Result<PageReport, Error>
+where
+    I: IntoIterator<Item = Result<R, Error>>,
+    R: Into<Reference>,
+{
+    Simulation::new(policy, frames)?.run(references)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::huge::Huge;
+    use crate::pages;
+    use crate::pff::Pff;
+    use crate::policy::{by_name, Fifo, Lru, Nru, POLICIES};
+    use crate::tier::Tiers;
+    use crate::workload::Workload;
+    use crate::zswap::{Pool, Zswap};
+    use crate::{algorithms, simulate, simulate_references, simulate_references_checked};
+
+    #[test]
+    fn access_should_report_outcome() {
+        let mut simulation = Simulation::new(Box::new(Fifo::new()), 2).unwrap();
+        assert_eq!(
+            simulation.access(0.into()),
+            Ok(Outcome::Fault { evicted: None })
+        );
+        assert_eq!(
+            simulation.access(1.into()),
+            Ok(Outcome::Fault { evicted: None })
+        );
+        assert_eq!(simulation.access(0.into()), Ok(Outcome::Hit));
+        assert_eq!(
+            simulation.access(2.into()),
+            Ok(Outcome::Fault {
+                evicted: Some(0.into())
+            })
+        );
+        assert!(simulation.is_resident(2.into()) && !simulation.is_resident(0.into()));
+        assert_eq!(simulation.resident_count(), 2);
+    }
+
+    #[test]
+    fn run_should_track_victims() {
+        let res = run(Box::new(Fifo::new()), &pages(&[0, 1, 2, 0, 3, 0]), 3).unwrap();
+        assert_eq!((res.hits(), res.faults(), res.removed_count()), (1, 5, 2));
+        assert_eq!(res.compulsory_faults(), 4);
+        assert_eq!(
+            res.victim_ages().iter().collect::<Vec<_>>(),
+            vec![(1, 1), (4, 1)]
+        );
+        assert_eq!(res.resident_time(0.into()), 4 + 1);
+    }
+
+    #[test]
+    fn run_stream_should_match_run() {
+        let trace = pages(&[0, 1, 2, 0, 3, 0, 4, 1]);
+        let expected = run(Box::new(Fifo::new()), &trace, 3);
+        let res = run_stream(Box::new(Fifo::new()), trace.into_iter().map(Ok), 3);
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn run_stream_should_stop_at_bad_reference() {
+        let bad = Error::Trace("line 2: bad".to_string());
+        let trace: Vec<Result<PageId, Error>> = vec![Ok(0.into()), Err(bad.clone()), Ok(1.into())];
+        let res = run_stream(Box::new(Fifo::new()), trace, 3);
+        assert_eq!(res, Err(bad));
+    }
+
+    #[test]
+    fn run_should_match_reference_algorithms() {
+        let trace = Workload::Uniform {
+            length: 300,
+            pages: 12,
+        }
+        .generate(5);
+        let references: [(&str, crate::Algorithm); 3] = [
+            ("fifo", algorithms::fifo),
+            ("lru", algorithms::lru),
+            ("second_chance", algorithms::second_chance),
+        ];
+        for (name, algorithm) in references.iter() {
+            for frames in 1..8 {
+                let numbers = trace.iter().map(|x| x.number).collect::<Vec<u32>>();
+                let expected = simulate(algorithm, &numbers, frames).unwrap();
+                let res = run(by_name(name, 0).unwrap(), &trace, frames).unwrap();
+                let summary = |x: &PageReport| {
+                    (
+                        (x.hits(), x.faults(), x.removed_count()),
+                        (x.compulsory_faults(), x.premature_evictions()),
+                        x.victim_ages().clone(),
+                        x.residency_summary(),
+                    )
+                };
+                assert_eq!(
+                    summary(&res),
+                    summary(&expected),
+                    "{} with {} frames",
+                    name,
+                    frames
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn pins_should_match_reference_algorithms() {
+        let mut trace = vec![Reference::Pin(0.into()), Reference::Pin(1.into())];
+        trace.extend(
+            Workload::Uniform {
+                length: 300,
+                pages: 12,
+            }
+            .generate(9)
+            .into_iter()
+            .map(Reference::from),
+        );
+        let references: [(&str, crate::Algorithm); 3] = [
+            ("fifo", algorithms::fifo),
+            ("lru", algorithms::lru),
+            ("second_chance", algorithms::second_chance),
+        ];
+        for (name, algorithm) in references.iter() {
+            for frames in 3..8 {
+                let expected = simulate_references_checked(algorithm, &trace, frames).unwrap();
+                let mut simulation = Simulation::new(by_name(name, 0).unwrap(), frames)
+                    .unwrap()
+                    .checked();
+                for reference in trace.iter() {
+                    simulation.apply(*reference).unwrap();
+                }
+                assert!(simulation.is_resident(0.into()) && simulation.is_resident(1.into()));
+                let res = simulation.finish();
+                assert_eq!(
+                    (res.hits(), res.faults(), res.removed_count()),
+                    (expected.hits(), expected.faults(), expected.removed_count()),
+                    "{} with {} frames",
+                    name,
+                    frames
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_policy_should_keep_pinned_pages() {
+        for name in POLICIES.iter() {
+            let mut simulation = Simulation::new(by_name(name, 3).unwrap(), 3)
+                .unwrap()
+                .checked();
+            simulation.pin(PageId::new(0, 7)).unwrap();
+            for page in pages(&[1, 2, 3, 4, 1, 5, 6, 2]) {
+                simulation.access(page).unwrap();
+            }
+            assert!(simulation.is_resident(PageId::new(0, 7)), "{}", name);
+            simulation.unpin(PageId::new(0, 7));
+            for number in 8..40 {
+                simulation.access(number.into()).unwrap();
+            }
+            assert!(!simulation.is_resident(PageId::new(0, 7)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn evicted_pages_should_leave_the_tlb() {
+        let translation = "entries=8,ways=8,levels=4/4".parse().unwrap();
+        let trace = pages(&[0, 1, 2, 0, 3, 0, 4, 2, 3, 0, 3, 2, 1, 2, 0]);
+        let references = trace
+            .iter()
+            .copied()
+            .map(Reference::from)
+            .collect::<Vec<_>>();
+        for name in POLICIES.iter() {
+            let report = Simulation::new(by_name(name, 1).unwrap(), 3)
+                .unwrap()
+                .checked()
+                .with_mmu(Mmu::new(&translation, 0))
+                .run(trace.iter().copied().map(Ok))
+                .unwrap();
+            let tlb = report.translation().unwrap();
+            // the TLB holds every page, so it misses exactly when the frames do
+            assert_eq!(tlb.misses(), report.faults(), "{}", name);
+            assert_eq!(tlb.hits(), report.hits(), "{}", name);
+            assert_eq!(
+                report.faults(),
+                run(by_name(name, 1).unwrap(), &trace, 3).unwrap().faults()
+            );
+        }
+        let report = Simulation::new(Box::new(Fifo::new()), 3)
+            .unwrap()
+            .with_mmu(Mmu::new(&translation, 0))
+            .run(trace.iter().copied().map(Ok))
+            .unwrap();
+        let legacy =
+            crate::simulate_translated(algorithms::fifo, &references, 3, Mmu::new(&translation, 0))
+                .unwrap();
+        assert_eq!(legacy.translation(), report.translation());
+        assert_eq!(legacy.faults(), report.faults());
+    }
+
+    #[test]
+    fn readahead_should_save_faults_on_a_scan() {
+        let scan = (0..62).map(PageId::from).collect::<Vec<PageId>>();
+        let run = |prefetch: &str| {
+            Simulation::new(Box::new(Fifo::new()), 8)
+                .unwrap()
+                .checked()
+                .with_prefetch(prefetch.parse().unwrap())
+                .run(scan.iter().copied().map(Ok))
+                .unwrap()
+        };
+        let demand = run("off");
+        assert_eq!((demand.faults(), demand.prefetches()), (62, 0));
+        let sequential = run("sequential:window=3");
+        assert_eq!(sequential.faults(), 16);
+        assert_eq!(sequential.useful_prefetches(), 46);
+        // the scan stops short of 62 and 63
+        assert_eq!(sequential.wasted_prefetches(), 2);
+        assert!(run("adaptive:initial=1,max=7").faults() < 16);
+        // the third reference confirms the stride, the rest are read ahead
+        assert_eq!(run("stride:degree=4").faults(), 3);
+    }
+
+    #[test]
+    fn prefetches_should_be_used_or_wasted() {
+        let trace = Workload::Zipf {
+            length: 500,
+            pages: 40,
+            exponent: 0.8,
+        }
+        .generate(5);
+        for name in POLICIES.iter() {
+            for prefetch in ["sequential:window=2", "adaptive", "stride"].iter() {
+                let report = Simulation::new(by_name(name, 5).unwrap(), 6)
+                    .unwrap()
+                    .checked()
+                    .with_prefetch(prefetch.parse().unwrap())
+                    .run(trace.iter().copied().map(Ok))
+                    .unwrap();
+                assert_eq!(report.references(), 500, "{} {}", name, prefetch);
+                assert_eq!(
+                    report.prefetches(),
+                    report.useful_prefetches() + report.wasted_prefetches(),
+                    "{} {}",
+                    name,
+                    prefetch
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn prefetching_should_stop_at_pinned_frames() {
+        let mut simulation = Simulation::new(Box::new(Fifo::new()), 2)
+            .unwrap()
+            .checked()
+            .with_prefetch("sequential:window=4".parse().unwrap());
+        simulation.pin(10.into()).unwrap();
+        assert!(simulation.is_resident(11.into()));
+        // 11 makes room for 0, which cannot make room for 1
+        simulation.access(0.into()).unwrap();
+        assert!(simulation.is_resident(10.into()) && simulation.is_resident(0.into()));
+        assert!(!simulation.is_resident(1.into()));
+        let report = simulation.finish();
+        assert_eq!((report.prefetches(), report.wasted_prefetches()), (1, 1));
+    }
+
+    #[test]
+    fn nru_should_evict_clean_pages_before_dirty_ones() {
+        for seed in 0..20 {
+            let mut simulation = Simulation::new(Box::new(Nru::new(seed)), 3)
+                .unwrap()
+                .checked();
+            simulation.apply(Reference::Write(0.into())).unwrap();
+            simulation.access(1.into()).unwrap();
+            simulation.apply(Reference::Write(2.into())).unwrap();
+            assert_eq!(
+                simulation.access(3.into()),
+                Ok(Outcome::Fault {
+                    evicted: Some(1.into())
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn dirty_victims_should_be_written_back() {
+        let trace = [
+            Reference::Write(0.into()),
+            Reference::from(1),
+            Reference::from(2),
+            Reference::Write(1.into()),
+            Reference::from(3),
+            Reference::from(4),
+        ];
+        let report = run_stream(Box::new(Fifo::new()), trace.iter().copied().map(Ok), 2).unwrap();
+        // 0 when 2 comes in, 1 when 3 does
+        assert_eq!(report.write_backs(), 2);
+        let legacy = simulate_references(algorithms::fifo, &trace, 2).unwrap();
+        assert_eq!(legacy.write_backs(), report.write_backs());
+    }
+
+    #[test]
+    fn swap_should_charge_faults_and_reward_cleaning() {
+        let trace = [
+            Reference::Write(0.into()),
+            Reference::Write(1.into()),
+            Reference::from(2),
+            Reference::from(3),
+        ];
+        let run = |spec: &str| {
+            Simulation::new(Box::new(Fifo::new()), 2)
+                .unwrap()
+                .checked()
+                .with_swap(Swap::new(&spec.parse().unwrap()))
+                .run(trace.iter().copied().map(Ok))
+                .unwrap()
+        };
+        let device = "read=1000,write=1000,bandwidth=4096,memory=0,queue_depth=2";
+        let report = run(device);
+        let swap = report.swap().unwrap();
+        // two plain reads, then two reads each behind a write-back
+        assert_eq!(swap.elapsed(), 2.0 * 1001.0 + 2.0 * 2002.0);
+        assert_eq!((swap.reads(), swap.writes()), (4, 2));
+        assert_eq!(report.write_backs(), 2);
+
+        let report = run(&format!("{},clean=1", device));
+        let swap = report.swap().unwrap();
+        // cleaning wrote 0 and 1 in the second slot, so no fault waited on a write
+        assert_eq!(swap.elapsed(), 4.0 * 1001.0);
+        assert_eq!((swap.cleaned(), swap.writes()), (2, 0));
+        assert_eq!(report.write_backs(), 0);
+        assert!(Simulation::new(Box::new(Fifo::new()), 2)
+            .unwrap()
+            .finish()
+            .swap()
+            .is_none());
+    }
+
+    #[test]
+    fn kswapd_should_keep_frames_free_for_faults() {
+        let scan = (0..20).map(PageId::from).collect::<Vec<PageId>>();
+        let run = |watermarks: Option<&str>| {
+            let simulation = Simulation::new(Box::new(Fifo::new()), 4).unwrap().checked();
+            let simulation = match watermarks {
+                Some(x) => simulation.with_watermarks(x.parse().unwrap()),
+                None => simulation,
+            };
+            simulation.run(scan.iter().copied().map(Ok)).unwrap()
+        };
+        let demand = run(None);
+        assert_eq!(demand.direct_reclaims(), 16);
+        assert_eq!(demand.background_reclaims(), 0);
+
+        // one page a reference keeps up with one fault a reference
+        let report = run(Some("low=1,high=2,batch=1"));
+        assert_eq!(report.direct_reclaims(), 0);
+        assert_eq!(report.background_reclaims(), report.removed_count());
+        assert_eq!(report.kswapd_wakeups(), 1);
+        // a bigger batch reaches the high watermark and sleeps in between
+        let report = run(Some("low=1,high=2,batch=2"));
+        assert_eq!(report.direct_reclaims(), 0);
+        assert!(report.kswapd_wakeups() > 1);
+    }
+
+    #[test]
+    fn kswapd_falling_behind_should_reclaim_directly() {
+        let scan = (0..40).map(PageId::from).collect::<Vec<PageId>>();
+        for name in POLICIES.iter() {
+            let report = Simulation::new(by_name(name, 2).unwrap(), 6)
+                .unwrap()
+                .checked()
+                .with_prefetch("sequential:window=4".parse().unwrap())
+                .with_watermarks("low=1,high=2,batch=1".parse().unwrap())
+                .run(scan.iter().copied().map(Ok))
+                .unwrap();
+            // readahead takes frames faster than the daemon frees them
+            assert!(report.direct_reclaims() > 0, "{}", name);
+            assert!(report.background_reclaims() > 0, "{}", name);
+        }
+    }
+
+    #[test]
+    fn kswapd_should_take_write_backs_off_the_fault_path() {
+        let trace = (0..20)
+            .map(|x| Reference::Write(x.into()))
+            .collect::<Vec<Reference>>();
+        let device = "read=1000,write=1000,bandwidth=4096,memory=0,queue_depth=2"
+            .parse()
+            .unwrap();
+        let run = |simulation: Simulation| {
+            simulation
+                .with_swap(Swap::new(&device))
+                .run(trace.iter().copied().map(Ok))
+                .unwrap()
+        };
+        let simulation = || Simulation::new(Box::new(Lru::new()), 4).unwrap();
+        let demand = run(simulation());
+        let swap = demand.swap().unwrap();
+        assert_eq!(swap.write_back_stall(), 16.0 * 1001.0);
+        let report = run(simulation().with_watermarks("low=1,high=1,batch=1".parse().unwrap()));
+        let background = report.swap().unwrap();
+        assert_eq!(background.write_back_stall(), 0.0);
+        // the daemon keeps a frame free, so one more page left
+        assert_eq!(background.writes(), swap.writes() + 1);
+        assert!(background.elapsed() < swap.elapsed());
+    }
+
+    fn tiers(spec: &str) -> Tiers {
+        Tiers::new(&spec.parse().unwrap())
+    }
+
+    #[test]
+    fn evicted_pages_should_be_demoted_to_the_slow_tier() {
+        let mut simulation = Simulation::new(Box::new(Fifo::new()), 2)
+            .unwrap()
+            .checked()
+            .with_tiers(tiers("tiers=2@300,hot=10"));
+        for page in pages(&[0, 1, 2, 3]) {
+            simulation.access(page).unwrap();
+        }
+        assert_eq!(simulation.tier(0.into()), Some(1));
+        assert_eq!(simulation.tier(3.into()), Some(0));
+        // found in the slow tier, not a fault
+        assert_eq!(simulation.access(0.into()), Ok(Outcome::Hit));
+        assert_eq!(simulation.tier(0.into()), Some(1));
+        simulation.access(4.into()).unwrap();
+        // 1 was the coldest page of the full slow tier
+        assert_eq!(simulation.tier(1.into()), None);
+        let report = simulation.finish();
+        assert_eq!((report.hits(), report.faults()), (1, 5));
+        assert_eq!(report.fast_hits(), 0);
+        let tiers = report.tiers().unwrap();
+        assert_eq!((tiers.hits(0), tiers.demotions()), (Some(1), 3));
+    }
+
+    #[test]
+    fn hot_pages_should_be_promoted_back() {
+        let mut simulation = Simulation::new(Box::new(Fifo::new()), 2)
+            .unwrap()
+            .checked()
+            .with_tiers(tiers("tiers=4@300,hot=2,window=10"));
+        for page in pages(&[0, 1, 2, 0]) {
+            simulation.access(page).unwrap();
+        }
+        assert_eq!(simulation.tier(0.into()), Some(1));
+        assert_eq!(simulation.access(0.into()), Ok(Outcome::Hit));
+        // 0 took the frame of 1, the oldest page
+        assert_eq!(simulation.tier(0.into()), Some(0));
+        assert_eq!(simulation.tier(1.into()), Some(1));
+        simulation.access(0.into()).unwrap();
+        let report = simulation.finish();
+        assert_eq!(
+            (report.fast_hits(), report.hits(), report.faults()),
+            (1, 3, 3)
+        );
+        let tiers = report.tiers().unwrap();
+        assert_eq!((tiers.promotions(), tiers.demotions()), (1, 2));
+        assert_eq!(tiers.migration_bytes(), 3 * 4096);
+        assert_eq!(tiers.access_time(1), (100.0 + 2.0 * 300.0) / 3.0);
+    }
+
+    #[test]
+    fn pages_leaving_the_slowest_tier_should_be_written_back() {
+        let trace = (0..6)
+            .map(|x| Reference::Write(x.into()))
+            .collect::<Vec<Reference>>();
+        let report = Simulation::new(Box::new(Lru::new()), 2)
+            .unwrap()
+            .with_tiers(tiers("tiers=1@300/1@900"))
+            .run(trace.into_iter().map(Ok))
+            .unwrap();
+        // 4 pages left the frames, only 2 left memory
+        assert_eq!(report.removed_count(), 4);
+        assert_eq!(report.write_backs(), 2);
+        let references = [
+            Reference::Pin(0.into()),
+            Reference::from(1),
+            Reference::from(2),
+        ];
+        let mut simulation = Simulation::new(Box::new(Lru::new()), 2)
+            .unwrap()
+            .checked()
+            .with_tiers(tiers("tiers=4@300,hot=5"));
+        for reference in references.iter() {
+            simulation.apply(*reference).unwrap();
+        }
+        simulation.unpin(0.into());
+        simulation.access(3.into()).unwrap();
+        assert_eq!(simulation.tier(0.into()), Some(1));
+        // pinning promotes however cold the page is
+        simulation.pin(0.into()).unwrap();
+        assert_eq!(simulation.tier(0.into()), Some(0));
+    }
+
+    #[test]
+    fn every_policy_should_keep_tiers_consistent() {
+        let trace = (0..300u32)
+            .map(|x| (x * x + x / 7) % 23)
+            .collect::<Vec<u32>>();
+        for name in POLICIES.iter() {
+            let mut simulation = Simulation::new(by_name(name, 4).unwrap(), 4)
+                .unwrap()
+                .checked()
+                .with_tiers(tiers("tiers=3@200/5@800,hot=2,window=20"))
+                .with_prefetch("sequential:window=2".parse().unwrap())
+                .with_watermarks("low=1,high=2,batch=1".parse().unwrap());
+            for page in pages(&trace) {
+                simulation.access(page).unwrap();
+            }
+            let report = simulation.finish();
+            let tiers = report.tiers().unwrap();
+            assert!(tiers.promotions() > 0 && tiers.slow_hits() > 0, "{}", name);
+            assert_eq!(report.hits() + report.faults(), 300);
+        }
+    }
+
+    fn pool(spec: &str) -> Pool {
+        Pool::new(&spec.parse().unwrap(), 1)
+    }
+
+    #[test]
+    fn evicted_pages_should_be_decompressed_from_the_pool() {
+        let trace = [
+            Reference::Write(0.into()),
+            Reference::Write(1.into()),
+            Reference::Write(2.into()),
+            Reference::Write(3.into()),
+            Reference::from(0),
+            Reference::from(1),
+        ];
+        let device = "read=1000,write=1000,bandwidth=4096,memory=0"
+            .parse()
+            .unwrap();
+        let report = Simulation::new(Box::new(Fifo::new()), 2)
+            .unwrap()
+            .checked()
+            .with_swap(Swap::new(&device))
+            // room for two pages compressed to half
+            .with_pool(pool("size=4096,ratio=2,decompress=10,policy=lru"))
+            .run(trace.iter().copied().map(Ok))
+            .unwrap();
+        // 0 came back from the pool, 1 was written out to make room for 2
+        assert_eq!((report.faults(), report.compulsory_faults()), (6, 4));
+        let res = report.pool().unwrap();
+        assert_eq!((res.hits(), report.swap_ins()), (1, 1));
+        assert_eq!((res.stores(), res.evictions()), (4, 2));
+        // the pool dropped its copy of 0 while 0 was back in the frames
+        assert_eq!(report.write_backs(), 1);
+        let swap = report.swap().unwrap();
+        assert_eq!((swap.reads(), swap.writes()), (5, 1));
+        // decompressing 0 took 10 of the 1001 writing 1 out, then reading 1
+        // back waited for the rest
+        assert_eq!(swap.stall(), 5.0 * 1001.0 + 991.0);
+    }
+
+    #[test]
+    fn per_page_state_should_stay_within_distinct_pages() {
+        let mut simulation = Simulation::new(Box::new(Lru::new()), 4).unwrap();
+        for number in 0..10_000u32 {
+            simulation.access(PageId::from(number % 10)).unwrap();
+        }
+        // seen and resident_per_page keep one entry per distinct page
+        // however long the run, evicted_at one per premature window
+        assert_eq!(simulation.seen.len(), 10);
+        let report = simulation.finish();
+        assert_eq!(report.resident_per_page.len(), 10);
+        assert!(report.evicted_at.len() as Time <= report.premature_window() + 1);
+    }
+
+    #[test]
+    fn prefetched_pages_evicted_unused_should_be_swap_ins() {
+        let mut simulation = Simulation::new(Box::new(Fifo::new()), 3)
+            .unwrap()
+            .checked()
+            .with_prefetch("sequential:window=2".parse().unwrap());
+        for page in pages(&[0, 10, 20, 1]) {
+            simulation.access(page).unwrap();
+        }
+        let report = simulation.finish();
+        // 1 was read ahead of 0 and evicted unused, its first reference
+        // reads it back from the backing store
+        assert_eq!((report.faults(), report.compulsory_faults()), (4, 4));
+        assert_eq!(report.swap_ins(), 1);
+    }
+
+    #[test]
+    fn prefetched_pages_back_from_the_pool_should_not_be_swap_ins() {
+        let mut simulation = Simulation::new(Box::new(Fifo::new()), 3)
+            .unwrap()
+            .checked()
+            .with_pool(Pool::new(&Zswap::default(), 1))
+            .with_prefetch("sequential:window=2".parse().unwrap());
+        for page in pages(&[0, 10, 20, 1]) {
+            simulation.access(page).unwrap();
+        }
+        let report = simulation.finish();
+        // 1 was read ahead of 0, pushed into the pool unused by 10 and 20,
+        // and its first reference faults it back from there
+        assert_eq!((report.faults(), report.compulsory_faults()), (4, 4));
+        assert_eq!((report.pool().unwrap().hits(), report.swap_ins()), (1, 0));
+    }
+
+    #[test]
+    fn every_policy_should_keep_the_pool_consistent() {
+        let trace = (0..300u32)
+            .map(|x| (x * x + x / 7) % 23)
+            .collect::<Vec<u32>>();
+        for name in POLICIES.iter() {
+            let mut simulation = Simulation::new(by_name(name, 4).unwrap(), 4)
+                .unwrap()
+                .checked()
+                .with_tiers(tiers("tiers=3@200,hot=2,window=20"))
+                .with_pool(pool("size=16384,ratio=1.5..4,policy=clock"))
+                .with_prefetch("sequential:window=2".parse().unwrap());
+            for (i, page) in pages(&trace).into_iter().enumerate() {
+                if i % 3 == 0 {
+                    simulation.apply(Reference::Write(page)).unwrap();
+                } else {
+                    simulation.access(page).unwrap();
+                }
+            }
+            let report = simulation.finish();
+            let pool = report.pool().unwrap();
+            assert!(pool.hits() > 0 && pool.evictions() > 0, "{}", name);
+            assert!(report.swap_ins() > 0, "{}", name);
+            assert_eq!(report.hits() + report.faults(), 300);
+        }
+    }
+
+    fn huge(spec: &str) -> Huge {
+        Huge::new(spec.parse().unwrap())
+    }
+
+    #[test]
+    fn hot_regions_should_collapse_into_huge_pages() {
+        let translation = "entries=4,ways=4,levels=4/4/4".parse().unwrap();
+        let mut simulation = Simulation::new(Box::new(Lru::new()), 8)
+            .unwrap()
+            .checked()
+            .with_mmu(Mmu::new(&translation, 0))
+            .with_huge(huge("span=4,promote=2,window=4"));
+        for page in pages(&[0, 1, 9, 10]) {
+            simulation.access(page).unwrap();
+        }
+        // both regions were filled in, taking all 8 frames
+        assert_eq!(simulation.resident_count(), 8);
+        assert_eq!(simulation.access(2.into()), Ok(Outcome::Hit));
+        // the region at 8 was used least recently, and 8 never was
+        assert_eq!(
+            simulation.access(20.into()),
+            Ok(Outcome::Fault {
+                evicted: Some(8.into())
+            })
+        );
+        let report = simulation.finish();
+        assert_eq!((report.hits(), report.faults()), (1, 5));
+        let huge = report.huge().unwrap();
+        assert_eq!((huge.collapses(), huge.splits()), (2, 1));
+        // 3, 8 and 11 unused after the scan, 3 alone once 8 was split
+        assert_eq!(huge.mean_wasted_bytes(), 4.0 * 4096.0 / 6.0);
+        assert_eq!(huge.peak_wasted_bytes(), 3 * 4096);
+        assert!(report.translation().unwrap().reach_gain() > 1.0);
+    }
+
+    #[test]
+    fn regions_with_pages_used_before_should_not_collapse() {
+        let mut simulation = Simulation::new(Box::new(Fifo::new()), 4)
+            .unwrap()
+            .checked()
+            .with_huge(huge("span=4,promote=2,window=2"));
+        // 2 was evicted, collapsing cannot make it up
+        for page in pages(&[2, 4, 8, 12, 0, 1]) {
+            simulation.access(page).unwrap();
+        }
+        let report = simulation.finish();
+        assert_eq!(report.huge().unwrap().collapses(), 0);
+        let mut simulation = Simulation::new(Box::new(Fifo::new()), 4)
+            .unwrap()
+            .checked()
+            .with_huge(huge("span=4,promote=2,window=4"));
+        simulation.pin(4.into()).unwrap();
+        for page in pages(&[0, 1, 1]) {
+            simulation.access(page).unwrap();
+        }
+        // the huge page would need the frame pinned by 4
+        assert_eq!(simulation.finish().huge().unwrap().collapses(), 0);
+    }
+
+    #[test]
+    fn every_policy_should_keep_huge_pages_whole() {
+        let trace = (0..400u32)
+            .map(|x| (x / 3 % 5) * 8 + (x * 7 + x / 11) % 6)
+            .collect::<Vec<u32>>();
+        let translation = "entries=8,ways=2,levels=4/4/4".parse().unwrap();
+        for name in POLICIES.iter() {
+            let mut simulation = Simulation::new(by_name(name, 4).unwrap(), 20)
+                .unwrap()
+                .checked()
+                .with_mmu(Mmu::new(&translation, 0))
+                .with_huge(huge("span=8,promote=3,window=16"))
+                .with_watermarks("low=1,high=2,batch=1".parse().unwrap());
+            simulation.pin(3.into()).unwrap();
+            for page in pages(&trace) {
+                simulation.access(page).unwrap();
+            }
+            let report = simulation.finish();
+            let huge = report.huge().unwrap();
+            assert!(huge.collapses() > 0 && huge.splits() > 0, "{}", name);
+            assert_eq!(report.hits() + report.faults(), 401);
+        }
+    }
+
+    #[test]
+    fn all_pinned_frames_should_be_out_of_frames() {
+        let trace = [
+            Reference::Pin(0.into()),
+            Reference::Pin(1.into()),
+            Reference::from(2),
+        ];
+        for name in POLICIES.iter() {
+            let res = run_stream(by_name(name, 0).unwrap(), trace.iter().copied().map(Ok), 2);
+            assert_eq!(res, Err(Error::OutOfFrames { frames: 2 }), "{}", name);
+        }
+        let res = simulate_references(algorithms::second_chance, &trace, 2);
+        assert_eq!(res, Err(Error::OutOfFrames { frames: 2 }));
+        let unpinned = [trace[0], trace[1], Reference::Unpin(1.into()), trace[2]];
+        let res = run_stream(Box::new(Fifo::new()), unpinned.iter().copied().map(Ok), 2);
+        assert_eq!(res.map(|x| x.removed_count()), Ok(1));
+    }
+
+    #[test]
+    fn shared_frame_should_be_one_resident_unit() {
+        let mut simulation = Simulation::new(Box::new(Fifo::new()), 2).unwrap().checked();
+        simulation
+            .share(PageId::new(1, 0), PageId::new(0, 0))
+            .unwrap();
+        for page in [(0, 0), (1, 0), (0, 1), (1, 0)].iter() {
+            simulation.access(PageId::new(page.0, page.1)).unwrap();
+        }
+        assert_eq!(simulation.resident_count(), 2);
+        let report = simulation.finish();
+        assert_eq!((report.hits(), report.faults()), (2, 2));
+        assert_eq!(report.process(1).unwrap().hits(), 2);
+    }
+
+    #[test]
+    fn write_to_shared_frame_should_copy_it() {
+        let mut simulation = Simulation::new(Box::new(Fifo::new()), 3).unwrap().checked();
+        let (parent, child) = (PageId::new(0, 4), PageId::new(1, 4));
+        simulation.access(parent).unwrap();
+        // sharing a resident page keeps it resident
+        simulation.share(child, parent).unwrap();
+        assert_eq!(simulation.access(child), Ok(Outcome::Hit));
+        assert_eq!(
+            simulation.write(child),
+            Ok(Outcome::Fault { evicted: None })
+        );
+        assert_eq!(simulation.resident_count(), 2);
+        // both have frames of their own now, writing is no longer a copy
+        assert_eq!(simulation.write(child), Ok(Outcome::Hit));
+        assert_eq!(simulation.write(parent), Ok(Outcome::Hit));
+        assert!(simulation.share(child, parent).is_err());
+        let report = simulation.finish();
+        assert_eq!(report.cow_faults(), 1);
+        assert_eq!(
+            (report.compulsory_faults(), report.capacity_faults()),
+            (1, 0)
+        );
+        assert_eq!(report.process(1).unwrap().cow_faults(), 1);
+        assert!(report.to_string().contains("| cow: 1 |"));
+    }
+
+    #[test]
+    fn every_policy_should_keep_shared_frames_consistent() {
+        let workload = Workload::Uniform {
+            length: 200,
+            pages: 6,
+        };
+        let mut trace = (0..6)
+            .flat_map(|x| {
+                vec![
+                    Reference::Share {
+                        page: PageId::new(1, x),
+                        with: PageId::new(0, x),
+                    },
+                    Reference::Share {
+                        page: PageId::new(2, x),
+                        with: PageId::new(0, x),
+                    },
+                ]
+            })
+            .collect::<Vec<Reference>>();
+        let pages = Workload::Interleave(vec![workload; 3]).generate(4);
+        trace.extend(pages.iter().enumerate().map(|(i, page)| match i % 7 {
+            0 => Reference::Write(*page),
+            _ => Reference::Access(*page),
+        }));
+        for name in POLICIES.iter() {
+            for frames in 1..10 {
+                let simulation = Simulation::new(by_name(name, 1).unwrap(), frames)
+                    .unwrap()
+                    .checked();
+                let report = simulation
+                    .run(trace.iter().copied().map(Ok))
+                    .unwrap_or_else(|err| panic!("{} with {} frames: {}", name, frames, err));
+                assert_eq!(report.references(), 600);
+                assert!(report.cow_faults() > 0 && report.cow_faults() <= 12);
+            }
+        }
+    }
+
+    /// Evicts whatever it is told to, regardless of what is resident
+    struct Broken {
+        victim: Option<PageId>,
+    }
+
+    impl Policy for Broken {
+        fn hit(&mut self, _page: PageId) {}
+        fn insert(&mut self, _page: PageId) {}
+        fn evict(&mut self) -> Option<PageId> {
+            self.victim
+        }
+        fn remove(&mut self, _page: PageId) {}
+    }
+
+    #[test]
+    fn zero_frames_should_be_rejected() {
+        assert_eq!(
+            Simulation::new(Box::new(Fifo::new()), 0).err(),
+            Some(Error::NoFrames)
+        );
+        assert_eq!(
+            run(Box::new(Fifo::new()), &pages(&[0, 1]), 0),
+            Err(Error::NoFrames)
+        );
+    }
+
+    #[test]
+    fn policy_without_victim_should_fail() {
+        let res = run(Box::new(Broken { victim: None }), &pages(&[0, 1, 2]), 2);
+        assert_eq!(res, Err(Error::NothingToEvict));
+    }
+
+    #[test]
+    fn policy_evicting_non_resident_page_should_fail() {
+        let res = run(
+            Box::new(Broken {
+                victim: Some(9.into()),
+            }),
+            &pages(&[0, 1, 2]),
+            2,
+        );
+        assert_eq!(
+            res,
+            Err(Error::InconsistentState(
+                "policy evicted page 9 which is not resident".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn checked_should_match_unchecked() {
+        let trace = Workload::Zipf {
+            length: 500,
+            pages: 30,
+            exponent: 0.9,
+        }
+        .generate(2);
+        for name in POLICIES.iter() {
+            for frames in [1, 4, 9].iter() {
+                let references = || trace.iter().copied().map(Ok);
+                let simulation = || Simulation::new(by_name(name, 3).unwrap(), *frames).unwrap();
+                assert_eq!(
+                    simulation().checked().run(references()),
+                    simulation().run(references()),
+                    "{} with {} frames",
+                    name,
+                    frames
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn checked_should_number_the_failing_step() {
+        let broken = Broken {
+            victim: Some(9.into()),
+        };
+        let simulation = Simulation::new(Box::new(broken), 2).unwrap();
+        let res = simulation
+            .checked()
+            .run(pages(&[0, 1, 0, 2]).into_iter().map(Ok));
+        assert_eq!(
+            res.err().map(|x| x.to_string()),
+            Some("step 3: policy evicted page 9 which is not resident".to_string())
+        );
+    }
+
+    #[test]
+    fn processes_should_have_separate_pages() {
+        let trace = [(1, 0), (2, 0), (1, 0), (2, 1), (1, 1)]
+            .iter()
+            .map(|(pid, page)| PageId::new(*pid, *page))
+            .collect::<Vec<PageId>>();
+        let res = run(Box::new(Fifo::new()), &trace, 2).unwrap();
+        assert_eq!((res.hits(), res.faults(), res.removed_count()), (1, 4, 2));
+        let process = |pid| {
+            let x = res.process(pid).unwrap();
+            (x.hits(), x.faults(), x.removed_count())
+        };
+        // 1:0 then 2:0 were the oldest when 2:1 and 1:1 faulted
+        assert_eq!(process(1), (1, 2, 1));
+        assert_eq!(process(2), (0, 2, 1));
+    }
+
+    fn shares(shares: &[(Pid, u32)]) -> BTreeMap<Pid, u32> {
+        shares.iter().copied().collect()
+    }
+
+    #[test]
+    fn local_should_only_evict_pages_of_the_faulting_process() {
+        let mut simulation =
+            Simulation::local(&shares(&[(1, 1), (2, 2)]), |_| Box::new(Fifo::new())).unwrap();
+        for page in [(2, 0), (2, 1), (1, 0)].iter() {
+            simulation.access(PageId::new(page.0, page.1)).unwrap();
+        }
+        // process 2 is full, but process 1 may only replace its own page
+        assert_eq!(
+            simulation.access(PageId::new(1, 1)),
+            Ok(Outcome::Fault {
+                evicted: Some(PageId::new(1, 0))
+            })
+        );
+        assert_eq!(
+            simulation.access(PageId::new(2, 2)),
+            Ok(Outcome::Fault {
+                evicted: Some(PageId::new(2, 0))
+            })
+        );
+        let report = simulation.finish();
+        assert_eq!(report.process(1).unwrap().frames(), Some(1));
+        assert_eq!(report.process(2).unwrap().removed_count(), 1);
+    }
+
+    #[test]
+    fn local_should_reject_unknown_processes() {
+        let mut simulation =
+            Simulation::local(&shares(&[(1, 2)]), |_| Box::new(Fifo::new())).unwrap();
+        assert_eq!(
+            simulation.access(PageId::new(3, 0)),
+            Err(Error::InconsistentState(
+                "process 3 has no frames".to_string()
+            ))
+        );
+        let res = Simulation::local(&shares(&[(1, 2), (2, 0)]), |_| Box::new(Fifo::new()));
+        assert_eq!(res.err(), Some(Error::NoFrames));
+    }
+
+    #[test]
+    fn local_with_one_process_should_match_global() {
+        let trace = Workload::Zipf {
+            length: 400,
+            pages: 25,
+            exponent: 0.8,
+        }
+        .generate(6);
+        for name in POLICIES.iter() {
+            let references = || trace.iter().copied().map(Ok);
+            let global = Simulation::new(by_name(name, 1).unwrap(), 5).unwrap();
+            let local = Simulation::local(&shares(&[(0, 5)]), |_| by_name(name, 1).unwrap());
+            let summary = |x: PageReport| (x.hits(), x.faults(), x.victim_ages().clone());
+            assert_eq!(
+                local.unwrap().checked().run(references()).map(summary),
+                global.run(references()).map(summary),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn every_policy_should_run_under_local_scope() {
+        let workload = Workload::Zipf {
+            length: 200,
+            pages: 15,
+            exponent: 0.9,
+        };
+        let trace = Workload::Interleave(vec![workload; 3]).generate(8);
+        for name in POLICIES.iter() {
+            let simulation = Simulation::local(&shares(&[(0, 4), (1, 2), (2, 1)]), |_| {
+                by_name(name, 2).unwrap()
+            })
+            .unwrap()
+            .checked();
+            let report = simulation.run(trace.iter().copied().map(Ok)).unwrap();
+            let faults = report
+                .processes()
+                .map(|(_, x)| x.faults())
+                .collect::<Vec<_>>();
+            // fewer frames can only mean more faults on the same pages
+            assert!(faults[0] <= faults[2], "{} faults {:?}", name, faults);
+        }
+    }
+
+    #[test]
+    fn policy_evicting_another_process_should_fail() {
+        let broken = |pid: Pid| -> Box<dyn Policy> {
+            Box::new(Broken {
+                victim: Some(PageId::new(1 - pid, 0)),
+            })
+        };
+        let mut simulation = Simulation::local(&shares(&[(0, 1), (1, 1)]), broken).unwrap();
+        simulation.access(PageId::new(0, 0)).unwrap();
+        simulation.access(PageId::new(1, 0)).unwrap();
+        assert_eq!(
+            simulation.access(PageId::new(1, 1)),
+            Err(Error::InconsistentState(
+                "policy evicted page 0 of another process".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn pff_should_move_frames_to_the_faulting_process() {
+        // process 0 keeps hitting one page, process 1 loops over 5 pages
+        let trace = (0..400)
+            .map(|i| match i % 2 {
+                0 => PageId::new(0, 0),
+                _ => PageId::new(1, (i / 2) % 5),
+            })
+            .map(Ok);
+        let pff = Pff::new(10, 0.1, 0.3).unwrap();
+        let simulation = Simulation::local(&shares(&[(0, 3), (1, 3)]), |_| Box::new(Fifo::new()))
+            .unwrap()
+            .with_pff(pff)
+            .checked();
+        let report = simulation.run(trace).unwrap();
+        let quiet = report.process(0).unwrap();
+        let busy = report.process(1).unwrap();
+        // the first window has its compulsory fault, a rate of exactly lower
+        assert_eq!(quiet.allocations(), &[(0, 3), (40, 2), (60, 1)]);
+        assert_eq!(&busy.allocations()[..3], &[(0, 3), (41, 4), (61, 5)]);
+        // once the loop fits nothing faults, the process gives a frame back
+        // and starts faulting again, never holding more than the free frames
+        assert!(busy.allocations()[3..]
+            .iter()
+            .all(|(_, x)| *x == 4 || *x == 5));
+        assert_eq!(busy.max_frames(), Some(5));
+    }
+}
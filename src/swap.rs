@@ -0,0 +1,357 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Backing store pages are read from on a fault and written to when a dirty
+/// page leaves memory. Times are in nanoseconds, like the default cost model
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Device {
+    /// Time before a read starts moving data
+    pub read: f64,
+    /// Time before a write starts moving data
+    pub write: f64,
+    /// Bytes moved per nanosecond once a request runs
+    pub bandwidth: f64,
+    /// Requests the device serves at once, later ones wait for a slot
+    pub queue_depth: u32,
+    pub page_size: u64,
+    /// Time a reference to a resident page takes
+    pub memory: f64,
+    /// Dirty pages background cleaning writes after a reference while the
+    /// device has an idle slot, 0 to only write pages back on eviction
+    pub clean: u32,
+}
+
+impl Default for Device {
+    /// A disk taking a few milliseconds per request, one at a time
+    fn default() -> Device {
+        Device {
+            read: 5_000_000.0,
+            write: 5_000_000.0,
+            bandwidth: 0.1,
+            queue_depth: 1,
+            page_size: 4096,
+            memory: 100.0,
+            clean: 0,
+        }
+    }
+}
+
+impl Device {
+    pub fn validate(self) -> Result<Device, String> {
+        if self.read < 0.0 || self.write < 0.0 || self.memory < 0.0 {
+            return Err("swap latencies must not be negative".to_string());
+        }
+        if self.bandwidth <= 0.0 {
+            return Err(format!(
+                "swap bandwidth must be positive, got {}",
+                self.bandwidth
+            ));
+        }
+        if self.queue_depth == 0 {
+            return Err("swap queue_depth must be at least 1".to_string());
+        }
+        Ok(self)
+    }
+
+    /// Time the data of one page takes to move
+    pub fn transfer(&self) -> f64 {
+        self.page_size as f64 / self.bandwidth
+    }
+}
+
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "swap(read={},write={},bandwidth={},queue_depth={},page_size={},memory={},clean={})",
+            self.read,
+            self.write,
+            self.bandwidth,
+            self.queue_depth,
+            self.page_size,
+            self.memory,
+            self.clean
+        )
+    }
+}
+
+/// Comma separated read=R, write=R, bandwidth=R, queue_depth=N,
+/// page_size=N, memory=R and clean=N, missing ones keep their defaults
+impl FromStr for Device {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Device, String> {
+        let mut device = Device::default();
+        for part in s.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("swap option '{}' needs a value", part))?;
+            let expected = || format!("swap {} expects a number, got '{}'", key, value);
+            match key {
+                "read" => device.read = value.parse().map_err(|_| expected())?,
+                "write" => device.write = value.parse().map_err(|_| expected())?,
+                "bandwidth" => device.bandwidth = value.parse().map_err(|_| expected())?,
+                "queue_depth" => device.queue_depth = value.parse().map_err(|_| expected())?,
+                "page_size" => device.page_size = value.parse().map_err(|_| expected())?,
+                "memory" => device.memory = value.parse().map_err(|_| expected())?,
+                "clean" => device.clean = value.parse().map_err(|_| expected())?,
+                _ => return Err(format!("unknown swap option '{}'", key)),
+            }
+        }
+        device.validate()
+    }
+}
+
+/// 'off', 'on' for the default device, or its options as FromStr reads them
+pub fn parse_spec(spec: &str) -> Result<Option<Device>, String> {
+    match spec.trim() {
+        "off" => Ok(None),
+        "on" => Ok(Some(Device::default())),
+        options => options.parse().map(Some),
+    }
+}
+
+/// What the device did over a run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SwapReport {
+    pub(crate) reads: u64,
+    pub(crate) writes: u64,
+    pub(crate) cleaned: u64,
+    pub(crate) waits: u64,
+    pub(crate) stall: f64,
+    pub(crate) write_back_stall: f64,
+    pub(crate) elapsed: f64,
+}
+
+impl SwapReport {
+    /// Pages read, by faults and prefetching
+    pub fn reads(&self) -> u64 {
+        self.reads
+    }
+
+    /// Dirty pages written back as they were evicted
+    pub fn writes(&self) -> u64 {
+        self.writes
+    }
+
+    /// Dirty pages background cleaning wrote while they stayed resident
+    pub fn cleaned(&self) -> u64 {
+        self.cleaned
+    }
+
+    /// Requests that found every slot of the queue busy
+    pub fn waits(&self) -> u64 {
+        self.waits
+    }
+
+    /// Time references spent waiting on the device
+    pub fn stall(&self) -> f64 {
+        self.stall
+    }
+
+    /// The part of stall faults spent waiting for the dirty page they
+    /// evicted to be written, what reclaiming ahead of them saves
+    pub fn write_back_stall(&self) -> f64 {
+        self.write_back_stall
+    }
+
+    /// Simulated time from the first reference to the end of the last
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// References completed per millisecond of simulated time
+    pub fn throughput(&self, references: u64) -> f64 {
+        if self.elapsed == 0.0 {
+            return 0.0;
+        }
+        references as f64 * 1e6 / self.elapsed
+    }
+}
+
+/// Simulated clock of a run and the requests queued on its device
+///
+/// A fault waits for the dirty page it evicts to be written, then for its
+/// own page to be read. Every other request runs in the background, taking
+/// a slot of the queue from the faults that come after it
+pub struct Swap {
+    device: Device,
+    /// Time each slot of the queue is busy until
+    slots: Vec<f64>,
+    now: f64,
+    reads: u64,
+    writes: u64,
+    cleaned: u64,
+    waits: u64,
+    stall: f64,
+    write_back_stall: f64,
+}
+
+impl Swap {
+    pub fn new(device: &Device) -> Swap {
+        Swap {
+            device: *device,
+            slots: vec![0.0; device.queue_depth as usize],
+            now: 0.0,
+            reads: 0,
+            writes: 0,
+            cleaned: 0,
+            waits: 0,
+            stall: 0.0,
+            write_back_stall: 0.0,
+        }
+    }
+
+    /// Queue a request arriving at time at in the slot free soonest, and
+    /// return when it completes
+    fn submit(&mut self, at: f64, latency: f64) -> f64 {
+        let (slot, free) = self
+            .slots
+            .iter()
+            .copied()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+        if free > at {
+            self.waits += 1;
+        }
+        let done = free.max(at) + latency + self.device.transfer();
+        self.slots[slot] = done;
+        done
+    }
+
+    /// A reference found its page resident
+    pub fn hit(&mut self) {
+        self.access(self.device.memory);
+    }
+
+    /// A reference found its page in memory that takes latency to reach
+    pub fn access(&mut self, latency: f64) {
+        self.now += latency;
+    }
+
+    /// A reference faulted, read tells whether its page comes from the
+    /// device and write_back whether the page it evicted was dirty. A copy on
+    /// write fault copies a resident page and reads nothing
+    pub fn fault(&mut self, read: bool, write_back: bool) {
+        let mut ready = self.now;
+        if write_back {
+            ready = self.submit(ready, self.device.write);
+            self.writes += 1;
+            self.write_back_stall += ready - self.now;
+        }
+        if read {
+            ready = self.submit(ready, self.device.read);
+            self.reads += 1;
+        }
+        self.stall += ready - self.now;
+        self.now = ready + self.device.memory;
+    }
+
+    /// A page read ahead of its reference, nothing waits for it
+    pub fn prefetch(&mut self) {
+        self.submit(self.now, self.device.read);
+        self.reads += 1;
+    }
+
+    /// A dirty page left memory without a fault waiting on the frame
+    pub fn write_back(&mut self) {
+        self.submit(self.now, self.device.write);
+        self.writes += 1;
+    }
+
+    /// Dirty pages cleaning may write now, without making anything wait
+    pub fn cleanable(&self) -> u32 {
+        if !self.slots.iter().any(|x| *x <= self.now) {
+            return 0;
+        }
+        self.device.clean
+    }
+
+    /// Background cleaning wrote a resident dirty page
+    pub fn clean(&mut self) {
+        self.submit(self.now, self.device.write);
+        self.cleaned += 1;
+    }
+
+    pub fn report(&self) -> SwapReport {
+        SwapReport {
+            reads: self.reads,
+            writes: self.writes,
+            cleaned: self.cleaned,
+            waits: self.waits,
+            stall: self.stall,
+            write_back_stall: self.write_back_stall,
+            elapsed: self.now,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(spec: &str) -> Device {
+        spec.parse().unwrap()
+    }
+
+    #[test]
+    fn fault_should_wait_for_write_back_then_read() {
+        let mut swap = Swap::new(&device("read=1000,write=3000,bandwidth=4096,memory=1"));
+        swap.hit();
+        swap.fault(true, false);
+        swap.fault(true, true);
+        let report = swap.report();
+        assert_eq!((report.reads(), report.writes()), (2, 1));
+        // every request also moves a page in 1
+        assert_eq!(report.stall(), 1001.0 + 3001.0 + 1001.0);
+        assert_eq!(report.elapsed(), 3.0 + report.stall());
+        assert_eq!(report.write_back_stall(), 3001.0);
+        assert_eq!(report.waits(), 0);
+    }
+
+    #[test]
+    fn background_requests_should_delay_faults_behind_them() {
+        let spec = "read=100,write=100,bandwidth=4096,memory=0";
+        let mut swap = Swap::new(&device(spec));
+        swap.prefetch();
+        swap.fault(true, false);
+        assert_eq!(swap.report().stall(), 202.0);
+        assert_eq!(swap.report().waits(), 1);
+
+        // a second slot serves the fault next to the prefetch
+        let mut swap = Swap::new(&device(&format!("{},queue_depth=2", spec)));
+        swap.prefetch();
+        swap.fault(true, false);
+        assert_eq!(swap.report().stall(), 101.0);
+    }
+
+    #[test]
+    fn cleaning_should_only_use_idle_slots() {
+        let mut swap = Swap::new(&device("clean=2,memory=0"));
+        assert_eq!(swap.cleanable(), 2);
+        swap.clean();
+        assert_eq!(swap.cleanable(), 0);
+        swap.fault(true, false);
+        assert_eq!(swap.cleanable(), 2);
+        assert_eq!(swap.report().cleaned(), 1);
+        assert_eq!(Swap::new(&Device::default()).cleanable(), 0);
+    }
+
+    #[test]
+    fn parse_should_fill_defaults_and_reject_bad_settings() {
+        assert_eq!("".parse::<Device>(), Ok(Device::default()));
+        let res = device("read=10, queue_depth=4, clean=8");
+        assert_eq!(
+            res.to_string(),
+            "swap(read=10,write=5000000,bandwidth=0.1,queue_depth=4,page_size=4096,memory=100,clean=8)"
+        );
+        assert_eq!(res.transfer(), 40960.0);
+        assert!("queue_depth=0".parse::<Device>().is_err());
+        assert!("bandwidth=0".parse::<Device>().is_err());
+        assert!("read=-1".parse::<Device>().is_err());
+        assert!("latency=3".parse::<Device>().is_err());
+        assert_eq!(parse_spec("off"), Ok(None));
+        assert_eq!(parse_spec("on"), Ok(Some(Device::default())));
+    }
+}
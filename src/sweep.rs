@@ -0,0 +1,101 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+/// Called after every finished job with (jobs done, total jobs), always from
+/// the thread that started the sweep so calls never overlap
+pub type Progress<'a> = &'a dyn Fn(usize, usize);
+
+/// Threads to use when the caller asks for 0, one per available core
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |x| x.get())
+}
+
+/// Run job over every input on a pool of threads
+///
+/// Inputs are handed out one at a time so long and short jobs balance out.
+/// Results come back in the same order as inputs regardless of which thread
+/// finished first
+pub fn run<T, R, F>(inputs: &[T], threads: usize, job: F, progress: Progress) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let threads = match threads {
+        0 => default_threads(),
+        x => x,
+    }
+    .min(inputs.len())
+    .max(1);
+    let next = AtomicUsize::new(0);
+    let mut results = Vec::with_capacity(inputs.len());
+
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..threads {
+            let (sender, next, job) = (sender.clone(), &next, &job);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let input = match inputs.get(index) {
+                    Some(x) => x,
+                    None => break,
+                };
+                if sender.send((index, job(input))).is_err() {
+                    break;
+                }
+            });
+        }
+        // the workers hold the only senders left, the loop ends with them
+        drop(sender);
+        for (index, result) in receiver {
+            results.push((index, result));
+            progress(results.len(), inputs.len());
+        }
+    });
+
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, x)| x).collect()
+}
+
+/// Progress that reports nothing
+pub fn quiet(_done: usize, _total: usize) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn run_should_keep_input_order() {
+        let inputs = (0..100u64).collect::<Vec<u64>>();
+        let res = run(
+            &inputs,
+            4,
+            |x| {
+                // later inputs finish first
+                thread::sleep(std::time::Duration::from_micros(100 - x));
+                x * 2
+            },
+            &quiet,
+        );
+        assert_eq!(res, inputs.iter().map(|x| x * 2).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn run_should_report_every_job_in_order() {
+        let seen = RefCell::new(vec![]);
+        let inputs = vec![1, 2, 3, 4, 5];
+        run(&inputs, 0, |x| *x, &|done, total| {
+            seen.borrow_mut().push((done, total))
+        });
+        let seen = seen.into_inner();
+        assert_eq!(seen, vec![(1, 5), (2, 5), (3, 5), (4, 5), (5, 5)]);
+    }
+
+    #[test]
+    fn run_without_inputs_should_return_empty() {
+        let res: Vec<u32> = run(&[], 3, |x: &u32| *x, &quiet);
+        assert!(res.is_empty());
+    }
+}
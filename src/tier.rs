@@ -0,0 +1,393 @@
+use crate::utils::LinkedSet;
+use crate::{MemoryPage, PageId, Pid, Time};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Slower tiers a run may have below its frames, each gets columns of its
+/// own in the results
+pub const MAX_SLOW_TIERS: usize = 3;
+
+/// A tier of memory slower than the frames, such as CXL attached memory or
+/// NVM
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SlowTier {
+    /// Pages the tier holds
+    pub capacity: u32,
+    /// Time a reference to a page in the tier takes, in nanoseconds
+    pub latency: f64,
+}
+
+/// Memory below the frames: pages the policy evicts are demoted to the
+/// first slow tier, the coldest page of a full tier moves down to the next,
+/// and only pages pushed out of the last one leave memory. A page in a slow
+/// tier referenced hot times within window references is promoted back to
+/// the frames
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tiering {
+    /// Fastest first, at most MAX_SLOW_TIERS
+    pub tiers: Vec<SlowTier>,
+    /// Time a reference to a page in the frames takes
+    pub fast: f64,
+    pub hot: u32,
+    pub window: Time,
+    /// Bytes a migration moves per page
+    pub page_size: u64,
+}
+
+impl Default for Tiering {
+    /// One slow tier of 64 pages, three times slower than the frames, with
+    /// pages promoted on their second reference in 100
+    fn default() -> Tiering {
+        Tiering {
+            tiers: vec![SlowTier {
+                capacity: 64,
+                latency: 300.0,
+            }],
+            fast: 100.0,
+            hot: 2,
+            window: 100,
+            page_size: 4096,
+        }
+    }
+}
+
+impl Tiering {
+    pub fn validate(self) -> Result<Tiering, String> {
+        if self.tiers.is_empty() || self.tiers.len() > MAX_SLOW_TIERS {
+            return Err(format!(
+                "tiers needs 1 to {} slow tiers, got {}",
+                MAX_SLOW_TIERS,
+                self.tiers.len()
+            ));
+        }
+        if self.tiers.iter().any(|x| x.capacity == 0) {
+            return Err("every slow tier needs a capacity of at least 1".to_string());
+        }
+        if self.fast < 0.0 || self.tiers.iter().any(|x| x.latency < 0.0) {
+            return Err("tier latencies must not be negative".to_string());
+        }
+        if self.hot == 0 || self.window == 0 {
+            return Err("tiers hot and window must be at least 1".to_string());
+        }
+        Ok(self)
+    }
+}
+
+impl fmt::Display for Tiering {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tiers = self
+            .tiers
+            .iter()
+            .map(|x| format!("{}@{}", x.capacity, x.latency))
+            .collect::<Vec<String>>();
+        write!(
+            f,
+            "tiers(tiers={},fast={},hot={},window={},page_size={})",
+            tiers.join("/"),
+            self.fast,
+            self.hot,
+            self.window,
+            self.page_size
+        )
+    }
+}
+
+/// Comma separated tiers=CAPACITY@LATENCY/.., fast=R, hot=N, window=N and
+/// page_size=N, missing ones keep their defaults
+impl FromStr for Tiering {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Tiering, String> {
+        let mut tiering = Tiering::default();
+        for part in s.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("tiers option '{}' needs a value", part))?;
+            let expected = || format!("tiers {} expects a number, got '{}'", key, value);
+            match key {
+                "tiers" => {
+                    tiering.tiers = value
+                        .split('/')
+                        .map(|tier| {
+                            let (capacity, latency) = tier.split_once('@')?;
+                            Some(SlowTier {
+                                capacity: capacity.parse().ok()?,
+                                latency: latency.parse().ok()?,
+                            })
+                        })
+                        .collect::<Option<Vec<SlowTier>>>()
+                        .ok_or_else(|| {
+                            format!("tiers expects capacity@latency/.., got '{}'", value)
+                        })?
+                }
+                "fast" => tiering.fast = value.parse().map_err(|_| expected())?,
+                "hot" => tiering.hot = value.parse().map_err(|_| expected())?,
+                "window" => tiering.window = value.parse().map_err(|_| expected())?,
+                "page_size" => tiering.page_size = value.parse().map_err(|_| expected())?,
+                _ => return Err(format!("unknown tiers option '{}'", key)),
+            }
+        }
+        tiering.validate()
+    }
+}
+
+/// 'off', 'on' for the default tiers, or their options as FromStr reads them
+pub fn parse_spec(spec: &str) -> Result<Option<Tiering>, String> {
+    match spec.trim() {
+        "off" => Ok(None),
+        "on" => Ok(Some(Tiering::default())),
+        options => options.parse().map(Some),
+    }
+}
+
+/// References and migrations of the slow tiers over a run
+#[derive(Debug, Clone, PartialEq)]
+pub struct TierReport {
+    pub(crate) tiering: Tiering,
+    pub(crate) hits: Vec<u64>,
+    pub(crate) promotions: u64,
+    pub(crate) demotions: u64,
+}
+
+impl TierReport {
+    /// References served by slow tier tier, 0 being the fastest of them
+    pub fn hits(&self, tier: usize) -> Option<u64> {
+        self.hits.get(tier).copied()
+    }
+
+    /// References served by any slow tier
+    pub fn slow_hits(&self) -> u64 {
+        self.hits.iter().sum()
+    }
+
+    /// Pages moved up from a slow tier to the frames
+    pub fn promotions(&self) -> u64 {
+        self.promotions
+    }
+
+    /// Pages moved down one tier, from the frames or a slow tier
+    pub fn demotions(&self) -> u64 {
+        self.demotions
+    }
+
+    /// Bytes promotions and demotions moved between tiers
+    pub fn migration_bytes(&self) -> u64 {
+        (self.promotions + self.demotions) * self.tiering.page_size
+    }
+
+    /// Mean latency of the references that found their page in memory,
+    /// fast_hits of them in the frames
+    pub fn access_time(&self, fast_hits: u64) -> f64 {
+        let hits = fast_hits + self.slow_hits();
+        if hits == 0 {
+            return 0.0;
+        }
+        let slow = self
+            .hits
+            .iter()
+            .zip(self.tiering.tiers.iter())
+            .map(|(hits, tier)| *hits as f64 * tier.latency)
+            .sum::<f64>();
+        (fast_hits as f64 * self.tiering.fast + slow) / hits as f64
+    }
+}
+
+/// The slow tiers of a run and the pages in them
+pub struct Tiers {
+    tiering: Tiering,
+    /// Pages of every slow tier, least recently used first
+    levels: Vec<LinkedSet<PageId>>,
+    /// Tier and state of every page in a slow tier
+    pages: HashMap<PageId, (usize, MemoryPage)>,
+    /// Start of the hotness window of a page and its references since
+    heat: HashMap<PageId, (Time, u32)>,
+    hits: Vec<u64>,
+    promotions: u64,
+    demotions: u64,
+}
+
+impl Tiers {
+    pub fn new(tiering: &Tiering) -> Tiers {
+        Tiers {
+            tiering: tiering.clone(),
+            levels: tiering.tiers.iter().map(|_| LinkedSet::new()).collect(),
+            pages: HashMap::new(),
+            heat: HashMap::new(),
+            hits: vec![0; tiering.tiers.len()],
+            promotions: 0,
+            demotions: 0,
+        }
+    }
+
+    /// Slow tier holding page, 0 being the fastest of them
+    pub fn tier(&self, page: PageId) -> Option<usize> {
+        self.pages.get(&page).map(|x| x.0)
+    }
+
+    pub fn contains(&self, page: PageId) -> bool {
+        self.pages.contains_key(&page)
+    }
+
+    /// Reference page at time now where it is, None when it is in no slow
+    /// tier. Otherwise the latency of its tier, and whether the page turned
+    /// hot and should be promoted
+    pub fn reference(&mut self, page: PageId, write: bool, now: Time) -> Option<(f64, bool)> {
+        let (tier, state) = self.pages.get_mut(&page)?;
+        let used = if write {
+            state.modified_and_referenced()
+        } else {
+            state.referenced()
+        };
+        *state = used.used_at(now);
+        let tier = *tier;
+        self.levels[tier].push_back(page);
+        self.hits[tier] += 1;
+        let heat = self.heat.entry(page).or_insert((now, 0));
+        if now - heat.0 >= self.tiering.window {
+            *heat = (now, 0);
+        }
+        heat.1 += 1;
+        Some((self.tiering.tiers[tier].latency, heat.1 >= self.tiering.hot))
+    }
+
+    /// Take page out of its slow tier to move it up to the frames
+    pub fn promote(&mut self, page: PageId) -> Option<MemoryPage> {
+        let state = self.take(page)?;
+        self.promotions += 1;
+        Some(state)
+    }
+
+    /// Move a page evicted from the frames into the first slow tier, and
+    /// the coldest page of every tier that overflows into the next. Returns
+    /// the page pushed out of the last tier, which leaves memory
+    pub fn demote(&mut self, page: MemoryPage) -> Option<MemoryPage> {
+        let mut moving = page;
+        for tier in 0..self.levels.len() {
+            self.demotions += 1;
+            self.levels[tier].push_back(moving.id());
+            self.pages.insert(moving.id(), (tier, moving));
+            if self.levels[tier].len() as u32 <= self.tiering.tiers[tier].capacity {
+                return None;
+            }
+            let coldest = self.levels[tier].pop_front().unwrap();
+            self.heat.remove(&coldest);
+            moving = self.pages.remove(&coldest).unwrap().1;
+        }
+        Some(moving)
+    }
+
+    /// Take every page of process out of the slow tiers, it was swapped out
+    pub fn remove_process(&mut self, process: Pid) -> Vec<MemoryPage> {
+        let mut pages = self
+            .pages
+            .keys()
+            .filter(|x| x.process == process)
+            .copied()
+            .collect::<Vec<PageId>>();
+        pages.sort_unstable();
+        pages.into_iter().filter_map(|x| self.take(x)).collect()
+    }
+
+    /// Track the page in a slow tier under from as to instead
+    pub fn rename(&mut self, from: PageId, to: PageId) {
+        let (tier, state) = match self.pages.remove(&from) {
+            Some(x) => x,
+            None => return,
+        };
+        self.levels[tier].remove(&from);
+        self.levels[tier].push_back(to);
+        let state = MemoryPage {
+            process: to.process,
+            number: to.number,
+            ..state
+        };
+        self.pages.insert(to, (tier, state));
+        if let Some(heat) = self.heat.remove(&from) {
+            self.heat.insert(to, heat);
+        }
+    }
+
+    fn take(&mut self, page: PageId) -> Option<MemoryPage> {
+        let (tier, state) = self.pages.remove(&page)?;
+        self.levels[tier].remove(&page);
+        self.heat.remove(&page);
+        Some(state)
+    }
+
+    pub fn report(&self) -> TierReport {
+        TierReport {
+            tiering: self.tiering.clone(),
+            hits: self.hits.clone(),
+            promotions: self.promotions,
+            demotions: self.demotions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiers(spec: &str) -> Tiers {
+        Tiers::new(&spec.parse().unwrap())
+    }
+
+    fn page(number: u32) -> MemoryPage {
+        MemoryPage::new(number)
+    }
+
+    #[test]
+    fn full_tiers_should_demote_their_coldest_page() {
+        let mut tiers = tiers("tiers=2@300/1@1000");
+        assert_eq!(tiers.demote(page(0)), None);
+        assert_eq!(tiers.demote(page(1)), None);
+        tiers.reference(0.into(), false, 5);
+        // 1 is now the coldest of the first tier
+        assert_eq!(tiers.demote(page(2)), None);
+        assert_eq!(tiers.tier(1.into()), Some(1));
+        assert_eq!(tiers.demote(page(3)).map(|x| x.id()), Some(1.into()));
+        assert_eq!(tiers.tier(0.into()), Some(1));
+        let report = tiers.report();
+        // 2 moved down to the second tier on the way
+        assert_eq!(report.demotions(), 6);
+        assert_eq!(report.hits(0), Some(1));
+        assert_eq!(report.hits(1), Some(0));
+        assert_eq!(report.hits(2), None);
+    }
+
+    #[test]
+    fn pages_should_turn_hot_within_the_window() {
+        let mut tiers = tiers("tiers=4@250,hot=2,window=10");
+        tiers.demote(page(0));
+        assert_eq!(tiers.reference(0.into(), false, 1), Some((250.0, false)));
+        // the window ran out, counting starts over
+        assert_eq!(tiers.reference(0.into(), true, 11), Some((250.0, false)));
+        assert_eq!(tiers.reference(0.into(), false, 12), Some((250.0, true)));
+        let promoted = tiers.promote(0.into()).unwrap();
+        assert!(promoted.is_modified());
+        assert_eq!(tiers.reference(0.into(), false, 13), None);
+        let report = tiers.report();
+        assert_eq!(report.migration_bytes(), 2 * 4096);
+        // 3 references in the slow tier, one in the frames
+        assert_eq!(report.access_time(1), (100.0 + 3.0 * 250.0) / 4.0);
+    }
+
+    #[test]
+    fn parse_should_fill_defaults_and_reject_bad_settings() {
+        assert_eq!("".parse::<Tiering>(), Ok(Tiering::default()));
+        let res = "tiers=16@200/64@900.5, hot=3".parse::<Tiering>().unwrap();
+        assert_eq!(
+            res.to_string(),
+            "tiers(tiers=16@200/64@900.5,fast=100,hot=3,window=100,page_size=4096)"
+        );
+        assert!("tiers=0@100".parse::<Tiering>().is_err());
+        assert!("tiers=1@1/1@1/1@1/1@1".parse::<Tiering>().is_err());
+        assert!("tiers=16".parse::<Tiering>().is_err());
+        assert!("hot=0".parse::<Tiering>().is_err());
+        assert!("fast=-1".parse::<Tiering>().is_err());
+        assert!("size=3".parse::<Tiering>().is_err());
+        assert_eq!(parse_spec("off"), Ok(None));
+        assert_eq!(parse_spec("on"), Ok(Some(Tiering::default())));
+    }
+}
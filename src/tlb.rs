@@ -0,0 +1,454 @@
+use crate::error::Error;
+use crate::policy::{self, Policy};
+use crate::{PageId, Pid};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+/// Address translation in front of the frames: a set associative TLB and
+/// the multi-level page table walked when it misses
+#[derive(Debug, Clone, PartialEq)]
+pub struct Translation {
+    /// Translations the TLB holds
+    pub entries: u32,
+    /// Entries per set, entries for a fully associative TLB
+    pub ways: u32,
+    /// Replacement policy of every set, one of policy::POLICIES
+    pub policy: String,
+    /// Bits of the page number each level of the page table translates,
+    /// root first. A walk reads one entry per level
+    pub levels: Vec<u32>,
+    /// Bytes per page, the TLB reaches entries times this many bytes
+    pub page_size: u64,
+    /// Cost of a TLB lookup, in the unit of the cost model
+    pub lookup: f64,
+}
+
+impl Default for Translation {
+    /// 64 entries in sets of 4 over a four level table of 4 KiB pages
+    fn default() -> Translation {
+        Translation {
+            entries: 64,
+            ways: 4,
+            policy: "lru".to_string(),
+            levels: vec![8, 8, 8, 8],
+            page_size: 4096,
+            lookup: 1.0,
+        }
+    }
+}
+
+impl Translation {
+    pub fn validate(self) -> Result<Translation, String> {
+        if self.entries == 0 || self.ways == 0 {
+            return Err("tlb entries and ways must be at least 1".to_string());
+        }
+        if !self.entries.is_multiple_of(self.ways) {
+            return Err(format!(
+                "tlb entries must be a multiple of ways, got entries={} ways={}",
+                self.entries, self.ways
+            ));
+        }
+        if policy::by_name(&self.policy, 0).is_none() {
+            return Err(format!("unknown tlb policy '{}'", self.policy));
+        }
+        if self.levels.is_empty() || self.levels.contains(&0) {
+            return Err("every page table level needs at least 1 bit".to_string());
+        }
+        if self.bits() > 32 {
+            return Err(format!(
+                "page table levels translate {} bits, page numbers have 32",
+                self.bits()
+            ));
+        }
+        if self.page_size == 0 {
+            return Err("page size must be at least 1".to_string());
+        }
+        if self.lookup < 0.0 {
+            return Err("tlb lookup cost must not be negative".to_string());
+        }
+        Ok(self)
+    }
+
+    /// Bits of the page number the page table translates
+    pub fn bits(&self) -> u32 {
+        self.levels.iter().sum()
+    }
+
+    /// Bytes the TLB can translate without a walk
+    pub fn reach(&self) -> u64 {
+        self.entries as u64 * self.page_size
+    }
+}
+
+impl fmt::Display for Translation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let levels = self
+            .levels
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>();
+        write!(
+            f,
+            "tlb(entries={},ways={},policy={},levels={},page_size={},lookup={})",
+            self.entries,
+            self.ways,
+            self.policy,
+            levels.join("/"),
+            self.page_size,
+            self.lookup
+        )
+    }
+}
+
+/// Comma separated entries=N, ways=N, policy=NAME, levels=B/B/..,
+/// page_size=N and lookup=R, missing ones keep their defaults
+impl FromStr for Translation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Translation, String> {
+        let mut translation = Translation::default();
+        for part in s.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("tlb option '{}' needs a value", part))?;
+            let expected = || format!("tlb {} expects a number, got '{}'", key, value);
+            match key {
+                "entries" => translation.entries = value.parse().map_err(|_| expected())?,
+                "ways" => translation.ways = value.parse().map_err(|_| expected())?,
+                "policy" => translation.policy = value.to_lowercase(),
+                "levels" => {
+                    translation.levels = value
+                        .split('/')
+                        .map(|x| x.parse::<u32>())
+                        .collect::<Result<Vec<u32>, _>>()
+                        .map_err(|_| {
+                            format!("tlb levels expects bits like 9/9/9, got '{}'", value)
+                        })?
+                }
+                "page_size" => translation.page_size = value.parse().map_err(|_| expected())?,
+                "lookup" => translation.lookup = value.parse().map_err(|_| expected())?,
+                _ => return Err(format!("unknown tlb option '{}'", key)),
+            }
+        }
+        translation.validate()
+    }
+}
+
+/// 'off', 'on' for the default translation, or its options as FromStr reads
+/// them
+pub fn parse_spec(spec: &str) -> Result<Option<Translation>, String> {
+    match spec.trim() {
+        "off" => Ok(None),
+        "on" => Ok(Some(Translation::default())),
+        options => options.parse().map(Some),
+    }
+}
+
+/// Translation counters of a run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TranslationReport {
+    pub(crate) hits: u64,
+    pub(crate) misses: u64,
+    pub(crate) walk_references: u64,
+    pub(crate) table_pages: u64,
+    pub(crate) reach: u64,
+    pub(crate) lookup: f64,
+    /// Pages the entries of the TLB covered, and its entries, summed over
+    /// every lookup
+    pub(crate) covered: u64,
+    pub(crate) filled: u64,
+}
+
+impl TranslationReport {
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    pub fn hit_ratio(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+
+    /// Page table entries read by walks, one per level on every miss
+    pub fn walk_references(&self) -> u64 {
+        self.walk_references
+    }
+
+    /// Pages of page table the walks needed, over every process
+    pub fn table_pages(&self) -> u64 {
+        self.table_pages
+    }
+
+    /// Bytes the TLB translates without a walk
+    pub fn reach(&self) -> u64 {
+        self.reach
+    }
+
+    /// Cost of a TLB lookup
+    pub fn lookup(&self) -> f64 {
+        self.lookup
+    }
+
+    /// Pages an entry translated on average, over base pages only: how much
+    /// further huge pages made the TLB reach
+    pub fn reach_gain(&self) -> f64 {
+        if self.filled == 0 {
+            return 1.0;
+        }
+        self.covered as f64 / self.filled as f64
+    }
+}
+
+/// One set of the TLB, with a policy of its own
+struct Set {
+    policy: Box<dyn Policy>,
+    pages: HashSet<PageId>,
+}
+
+/// Translates every reference before it reaches the frames
+///
+/// Entries are tagged with the process, so switching processes flushes
+/// nothing. A page that leaves memory must be invalidated
+pub struct Mmu {
+    translation: Translation,
+    sets: Vec<Set>,
+    /// Every page of page table by process, level and the page number bits
+    /// above that level
+    tables: HashSet<(Pid, usize, u64)>,
+    /// Pages translated by every entry of a huge page
+    spans: HashMap<PageId, u32>,
+    /// Entries of the TLB and the pages they translate
+    entries: u64,
+    pages: u64,
+    hits: u64,
+    misses: u64,
+    walk_references: u64,
+    covered: u64,
+    filled: u64,
+}
+
+impl Mmu {
+    /// seed drives set policies that pick victims at random
+    pub fn new(translation: &Translation, seed: u64) -> Mmu {
+        let sets = (translation.entries / translation.ways).max(1);
+        Mmu {
+            translation: translation.clone(),
+            sets: (0..sets)
+                .map(|_| Set {
+                    policy: policy::by_name(&translation.policy, seed).unwrap(),
+                    pages: HashSet::new(),
+                })
+                .collect(),
+            tables: HashSet::new(),
+            spans: HashMap::new(),
+            entries: 0,
+            pages: 0,
+            hits: 0,
+            misses: 0,
+            walk_references: 0,
+            covered: 0,
+            filled: 0,
+        }
+    }
+
+    /// Look page up, walking the page table and caching the translation when
+    /// the TLB misses. True on a TLB hit
+    pub fn translate(&mut self, page: PageId) -> Result<bool, Error> {
+        self.lookup(page, 1)
+    }
+
+    /// Look up the huge page of span pages starting at head, one entry
+    /// translates all of them. The walk stops at the first level whose
+    /// entry covers the whole huge page. The entries of its base pages must
+    /// have been invalidated, and its own is once it is split
+    pub fn translate_huge(&mut self, head: PageId, span: u32) -> Result<bool, Error> {
+        self.lookup(head, span)
+    }
+
+    fn lookup(&mut self, page: PageId, span: u32) -> Result<bool, Error> {
+        let hit = self.walk(page, span)?;
+        self.covered += self.pages;
+        self.filled += self.entries;
+        Ok(hit)
+    }
+
+    fn walk(&mut self, page: PageId, span: u32) -> Result<bool, Error> {
+        let bits = self.translation.bits();
+        if (page.number as u64) >> bits != 0 {
+            return Err(Error::InconsistentState(format!(
+                "page {} does not fit the {} bits the page table translates",
+                page, bits
+            )));
+        }
+        let ways = self.translation.ways as usize;
+        let index = self.set(page);
+        let set = &mut self.sets[index];
+        if set.pages.contains(&page) {
+            set.policy.hit(page);
+            self.hits += 1;
+            return Ok(true);
+        }
+
+        self.misses += 1;
+        let mut below = bits;
+        for (level, width) in self.translation.levels.iter().enumerate() {
+            self.tables
+                .insert((page.process, level, page.number as u64 >> below));
+            self.walk_references += 1;
+            below = below.saturating_sub(*width);
+            if below <= span.trailing_zeros() {
+                break;
+            }
+        }
+
+        if set.pages.len() >= ways {
+            let victim = set.policy.evict().ok_or(Error::NothingToEvict)?;
+            set.pages.remove(&victim);
+            self.entries -= 1;
+            self.pages -= self.spans.remove(&victim).unwrap_or(1) as u64;
+        }
+        set.pages.insert(page);
+        set.policy.insert(page);
+        self.entries += 1;
+        self.pages += span as u64;
+        if span > 1 {
+            self.spans.insert(page, span);
+        }
+        Ok(false)
+    }
+
+    /// Drop the translation of page, it left memory
+    pub fn invalidate(&mut self, page: PageId) {
+        let index = self.set(page);
+        let set = &mut self.sets[index];
+        if set.pages.remove(&page) {
+            set.policy.remove(page);
+            self.entries -= 1;
+            self.pages -= self.spans.remove(&page).unwrap_or(1) as u64;
+        }
+    }
+
+    /// Low bits of the page number pick the set
+    fn set(&self, page: PageId) -> usize {
+        page.number as usize % self.sets.len()
+    }
+
+    pub fn report(&self) -> TranslationReport {
+        TranslationReport {
+            hits: self.hits,
+            misses: self.misses,
+            walk_references: self.walk_references,
+            table_pages: self.tables.len() as u64,
+            reach: self.translation.reach(),
+            lookup: self.translation.lookup,
+            covered: self.covered,
+            filled: self.filled,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mmu(spec: &str) -> Mmu {
+        Mmu::new(&spec.parse::<Translation>().unwrap(), 0)
+    }
+
+    #[test]
+    fn repeated_page_should_hit_after_one_walk() {
+        let mut mmu = mmu("levels=4/4/4");
+        assert_eq!(mmu.translate(5.into()), Ok(false));
+        assert_eq!(mmu.translate(5.into()), Ok(true));
+        assert_eq!(mmu.translate(PageId::new(1, 5)), Ok(false));
+        let report = mmu.report();
+        assert_eq!((report.hits(), report.misses()), (1, 2));
+        assert_eq!(report.walk_references(), 6);
+        // a root, a middle and a leaf table for each process
+        assert_eq!(report.table_pages(), 6);
+    }
+
+    #[test]
+    fn walks_should_share_upper_levels() {
+        let mut mmu = mmu("levels=4/4/4");
+        for number in [0x001, 0x002, 0x010, 0x100].iter() {
+            mmu.translate((*number).into()).unwrap();
+        }
+        // one root, middle tables 0x0 and 0x1, leaves 0x00, 0x01 and 0x10
+        assert_eq!(mmu.report().table_pages(), 6);
+        assert!(mmu.translate(0x1000.into()).is_err());
+    }
+
+    #[test]
+    fn full_set_should_evict_by_its_policy() {
+        // two sets of two ways, even pages share set 0
+        let mut mmu = mmu("entries=4,ways=2,policy=lru");
+        for number in [0, 2, 0, 4, 0, 2].iter() {
+            mmu.translate((*number).into()).unwrap();
+        }
+        let report = mmu.report();
+        // 2 was the least recently used when 4 came in
+        assert_eq!((report.hits(), report.misses()), (2, 4));
+        assert_eq!(report.reach(), 4 * 4096);
+    }
+
+    #[test]
+    fn huge_entries_should_walk_less_and_reach_further() {
+        let mut mmu = mmu("entries=2,ways=2,levels=4/4/4");
+        mmu.translate(1.into()).unwrap();
+        // the middle level maps the 16 pages of 0x10..0x20 at once
+        assert_eq!(mmu.translate_huge(0x10.into(), 16), Ok(false));
+        assert_eq!(mmu.report().walk_references(), 3 + 2);
+        assert_eq!(mmu.translate_huge(0x10.into(), 16), Ok(true));
+        // 1 page then 17 over 1 then 2 entries
+        assert_eq!(mmu.report().reach_gain(), (1.0 + 17.0 + 17.0) / 5.0);
+        // split again, its pages need a leaf table of their own
+        mmu.invalidate(0x10.into());
+        assert_eq!(mmu.translate(0x11.into()), Ok(false));
+        assert_eq!(mmu.report().walk_references(), 3 + 2 + 3);
+        assert_eq!(mmu.report().table_pages(), 4);
+    }
+
+    #[test]
+    fn invalidated_page_should_walk_again() {
+        let mut mmu = mmu("");
+        mmu.translate(3.into()).unwrap();
+        mmu.invalidate(3.into());
+        assert_eq!(mmu.translate(3.into()), Ok(false));
+        assert_eq!(mmu.report().walk_references(), 8);
+    }
+
+    #[test]
+    fn parse_should_fill_defaults_and_reject_bad_settings() {
+        assert_eq!("".parse::<Translation>(), Ok(Translation::default()));
+        let res = "entries=8, ways=8, policy=FIFO, levels=10/10".parse::<Translation>();
+        let res = res.unwrap();
+        assert_eq!(
+            res.to_string(),
+            "tlb(entries=8,ways=8,policy=fifo,levels=10/10,page_size=4096,lookup=1)"
+        );
+        assert_eq!(
+            res.to_string()
+                .trim_start_matches("tlb(")
+                .trim_end_matches(')')
+                .parse(),
+            Ok(res)
+        );
+        assert!("entries=6,ways=4".parse::<Translation>().is_err());
+        assert!("ways=0".parse::<Translation>().is_err());
+        assert!("policy=opt".parse::<Translation>().is_err());
+        assert!("levels=20/20".parse::<Translation>().is_err());
+        assert!("levels=9/x".parse::<Translation>().is_err());
+        assert!("size=3".parse::<Translation>().is_err());
+        assert_eq!(parse_spec("off"), Ok(None));
+        assert_eq!(parse_spec("on"), Ok(Some(Translation::default())));
+        assert_eq!(parse_spec("ways=64").unwrap().unwrap().ways, 64);
+    }
+}
@@ -1,9 +1,12 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
 pub fn add_if_not_found(mut acc: Vec<u32>, curr: u32) -> Vec<u32> {
     if acc.contains(&curr) {
         return acc;
     }
     acc.push(curr);
-    return acc;
+    acc
 }
 
 pub fn remove_first<T>(mut acc: Vec<T>) -> Vec<T> {
@@ -20,3 +23,157 @@ pub fn remove<T>(mut acc: Vec<T>, index: usize) -> (T, Vec<T>) {
     let t = acc.remove(index);
     (t, acc)
 }
+
+/// Ordered set with O(1) push to the back, pop from the front and removal
+/// of any element: a doubly linked list threaded through a Vec with an index
+/// from element to node
+#[derive(Debug, Clone)]
+pub struct LinkedSet<K> {
+    nodes: Vec<Node<K>>,
+    index: HashMap<K, usize>,
+    unused: Vec<usize>,
+    head: Option<usize>,
+    tail: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Node<K> {
+    key: K,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+impl<K: Copy + Eq + Hash> LinkedSet<K> {
+    pub fn new() -> LinkedSet<K> {
+        LinkedSet {
+            nodes: vec![],
+            index: HashMap::new(),
+            unused: vec![],
+            head: None,
+            tail: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.index.contains_key(key)
+    }
+
+    pub fn front(&self) -> Option<K> {
+        self.head.map(|x| self.nodes[x].key)
+    }
+
+    /// Add key at the back, moving it there if it is already in the set
+    pub fn push_back(&mut self, key: K) {
+        self.remove(&key);
+        let node = Node {
+            key,
+            prev: self.tail,
+            next: None,
+        };
+        let at = match self.unused.pop() {
+            Some(at) => {
+                self.nodes[at] = node;
+                at
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        match self.tail {
+            Some(tail) => self.nodes[tail].next = Some(at),
+            None => self.head = Some(at),
+        }
+        self.tail = Some(at);
+        self.index.insert(key, at);
+    }
+
+    pub fn pop_front(&mut self) -> Option<K> {
+        let key = self.front()?;
+        self.remove(&key);
+        Some(key)
+    }
+
+    /// false when key was not in the set
+    pub fn remove(&mut self, key: &K) -> bool {
+        let at = match self.index.remove(key) {
+            Some(at) => at,
+            None => return false,
+        };
+        let (prev, next) = (self.nodes[at].prev, self.nodes[at].next);
+        match prev {
+            Some(prev) => self.nodes[prev].next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => self.nodes[next].prev = prev,
+            None => self.tail = prev,
+        }
+        self.unused.push(at);
+        true
+    }
+
+    /// Elements from front to back
+    pub fn iter(&self) -> impl Iterator<Item = K> + '_ {
+        std::iter::successors(self.head, move |x| self.nodes[*x].next)
+            .map(move |x| self.nodes[x].key)
+    }
+}
+
+impl<K: Copy + Eq + Hash> Default for LinkedSet<K> {
+    fn default() -> Self {
+        LinkedSet::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linked_set_should_keep_insertion_order() {
+        let mut set = LinkedSet::new();
+        set.push_back(3);
+        set.push_back(1);
+        set.push_back(2);
+        assert_eq!(set.iter().collect::<Vec<u32>>(), vec![3, 1, 2]);
+        assert_eq!(set.pop_front(), Some(3));
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn linked_set_push_back_should_move_existing() {
+        let mut set = LinkedSet::new();
+        for x in [0, 1, 2, 0].iter() {
+            set.push_back(*x);
+        }
+        assert_eq!(set.iter().collect::<Vec<u32>>(), vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn linked_set_remove_should_relink_neighbours() {
+        let mut set = LinkedSet::new();
+        for x in 0..5 {
+            set.push_back(x);
+        }
+        assert!(set.remove(&2));
+        assert!(set.remove(&0));
+        assert!(set.remove(&4));
+        assert!(!set.remove(&9));
+        set.push_back(7);
+        assert_eq!(set.iter().collect::<Vec<u32>>(), vec![1, 3, 7]);
+        assert_eq!(set.front(), Some(1));
+        assert!(set.contains(&7) && !set.contains(&2));
+        while set.pop_front().is_some() {}
+        assert!(set.is_empty());
+        assert_eq!(set.front(), None);
+    }
+}
@@ -0,0 +1,616 @@
+use crate::error::Error;
+use crate::{PageId, Pid, Reference};
+use flate2::bufread::MultiGzDecoder;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+/// A synthetic reference string, generated the same way for the same seed
+#[derive(Clone, Debug, PartialEq)]
+pub enum Workload {
+    /// length references drawn uniformly from pages 0..pages
+    Uniform { length: usize, pages: u32 },
+    /// Scan pages 0..pages in order, over and over, until length references
+    Loop { length: usize, pages: u32 },
+    /// length references where page k is drawn with weight 1 / (k + 1)^exponent
+    Zipf {
+        length: usize,
+        pages: u32,
+        exponent: f64,
+    },
+    /// Several workloads taking turns one reference at a time, the n-th
+    /// workload is process n with its pages numbered from 0
+    Interleave(Vec<Workload>),
+}
+
+/// A stream of references that may fail part way, e.g. on a bad line of a trace
+pub type References = Box<dyn Iterator<Item = Result<Reference, Error>> + Send>;
+
+impl Workload {
+    pub fn generate(&self, seed: u64) -> Vec<PageId> {
+        self.references(seed).collect()
+    }
+
+    /// The same references as generate, produced one at a time
+    pub fn references(&self, seed: u64) -> Box<dyn Iterator<Item = PageId> + Send> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        match *self {
+            Workload::Uniform { length, pages } => {
+                Box::new((0..length).map(move |_| PageId::from(rng.gen_range(0, pages))))
+            }
+            Workload::Loop { length, pages } => {
+                Box::new((0..length).map(move |x| PageId::from((x % pages as usize) as u32)))
+            }
+            Workload::Zipf {
+                length,
+                pages,
+                exponent,
+            } => {
+                let cumulative = (0..pages)
+                    .scan(0.0, |total, k| {
+                        *total += 1.0 / ((k + 1) as f64).powf(exponent);
+                        Some(*total)
+                    })
+                    .collect::<Vec<f64>>();
+                let total = *cumulative.last().unwrap_or(&0.0);
+                Box::new((0..length).map(move |_| {
+                    let pick = rng.gen::<f64>() * total;
+                    let page = cumulative.partition_point(|x| *x <= pick);
+                    PageId::from(page.min(pages as usize - 1) as u32)
+                }))
+            }
+            Workload::Interleave(ref workloads) => {
+                // Each process gets its own seed so identical workloads differ
+                let mut processes = workloads
+                    .iter()
+                    .enumerate()
+                    .map(|(pid, x)| (pid as Pid, x.references(seed.wrapping_add(pid as u64))))
+                    .collect::<Vec<_>>();
+                let mut turn = 0;
+                Box::new(std::iter::from_fn(move || {
+                    while !processes.is_empty() {
+                        turn %= processes.len();
+                        let (pid, references) = &mut processes[turn];
+                        let pid = *pid;
+                        match references.next() {
+                            Some(page) => {
+                                turn += 1;
+                                return Some(PageId::new(pid, page.number));
+                            }
+                            None => {
+                                drop(processes.remove(turn));
+                            }
+                        }
+                    }
+                    None
+                }))
+            }
+        }
+    }
+}
+
+/// Identity of the workload, recorded alongside every result
+impl fmt::Display for Workload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Workload::Uniform { length, pages } => {
+                write!(f, "uniform(length={},pages={})", length, pages)
+            }
+            Workload::Loop { length, pages } => {
+                write!(f, "loop(length={},pages={})", length, pages)
+            }
+            Workload::Zipf {
+                length,
+                pages,
+                exponent,
+            } => write!(
+                f,
+                "zipf(length={},pages={},exponent={})",
+                length, pages, exponent
+            ),
+            Workload::Interleave(workloads) => {
+                let names = workloads
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<String>>();
+                write!(f, "interleave({})", names.join(","))
+            }
+        }
+    }
+}
+
+/// Where a reference string comes from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Generate(Workload),
+    Trace(PathBuf),
+}
+
+impl Source {
+    /// The whole reference string, seed is ignored for recorded traces
+    pub fn load(&self, seed: u64) -> Result<Vec<Reference>, Error> {
+        self.references(seed)?.collect()
+    }
+
+    /// The reference string one reference at a time
+    pub fn references(&self, seed: u64) -> Result<References, Error> {
+        match self {
+            Source::Generate(workload) => Ok(Box::new(
+                workload.references(seed).map(|x| Ok(Reference::from(x))),
+            )),
+            Source::Trace(path) => Ok(Box::new(open_trace(path)?)),
+        }
+    }
+
+    /// Distinct pages each process references, reads the whole stream once
+    pub fn process_sizes(&self, seed: u64) -> Result<BTreeMap<Pid, u64>, Error> {
+        let mut seen = HashSet::new();
+        let mut sizes = BTreeMap::new();
+        for reference in self.references(seed)? {
+            let page = match reference? {
+                Reference::Unpin(_) | Reference::Share { .. } => continue,
+                reference => reference.page(),
+            };
+            let size = sizes.entry(page.process).or_insert(0);
+            if seen.insert(page) {
+                *size += 1;
+            }
+        }
+        Ok(sizes)
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Generate(workload) => write!(f, "{}", workload),
+            Source::Trace(path) => write!(f, "trace({})", path.display()),
+        }
+    }
+}
+
+/// Reads a recorded trace: page numbers separated by whitespace or commas
+/// A page of another process than 0 is written 'process:number'
+/// Anything after a '#' on a line is a comment
+/// A line starting with 'write', 'pin' or 'unpin' writes, pins or unpins the
+/// pages on it, e.g. 'pin 3 1:4'. 'share 0:5 1:5 2:5' maps 1:5 and 2:5 onto
+/// the frame of 0:5, see Reference
+///
+/// The trace is read a token at a time, so a line of any length streams
+/// through in memory bounded by MAX_TOKEN
+pub struct TraceReader<R> {
+    reader: R,
+    /// The token being read
+    token: Vec<u8>,
+    line_number: usize,
+    /// Inside a comment, until the end of the line
+    comment: bool,
+    /// The directive of the current line, once its first token was read
+    directive: Option<Option<&'static str>>,
+    /// Pages read on the current line
+    pages: usize,
+    /// The page a share line maps the others onto
+    with: Option<PageId>,
+}
+
+/// Longest token a trace may hold, longer ones are errors rather than read
+/// into memory whole
+pub const MAX_TOKEN: usize = 64;
+
+enum Token {
+    Word,
+    EndOfLine,
+    EndOfFile,
+}
+
+impl<R: BufRead> TraceReader<R> {
+    pub fn new(reader: R) -> TraceReader<R> {
+        TraceReader {
+            reader,
+            token: vec![],
+            line_number: 1,
+            comment: false,
+            directive: None,
+            pages: 0,
+            with: None,
+        }
+    }
+
+    fn error(&self, err: String) -> Error {
+        Error::Trace(format!("line {}: {}", self.line_number, err))
+    }
+
+    /// Read up to the end of the next token, leaving it in self.token
+    fn next_token(&mut self) -> Result<Token, Error> {
+        self.token.clear();
+        loop {
+            let buffer = match self.reader.fill_buf() {
+                Ok(buffer) => buffer,
+                Err(err) => return Err(self.error(err.to_string())),
+            };
+            if buffer.is_empty() {
+                return Ok(match self.token.is_empty() {
+                    true => Token::EndOfFile,
+                    false => Token::Word,
+                });
+            }
+            let mut used = 0;
+            let mut found = None;
+            for byte in buffer.iter() {
+                match *byte {
+                    // the end of the line is read by the next call
+                    b'\n' if !self.token.is_empty() => {
+                        found = Some(Token::Word);
+                        break;
+                    }
+                    b'\n' => {
+                        used += 1;
+                        self.comment = false;
+                        found = Some(Token::EndOfLine);
+                        break;
+                    }
+                    _ if self.comment => used += 1,
+                    x if x == b'#' || x == b',' || x.is_ascii_whitespace() => {
+                        used += 1;
+                        self.comment = x == b'#';
+                        if !self.token.is_empty() {
+                            found = Some(Token::Word);
+                            break;
+                        }
+                    }
+                    x if self.token.len() < MAX_TOKEN => {
+                        used += 1;
+                        self.token.push(x);
+                    }
+                    _ => {
+                        return Err(Error::Trace(format!(
+                            "line {}: a token is longer than {} bytes",
+                            self.line_number, MAX_TOKEN
+                        )))
+                    }
+                }
+            }
+            self.reader.consume(used);
+            if let Some(token) = found {
+                return Ok(token);
+            }
+        }
+    }
+
+    /// Check the line that just ended had the pages its directive needs,
+    /// and start the next one
+    fn end_line(&mut self) -> Result<(), Error> {
+        let res = match self.directive.flatten() {
+            Some("share") if self.pages < 2 => {
+                Err("share needs a page and the pages sharing it".to_string())
+            }
+            Some(directive) if self.pages == 0 => {
+                Err(format!("{} needs at least one page", directive))
+            }
+            _ => Ok(()),
+        };
+        let res = res.map_err(|err| self.error(err));
+        self.line_number += 1;
+        self.directive = None;
+        self.pages = 0;
+        self.with = None;
+        res
+    }
+
+    /// The reference the token just read makes, if it makes one
+    fn reference(&mut self) -> Result<Option<Reference>, Error> {
+        let token = String::from_utf8_lossy(&self.token);
+        let directive = match self.directive {
+            Some(directive) => directive,
+            None => {
+                let directive = DIRECTIVES.iter().find(|x| **x == token).copied();
+                self.directive = Some(directive);
+                if directive.is_some() {
+                    return Ok(None);
+                }
+                directive
+            }
+        };
+        let page = token.parse::<PageId>().map_err(|err| self.error(err))?;
+        self.pages += 1;
+        Ok(match directive {
+            None => Some(Reference::Access(page)),
+            Some("share") => match self.with {
+                Some(with) => Some(Reference::Share { page, with }),
+                None => {
+                    self.with = Some(page);
+                    None
+                }
+            },
+            Some("write") => Some(Reference::Write(page)),
+            Some("pin") => Some(Reference::Pin(page)),
+            Some(_) => Some(Reference::Unpin(page)),
+        })
+    }
+}
+
+impl<R: BufRead> Iterator for TraceReader<R> {
+    type Item = Result<Reference, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let res = match self.next_token() {
+                Ok(Token::Word) => self.reference(),
+                Ok(Token::EndOfLine) => self.end_line().map(|_| None),
+                Ok(Token::EndOfFile) if self.directive.is_some() => self.end_line().map(|_| None),
+                Ok(Token::EndOfFile) => return None,
+                Err(err) => Err(err),
+            };
+            match res {
+                Ok(Some(reference)) => return Some(Ok(reference)),
+                Ok(None) => {}
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+/// Words starting a line that does something else than read its pages
+const DIRECTIVES: [&str; 4] = ["write", "pin", "unpin", "share"];
+
+pub fn parse_trace(text: &str) -> Result<Vec<Reference>, Error> {
+    TraceReader::new(text.as_bytes()).collect()
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Stream a trace file, gzip and zstd compressed files are recognised by
+/// their first bytes and decompressed on the fly
+pub fn open_trace(path: &Path) -> Result<TraceReader<Box<dyn BufRead + Send>>, Error> {
+    let error =
+        |err: std::io::Error| Error::Trace(format!("could not read {}: {}", path.display(), err));
+    let mut file = BufReader::new(File::open(path).map_err(error)?);
+    let start = file.fill_buf().map_err(error)?;
+    let reader: Box<dyn BufRead + Send> = if start.starts_with(&GZIP_MAGIC) {
+        Box::new(BufReader::new(MultiGzDecoder::new(file)))
+    } else if start.starts_with(&ZSTD_MAGIC) {
+        Box::new(BufReader::new(
+            zstd::stream::read::Decoder::with_buffer(file).map_err(error)?,
+        ))
+    } else {
+        Box::new(file)
+    };
+    Ok(TraceReader::new(reader))
+}
+
+pub fn read_trace(path: &Path) -> Result<Vec<Reference>, Error> {
+    open_trace(path)?.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{accesses, pages};
+
+    #[test]
+    fn same_seed_should_generate_same_trace() {
+        let workload = Workload::Uniform {
+            length: 50,
+            pages: 10,
+        };
+        assert_eq!(workload.generate(7), workload.generate(7));
+        assert_ne!(workload.generate(7), workload.generate(8));
+    }
+
+    #[test]
+    fn uniform_should_stay_in_range() {
+        let workload = Workload::Uniform {
+            length: 200,
+            pages: 4,
+        };
+        let trace = workload.generate(1);
+        assert_eq!(trace.len(), 200);
+        assert!(trace.iter().all(|x| x.number < 4));
+        assert_eq!(workload.to_string(), "uniform(length=200,pages=4)");
+    }
+
+    #[test]
+    fn loop_should_scan_in_order() {
+        let workload = Workload::Loop {
+            length: 7,
+            pages: 3,
+        };
+        assert_eq!(workload.generate(0), pages(&[0, 1, 2, 0, 1, 2, 0]));
+    }
+
+    #[test]
+    fn zipf_should_favor_low_pages() {
+        let workload = Workload::Zipf {
+            length: 2000,
+            pages: 50,
+            exponent: 1.2,
+        };
+        let trace = workload.generate(3);
+        let zeros = trace.iter().filter(|x| x.number == 0).count();
+        let tail = trace.iter().filter(|x| x.number == 49).count();
+        assert!(trace.iter().all(|x| x.number < 50));
+        assert!(zeros > tail);
+    }
+
+    #[test]
+    fn parse_trace_should_skip_comments_and_separators() {
+        let res = parse_trace("# recorded\n1 2, 3\n\n4 # four\n");
+        assert_eq!(res, Ok(accesses(&[1, 2, 3, 4])));
+    }
+
+    #[test]
+    fn references_should_match_generate() {
+        let workload = Workload::Zipf {
+            length: 100,
+            pages: 10,
+            exponent: 1.0,
+        };
+        assert_eq!(
+            workload.references(4).collect::<Vec<PageId>>(),
+            workload.generate(4)
+        );
+    }
+
+    #[test]
+    fn open_trace_should_read_plain_gzip_and_zstd() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let text = "# recorded\n1 2 3\n4\n";
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let plain = dir.join(format!("page_replacement_{}.trace", id));
+        let gzip = dir.join(format!("page_replacement_{}.trace.gz", id));
+        let zstd = dir.join(format!("page_replacement_{}.trace.zst", id));
+
+        std::fs::write(&plain, text).unwrap();
+        let mut encoder = GzEncoder::new(File::create(&gzip).unwrap(), Default::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+        std::fs::write(&zstd, zstd::encode_all(text.as_bytes(), 0).unwrap()).unwrap();
+
+        for path in [&plain, &gzip, &zstd].iter() {
+            assert_eq!(
+                read_trace(path),
+                Ok(accesses(&[1, 2, 3, 4])),
+                "{}",
+                path.display()
+            );
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn trace_reader_should_stream_lazily() {
+        let mut reader = TraceReader::new("1 2\nbad\n".as_bytes());
+        assert_eq!(reader.next(), Some(Ok(1.into())));
+        assert_eq!(reader.next(), Some(Ok(2.into())));
+        assert_eq!(
+            reader.next(),
+            Some(Err(Error::Trace(
+                "line 2: 'bad' is not a page number".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn trace_reader_should_not_wait_for_the_end_of_a_line() {
+        // a line that never ends
+        let endless = std::io::Read::chain("write 5 6".as_bytes(), std::io::repeat(b' '));
+        let res = TraceReader::new(BufReader::new(endless))
+            .take(2)
+            .collect::<Result<Vec<Reference>, Error>>();
+        assert_eq!(
+            res,
+            Ok(vec![Reference::Write(5.into()), Reference::Write(6.into())])
+        );
+        let mut reader = TraceReader::new(BufReader::new(std::io::repeat(b'7')));
+        let err = format!("line 1: a token is longer than {} bytes", MAX_TOKEN);
+        assert_eq!(reader.next(), Some(Err(Error::Trace(err))));
+    }
+
+    #[test]
+    fn parse_trace_should_report_bad_line() {
+        let res = parse_trace("1\n2 x\n");
+        assert_eq!(
+            res,
+            Err(Error::Trace("line 2: 'x' is not a page number".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_trace_should_read_process_ids() {
+        let res = parse_trace("1:4 7 2:4\n");
+        assert_eq!(
+            res,
+            Ok(vec![
+                PageId::new(1, 4).into(),
+                PageId::new(0, 7).into(),
+                PageId::new(2, 4).into()
+            ])
+        );
+        assert_eq!(
+            parse_trace("1:x"),
+            Err(Error::Trace(
+                "line 1: '1:x' is not a page number".to_string()
+            ))
+        );
+        assert_eq!(PageId::new(2, 4).to_string(), "2:4");
+        assert_eq!(PageId::new(0, 4).to_string(), "4");
+    }
+
+    #[test]
+    fn parse_trace_should_read_pin_directives() {
+        let res = parse_trace("pin 3 1:4\n3 5\nunpin 3 # done\n");
+        assert_eq!(
+            res,
+            Ok(vec![
+                Reference::Pin(PageId::new(0, 3)),
+                Reference::Pin(PageId::new(1, 4)),
+                3.into(),
+                5.into(),
+                Reference::Unpin(PageId::new(0, 3)),
+            ])
+        );
+        assert_eq!(
+            parse_trace("1\nunpin\n"),
+            Err(Error::Trace(
+                "line 2: unpin needs at least one page".to_string()
+            ))
+        );
+        assert_eq!(Reference::Pin(PageId::new(1, 4)).to_string(), "pin 1:4");
+    }
+
+    #[test]
+    fn parse_trace_should_read_writes_and_shares() {
+        let res = parse_trace("share 0:5 1:5 2:5\nwrite 1:5\n");
+        let share = |process| Reference::Share {
+            page: PageId::new(process, 5),
+            with: PageId::new(0, 5),
+        };
+        assert_eq!(
+            res,
+            Ok(vec![
+                share(1),
+                share(2),
+                Reference::Write(PageId::new(1, 5))
+            ])
+        );
+        assert_eq!(share(2).to_string(), "share 5 2:5");
+        assert_eq!(
+            parse_trace("share 0:5"),
+            Err(Error::Trace(
+                "line 1: share needs a page and the pages sharing it".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn interleave_should_take_turns_until_every_process_is_done() {
+        let workload = Workload::Interleave(vec![
+            Workload::Loop {
+                length: 3,
+                pages: 3,
+            },
+            Workload::Loop {
+                length: 1,
+                pages: 5,
+            },
+        ]);
+        let res = workload.generate(0);
+        let expected = [(0, 0), (1, 0), (0, 1), (0, 2)]
+            .iter()
+            .map(|(pid, page)| PageId::new(*pid, *page))
+            .collect::<Vec<PageId>>();
+        assert_eq!(res, expected);
+        assert_eq!(
+            workload.to_string(),
+            "interleave(loop(length=3,pages=3),loop(length=1,pages=5))"
+        );
+    }
+}
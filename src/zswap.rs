@@ -0,0 +1,367 @@
+use crate::policy::{self, Policy};
+use crate::{MemoryPage, PageId};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// A compressed pool in RAM pages go to before swap, as zswap and zram
+/// keep one
+///
+/// Pages leaving memory are compressed into the pool, and a fault on one of
+/// them decompresses it instead of reading the device. The pool keeps its
+/// copy until the page is written, so evicting a page it still holds costs
+/// nothing. When the pool is full its policy picks pages to write out to the
+/// device
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zswap {
+    /// Bytes of RAM the pool takes
+    pub size: u64,
+    /// Every page compresses by a ratio drawn uniformly from min_ratio to
+    /// max_ratio, the same one each time
+    pub min_ratio: f64,
+    pub max_ratio: f64,
+    /// Time a fault takes to decompress a page, in nanoseconds
+    pub decompress: f64,
+    /// Spec of the policy picking the pages written out of a full pool
+    pub policy: String,
+    pub page_size: u64,
+}
+
+impl Default for Zswap {
+    /// A pool of 64 uncompressed pages, each compressing to a third
+    fn default() -> Zswap {
+        Zswap {
+            size: 64 * 4096,
+            min_ratio: 3.0,
+            max_ratio: 3.0,
+            decompress: 2000.0,
+            policy: "lru".to_string(),
+            page_size: 4096,
+        }
+    }
+}
+
+impl Zswap {
+    pub fn validate(self) -> Result<Zswap, String> {
+        if self.size == 0 || self.page_size == 0 {
+            return Err("zswap size and page_size must be at least 1".to_string());
+        }
+        if self.min_ratio < 1.0 || self.max_ratio < self.min_ratio {
+            return Err(format!(
+                "zswap needs 1 <= ratio min <= max, got {}..{}",
+                self.min_ratio, self.max_ratio
+            ));
+        }
+        if self.decompress < 0.0 {
+            return Err("zswap decompress must not be negative".to_string());
+        }
+        policy::from_spec(&self.policy, 0)?;
+        Ok(self)
+    }
+
+    /// Bytes page takes once compressed
+    pub fn compressed(&self, page: PageId, seed: u64) -> u64 {
+        let ratio = if self.min_ratio == self.max_ratio {
+            self.min_ratio
+        } else {
+            let key = ((page.process as u64) << 32 | page.number as u64) ^ seed;
+            StdRng::seed_from_u64(key).gen_range(self.min_ratio, self.max_ratio)
+        };
+        ((self.page_size as f64 / ratio).ceil() as u64).max(1)
+    }
+}
+
+impl fmt::Display for Zswap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ratio = if self.min_ratio == self.max_ratio {
+            self.min_ratio.to_string()
+        } else {
+            format!("{}..{}", self.min_ratio, self.max_ratio)
+        };
+        write!(
+            f,
+            "zswap(size={},ratio={},decompress={},policy={},page_size={})",
+            self.size, ratio, self.decompress, self.policy, self.page_size
+        )
+    }
+}
+
+/// Comma separated size=N, ratio=R or ratio=MIN..MAX, decompress=R,
+/// policy=NAME and page_size=N, missing ones keep their defaults
+impl FromStr for Zswap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Zswap, String> {
+        let mut zswap = Zswap::default();
+        for part in s.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("zswap option '{}' needs a value", part))?;
+            let expected = || format!("zswap {} expects a number, got '{}'", key, value);
+            match key {
+                "size" => zswap.size = value.parse().map_err(|_| expected())?,
+                "ratio" => {
+                    let (min, max) = value.split_once("..").unwrap_or((value, value));
+                    zswap.min_ratio = min.parse().map_err(|_| expected())?;
+                    zswap.max_ratio = max.parse().map_err(|_| expected())?;
+                }
+                "decompress" => zswap.decompress = value.parse().map_err(|_| expected())?,
+                "policy" => zswap.policy = value.to_string(),
+                "page_size" => zswap.page_size = value.parse().map_err(|_| expected())?,
+                _ => return Err(format!("unknown zswap option '{}'", key)),
+            }
+        }
+        zswap.validate()
+    }
+}
+
+/// 'off', 'on' for the default pool, or its options as FromStr reads them
+pub fn parse_spec(spec: &str) -> Result<Option<Zswap>, String> {
+    match spec.trim() {
+        "off" => Ok(None),
+        "on" => Ok(Some(Zswap::default())),
+        options => options.parse().map(Some),
+    }
+}
+
+/// What the compressed pool did over a run
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolReport {
+    pub(crate) zswap: Zswap,
+    pub(crate) hits: u64,
+    pub(crate) stores: u64,
+    pub(crate) stored_bytes: u64,
+    pub(crate) evictions: u64,
+    pub(crate) peak: u64,
+}
+
+impl PoolReport {
+    /// Faults served by decompressing a page of the pool
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Pages compressed into the pool
+    pub fn stores(&self) -> u64 {
+        self.stores
+    }
+
+    /// Pages the policy of the full pool dropped, written out to the device
+    /// when it held their only copy
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    /// Uncompressed over compressed bytes of the pages stored
+    pub fn compression_ratio(&self) -> f64 {
+        if self.stored_bytes == 0 {
+            return 0.0;
+        }
+        (self.stores * self.zswap.page_size) as f64 / self.stored_bytes as f64
+    }
+
+    /// Most bytes the pool held at once
+    pub fn peak_bytes(&self) -> u64 {
+        self.peak
+    }
+
+    /// Time faults spent decompressing pages
+    pub fn decompress_time(&self) -> f64 {
+        self.hits as f64 * self.zswap.decompress
+    }
+}
+
+/// The compressed pool of a run and the pages in it
+pub struct Pool {
+    zswap: Zswap,
+    seed: u64,
+    policy: Box<dyn Policy>,
+    /// Compressed size and state of every page in the pool
+    pages: HashMap<PageId, (u64, MemoryPage)>,
+    used: u64,
+    hits: u64,
+    stores: u64,
+    stored_bytes: u64,
+    evictions: u64,
+    peak: u64,
+}
+
+impl Pool {
+    /// seed draws the ratio of every page and drives a random policy
+    pub fn new(zswap: &Zswap, seed: u64) -> Pool {
+        Pool {
+            zswap: zswap.clone(),
+            seed,
+            policy: policy::from_spec(&zswap.policy, seed).expect("validated zswap policy"),
+            pages: HashMap::new(),
+            used: 0,
+            hits: 0,
+            stores: 0,
+            stored_bytes: 0,
+            evictions: 0,
+            peak: 0,
+        }
+    }
+
+    pub fn contains(&self, page: PageId) -> bool {
+        self.pages.contains_key(&page)
+    }
+
+    /// Compress page leaving memory into the pool, unless it holds a copy
+    /// already. Returns the pages the policy dropped to make room, page
+    /// itself when it does not fit at all
+    pub fn store(&mut self, page: MemoryPage) -> Vec<MemoryPage> {
+        let id = page.id();
+        if self.pages.contains_key(&id) {
+            return vec![];
+        }
+        let bytes = self.zswap.compressed(id, self.seed);
+        if bytes > self.zswap.size {
+            return vec![page];
+        }
+        self.stores += 1;
+        self.stored_bytes += bytes;
+        let mut dropped = vec![];
+        while self.used + bytes > self.zswap.size {
+            let victim = match self.policy.evict() {
+                Some(victim) => victim,
+                None => break,
+            };
+            if let Some((size, state)) = self.pages.remove(&victim) {
+                self.used -= size;
+                self.evictions += 1;
+                dropped.push(state);
+            }
+        }
+        self.used += bytes;
+        self.peak = self.peak.max(self.used);
+        self.pages.insert(id, (bytes, page));
+        self.policy.insert(id);
+        dropped
+    }
+
+    /// A fault on page, the state it was stored with when the pool holds
+    /// it. A write drops the copy, which goes stale once the page changes
+    pub fn load(&mut self, page: PageId, write: bool) -> Option<MemoryPage> {
+        let state = self.pages.get(&page)?.1;
+        self.hits += 1;
+        if write {
+            self.invalidate(page);
+        } else {
+            self.policy.hit(page);
+        }
+        Some(state)
+    }
+
+    /// Time a fault on a page of the pool takes to decompress it
+    pub fn latency(&self) -> f64 {
+        self.zswap.decompress
+    }
+
+    /// Drop the copy of page, it was written
+    pub fn invalidate(&mut self, page: PageId) {
+        if let Some((bytes, _)) = self.pages.remove(&page) {
+            self.used -= bytes;
+            self.policy.remove(page);
+        }
+    }
+
+    /// Track the page in the pool under from as to instead
+    pub fn rename(&mut self, from: PageId, to: PageId) {
+        let (bytes, state) = match self.pages.remove(&from) {
+            Some(x) => x,
+            None => return,
+        };
+        self.policy.remove(from);
+        self.policy.insert(to);
+        let state = MemoryPage {
+            process: to.process,
+            number: to.number,
+            ..state
+        };
+        self.pages.insert(to, (bytes, state));
+    }
+
+    pub fn report(&self) -> PoolReport {
+        PoolReport {
+            zswap: self.zswap.clone(),
+            hits: self.hits,
+            stores: self.stores,
+            stored_bytes: self.stored_bytes,
+            evictions: self.evictions,
+            peak: self.peak,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(spec: &str) -> Pool {
+        Pool::new(&spec.parse().unwrap(), 1)
+    }
+
+    fn ids(pages: Vec<MemoryPage>) -> Vec<PageId> {
+        pages.into_iter().map(|x| x.id()).collect()
+    }
+
+    #[test]
+    fn full_pool_should_drop_pages_by_its_policy() {
+        // 4 pages compressed to 1024 bytes fill the pool
+        let mut pool = pool("size=4096,ratio=4,policy=lru");
+        for number in 0..4 {
+            assert!(pool.store(MemoryPage::new(number)).is_empty());
+        }
+        assert_eq!(pool.load(0.into(), false).map(|x| x.id()), Some(0.into()));
+        // the pool kept 0, storing it again is free
+        assert!(pool.store(MemoryPage::new(0)).is_empty());
+        assert_eq!(ids(pool.store(MemoryPage::new(4))), vec![1.into()]);
+        assert!(pool.load(1.into(), false).is_none());
+        assert!(pool.load(2.into(), true).is_some());
+        assert!(!pool.contains(2.into()));
+        let report = pool.report();
+        assert_eq!((report.hits(), report.stores()), (2, 5));
+        assert_eq!((report.evictions(), report.peak_bytes()), (1, 4096));
+        assert_eq!(report.compression_ratio(), 4.0);
+        assert_eq!(report.decompress_time(), 4000.0);
+    }
+
+    #[test]
+    fn ratios_should_stay_within_range_for_every_page() {
+        let zswap = "ratio=2..4".parse::<Zswap>().unwrap();
+        for number in 0..100 {
+            let bytes = zswap.compressed(number.into(), 7);
+            assert!((1024..=2048).contains(&bytes), "{}", bytes);
+            assert_eq!(bytes, zswap.compressed(number.into(), 7));
+        }
+        // a page larger than the pool goes straight to the device
+        let mut pool = pool("size=1000,ratio=2");
+        assert_eq!(ids(pool.store(MemoryPage::new(3))), vec![3.into()]);
+        assert_eq!(pool.report().stores(), 0);
+    }
+
+    #[test]
+    fn parse_should_fill_defaults_and_reject_bad_settings() {
+        assert_eq!("".parse::<Zswap>(), Ok(Zswap::default()));
+        let res = "ratio=1.5..3, policy=fifo".parse::<Zswap>().unwrap();
+        assert_eq!(
+            res.to_string(),
+            "zswap(size=262144,ratio=1.5..3,decompress=2000,policy=fifo,page_size=4096)"
+        );
+        assert_eq!(
+            Zswap::default().to_string(),
+            "zswap(size=262144,ratio=3,decompress=2000,policy=lru,page_size=4096)"
+        );
+        assert!("ratio=0.5".parse::<Zswap>().is_err());
+        assert!("ratio=3..2".parse::<Zswap>().is_err());
+        assert!("size=0".parse::<Zswap>().is_err());
+        assert!("policy=belady".parse::<Zswap>().is_err());
+        assert!("decompress=-1".parse::<Zswap>().is_err());
+        assert!("level=3".parse::<Zswap>().is_err());
+        assert_eq!(parse_spec("off"), Ok(None));
+        assert_eq!(parse_spec("on"), Ok(Some(Zswap::default())));
+    }
+}